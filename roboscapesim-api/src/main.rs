@@ -1,16 +1,21 @@
 use async_once_cell::OnceCell;
-use axum::{body::Body, extract::Query, http::{HeaderValue, Request}, middleware::Next, response::{IntoResponse, Response}, routing::{get, post, put}, Json, Router};
+use axum::{body::Body, extract::Query, http::{header, HeaderValue, Request}, middleware::Next, response::{IntoResponse, Response}, routing::{get, post, put}, Json, Router};
 use dashmap::DashMap;
-use log::{debug, error, info, trace};
+use log::{debug, error, info, trace, warn};
 use once_cell::sync::Lazy;
 use roboscapesim_common::api::{
-    CreateRoomRequestData, CreateRoomResponseData, EnvironmentInfo, RoomInfo, ServerStatus, ServerInfo
+    CloneRoomRequestData, CreateRoomRequestData, CreateRoomResponseData, EnvironmentInfo, RoomInfo, ServerStatus, ServerInfo
 };
+use roboscapesim_common::metrics::{MetricType, MetricsWriter, METRICS_CONTENT_TYPE};
+use roboscapesim_common::signing::{verify, SignedRequest, MAX_SIGNATURE_AGE_SECS, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
 use tower_http::cors::CorsLayer;
 use simple_logger::SimpleLogger;
-use rand::Rng;
+use placement::{get_candidates, PLACEMENT_POLICY};
 
-use std::{collections::HashMap, net::SocketAddr, time::SystemTime};
+use std::{collections::HashMap, net::SocketAddr, sync::atomic::{AtomicU64, Ordering}, time::{SystemTime, UNIX_EPOCH}};
+
+mod placement;
+mod registry;
 
 /// Known servers
 static SERVERS: Lazy<DashMap<String, ServerInfo>> = Lazy::new(|| DashMap::new());
@@ -21,6 +26,25 @@ static ENVIRONMENTS: Lazy<DashMap<String, EnvironmentInfo>> = Lazy::new(|| DashM
 /// Known rooms
 static ROOMS: Lazy<DashMap<String, RoomInfo>> = Lazy::new(|| DashMap::new());
 
+/// Room create and clone requests received
+static CREATE_REQUESTS: AtomicU64 = AtomicU64::new(0);
+static CLONE_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Room create and clone requests that could not be forwarded to a server
+static FAILED_REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// Key room servers sign announcements with, the API server only starts without one if `ALLOW_UNSIGNED_ANNOUNCEMENTS` is set
+static ANNOUNCE_KEY: Lazy<Option<String>> = Lazy::new(|| std::env::var("ANNOUNCE_KEY").ok().filter(|k| !k.trim().is_empty()));
+
+/// Whether to accept unsigned announcements from any server when `ANNOUNCE_KEY` is not set, for local development
+static ALLOW_UNSIGNED_ANNOUNCEMENTS: Lazy<bool> = Lazy::new(|| std::env::var("ALLOW_UNSIGNED_ANNOUNCEMENTS").is_ok_and(|v| v == "1" || v.eq_ignore_ascii_case("true")));
+
+/// Nonces of accepted requests and when they were signed, so a captured request cannot be replayed while its timestamp is still valid
+static SEEN_NONCES: Lazy<DashMap<String, i64>> = Lazy::new(|| DashMap::new());
+
+/// Largest announcement body accepted, in bytes
+const MAX_ANNOUNCEMENT_SIZE: usize = 2 * 1024 * 1024;
+
 /// External IP address
 static EXTERNAL_IP: OnceCell<String> = OnceCell::new();
 
@@ -58,24 +82,45 @@ async fn main() {
         Ok(response)
     }
 
+    if ANNOUNCE_KEY.is_none() {
+        if !*ALLOW_UNSIGNED_ANNOUNCEMENTS {
+            error!("ANNOUNCE_KEY is not set, set it or set ALLOW_UNSIGNED_ANNOUNCEMENTS=1 to accept announcements from any server");
+            std::process::exit(1);
+        }
+
+        warn!("ANNOUNCE_KEY is not set, announcements from any server will be accepted");
+    }
+
+    // Routes room servers use to announce themselves, signed with the announce key
+    let server_routes = Router::new()
+        .route("/server/announce", post(post_server_announce))
+        .route("/server/rooms", put(put_server_rooms))
+        .route("/server/environments", put(put_server_environments))
+        .route_layer(axum::middleware::from_fn(require_signature));
+
     let app = Router::new()
         .route("/server/status", get(get_server_status))
         .route("/rooms/list", get(get_rooms_list))
         .route("/rooms/create", post(post_create))
+        .route("/rooms/clone", post(post_clone))
         .route("/rooms/info", get(get_room_info))
-        .route("/server/announce", post(post_server_announce))
-        .route("/server/rooms", put(put_server_rooms))
-        .route("/server/environments", put(put_server_environments))
         .route("/environments/list", get(get_environments_list))
+        .route("/metrics", get(get_metrics))
+        .merge(server_routes)
         .layer(
             CorsLayer::very_permissive()
         )
         .layer(axum::middleware::from_fn(additional_cors))
         .layer(tower_http::timeout::TimeoutLayer::with_status_code(axum::http::StatusCode::REQUEST_TIMEOUT, std::time::Duration::from_secs(10)));
 
+    // Load known servers and rooms from before a restart, then check them against the servers
+    registry::restore();
+    tokio::spawn(registry::reconcile());
+    tokio::spawn(registry::save_periodically());
+
     let addr = SocketAddr::from(([0, 0, 0, 0], 5001));
     let listener = tokio::net::TcpListener::bind(addr).await.expect("Failed to bind port");
-    let server = axum::serve(listener, app.into_make_service());
+    let server = axum::serve(listener, app.into_make_service()).with_graceful_shutdown(shutdown_signal());
     debug!("listening on {}", addr);
 
     // Clean up servers not updated in 6 minutes
@@ -124,6 +169,7 @@ async fn main() {
 
                     // Remove rooms on server more efficiently
                     ROOMS.retain(|_, room| room.server != server_addr);
+                    registry::mark_changed();
                 }
             }
             
@@ -134,14 +180,79 @@ async fn main() {
     if let Err(err) = server.await {
         error!("server error: {}", err);
     }
+
+    registry::save_on_shutdown().await;
+}
+
+/// Wait for Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; },
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate => {},
+    }
+
+    info!("Shutting down");
+}
+
+/// Reject announcements without a valid signature from the announce key, if one is set
+async fn require_signature(req: Request<Body>, next: Next) -> Result<Response, axum::http::StatusCode> {
+    let Some(key) = ANNOUNCE_KEY.as_ref() else {
+        // Only reachable if unsigned announcements were allowed at startup
+        return Ok(next.run(req).await);
+    };
+
+    // The body is needed to check the signature, so it is read here and put back for the handler
+    let (parts, body) = req.into_parts();
+    let body = axum::body::to_bytes(body, MAX_ANNOUNCEMENT_SIZE).await.map_err(|_| axum::http::StatusCode::PAYLOAD_TOO_LARGE)?;
+
+    let timestamp = parts.headers.get(TIMESTAMP_HEADER).and_then(|h| h.to_str().ok()).and_then(|t| t.parse::<i64>().ok());
+    let nonce = parts.headers.get(NONCE_HEADER).and_then(|h| h.to_str().ok()).filter(|n| !n.is_empty());
+    let signature = parts.headers.get(SIGNATURE_HEADER).and_then(|h| h.to_str().ok());
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default();
+
+    match (timestamp, nonce, signature) {
+        (Some(timestamp), Some(nonce), Some(signature)) if verify(key, &SignedRequest { timestamp, nonce, method: parts.method.as_str(), path: parts.uri.path(), body: &body }, now, signature) => {
+            // Nonces old enough to fail verification no longer need to be remembered
+            SEEN_NONCES.retain(|_, signed_at| (now - *signed_at).abs() <= MAX_SIGNATURE_AGE_SECS);
+
+            if SEEN_NONCES.insert(nonce.to_owned(), timestamp).is_some() {
+                warn!("Rejected replayed request to {}", parts.uri);
+                return Err(axum::http::StatusCode::UNAUTHORIZED);
+            }
+
+            Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+        },
+        _ => {
+            warn!("Rejected unsigned or invalid request to {}", parts.uri);
+            Err(axum::http::StatusCode::UNAUTHORIZED)
+        }
+    }
 }
 
 /// Get status of rooms on server
 async fn get_server_status() -> impl IntoResponse {
     serde_json::to_string(&ServerInfo {
         address: EXTERNAL_IP.get_or_init(async { get_external_ip().await.unwrap().trim().to_owned() }).await.to_owned(),
+        ws_address: String::new(),
         max_rooms: SERVERS.iter().map(|x| x.value().max_rooms).sum(),
         last_update: SystemTime::now(),
+        draining: false,
+        tick_load: 0.0,
+        tags: vec![],
+        environments: vec![],
     }).unwrap()
 }
 
@@ -171,21 +282,22 @@ async fn get_rooms_list(Query(params): Query<HashMap<String, String>>) -> impl I
     serde_json::to_string(&rooms).unwrap()
 }
 
-/// Create a new room
-async fn post_create(Json(data): Json<CreateRoomRequestData>) -> impl IntoResponse {
+/// Create a new room, errors are sent as text explaining them
+async fn post_create(Json(data): Json<CreateRoomRequestData>) -> Response {
     info!("Request to create room for user {} with environment {:?} (edit mode: {})", data.username, data.environment, data.edit_mode);
+    CREATE_REQUESTS.fetch_add(1, Ordering::Relaxed);
 
-    // Pick server to forward request to
-    let server = get_best_server();
-
-    // Return error when no servers available
-    if server.is_none() {
-        info!("No servers available");
-        return (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(None));
-    }
+    // Pick server to forward request to, explaining why when none can take the room
+    let server = match PLACEMENT_POLICY.place(&data, &get_candidates(&data.username)) {
+        Ok(server) => server,
+        Err(e) => {
+            info!("No server for room: {}", e);
+            FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+            return (axum::http::StatusCode::SERVICE_UNAVAILABLE, e.to_string()).into_response();
+        }
+    };
 
     // Forward request to server
-    let server = server.unwrap();
     let response = REQWEST_CLIENT
         .post(format!("{}/rooms/create", server))
         .json(&data)
@@ -199,12 +311,20 @@ async fn post_create(Json(data): Json<CreateRoomRequestData>) -> impl IntoRespon
             error!("Error sending request to server: {:?}", e);
             // Remove server from list
             SERVERS.remove(&server);
-            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(None));
+            registry::mark_changed();
+            FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "The server could not be reached, try again").into_response();
         }
     };
 
     info!("Response from server: {:?}", response.status());
 
+    // Pass on errors, such as the server filling up since it last announced
+    if !response.status().is_success() {
+        FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+        return (response.status(), "The server could not create the room, try again").into_response();
+    }
+
     // Parse as JSON
     let parsed_response = response.json::<CreateRoomResponseData>().await;
     
@@ -213,41 +333,69 @@ async fn post_create(Json(data): Json<CreateRoomRequestData>) -> impl IntoRespon
         Ok(data) => data,
         Err(e) => {
             error!("Error parsing response from server: {:?}", e);
-            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(None));
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, "The server sent an invalid response").into_response();
         }
     };
 
     // If success, return created room's info
-    (axum::http::StatusCode::OK, Json(Some(parsed_response)))
+    (axum::http::StatusCode::OK, Json(Some(parsed_response))).into_response()
 }
 
-/// Get the best server to create a room on
-/// This is the server with the fewest active rooms
-/// If multiple servers have the same number of active rooms, one is picked at random
-fn get_best_server() -> Option<String> {
-    let active_rooms_per_server = get_active_rooms_per_server();
-    
-    if active_rooms_per_server.is_empty() {
-        return None;
+/// Clone an existing room, the new room is created on the same server as the original
+async fn post_clone(Json(data): Json<CloneRoomRequestData>) -> impl IntoResponse {
+    info!("Request to clone room {} for user {} (keep robot IDs: {})", data.room_id, data.username, data.keep_robot_ids);
+    CLONE_REQUESTS.fetch_add(1, Ordering::Relaxed);
+
+    // Find the API address of the server hosting the room
+    let room_server = match ROOMS.get(&data.room_id) {
+        Some(room) => room.server.clone(),
+        None => return (axum::http::StatusCode::NOT_FOUND, Json(None)),
+    };
+
+    // Clones are created on the same server, so it needs room for them like a new room would
+    let server = get_candidates(&data.username).into_iter().find(|c| c.info.ws_address == room_server);
+
+    let server = match server {
+        Some(server) if server.has_capacity() => server.address,
+        Some(_) => {
+            info!("Server {} hosting room {} is full or restarting", room_server, data.room_id);
+            FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+            return (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(None));
+        },
+        None => {
+            info!("Server {} hosting room {} not available", room_server, data.room_id);
+            FAILED_REQUESTS.fetch_add(1, Ordering::Relaxed);
+            return (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(None));
+        }
+    };
+
+    // Forward request to server
+    let response = REQWEST_CLIENT
+        .post(format!("{}/rooms/clone", server))
+        .json(&data)
+        .send()
+        .await;
+
+    let response = match response {
+        Ok(resp) => resp,
+        Err(e) => {
+            error!("Error sending request to server: {:?}", e);
+            return (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(None));
+        }
+    };
+
+    // Pass on errors such as a wrong password
+    if !response.status().is_success() {
+        info!("Server refused to clone room {}: {:?}", data.room_id, response.status());
+        return (response.status(), Json(None));
     }
-    
-    // Find minimum number of active rooms
-    let min_rooms = active_rooms_per_server.values().min().copied().unwrap_or(0);
-    
-    // Collect servers with minimum rooms
-    let best_servers: Vec<&String> = active_rooms_per_server
-        .iter()
-        .filter(|(_, &count)| count == min_rooms)
-        .map(|(server, _)| server)
-        .collect();
-    
-    // Pick one randomly
-    if !best_servers.is_empty() {
-        let mut rng = rand::rng();
-        let index = rng.random_range(0..best_servers.len());
-        Some(best_servers[index].clone())
-    } else {
-        None
+
+    match response.json::<CreateRoomResponseData>().await {
+        Ok(data) => (axum::http::StatusCode::OK, Json(Some(data))),
+        Err(e) => {
+            error!("Error parsing response from server: {:?}", e);
+            (axum::http::StatusCode::INTERNAL_SERVER_ERROR, Json(None))
+        }
     }
 }
 
@@ -279,8 +427,13 @@ async fn post_server_announce(Json(data): Json<(String, ServerStatus)>) -> impl
     let (ip, data) = data;
     let server = ServerInfo {
         address: ip.clone(),
+        ws_address: data.address.clone(),
         max_rooms: data.max_rooms,
         last_update: SystemTime::now(),
+        draining: data.draining,
+        tick_load: data.tick_load,
+        tags: data.tags.clone(),
+        environments: data.environments.clone(),
     };
 
     // Check if server has been reset
@@ -290,7 +443,12 @@ async fn post_server_announce(Json(data): Json<(String, ServerStatus)>) -> impl
         ROOMS.retain(|_, room| room.server != server.address);
     }
 
+    if data.draining {
+        info!("Server {} is shutting down", ip);
+    }
+
     SERVERS.insert(server.address.clone(), server);
+    registry::mark_changed();
     info!("Server {} announced", ip);
     
     (axum::http::StatusCode::OK, Json(()))
@@ -301,6 +459,7 @@ async fn put_server_rooms(Json(data): Json<Vec<RoomInfo>>) -> impl IntoResponse
     for room in data {
         ROOMS.insert(room.id.clone(), room);
     }
+    registry::mark_changed();
     
     (axum::http::StatusCode::OK, Json(()))
 }
@@ -311,6 +470,7 @@ async fn put_server_environments(Json(data): Json<Vec<EnvironmentInfo>>) -> impl
     for environment in data {
         ENVIRONMENTS.insert(environment.id.clone(), environment);
     }
+    registry::mark_changed();
     
     (axum::http::StatusCode::OK, Json(()))
 }
@@ -322,6 +482,30 @@ async fn get_environments_list() -> impl IntoResponse {
     )
 }
 
+/// Get metrics in the Prometheus text format
+async fn get_metrics() -> impl IntoResponse {
+    let mut metrics = MetricsWriter::new();
+
+    let hibernating = ROOMS.iter().filter(|r| r.value().is_hibernating).count();
+    metrics.gauge("roboscapesim_api_servers", "Room servers that have announced themselves", SERVERS.len() as f64);
+    metrics.gauge("roboscapesim_api_server_capacity", "Maximum rooms across all room servers", SERVERS.iter().map(|s| s.value().max_rooms).sum::<usize>() as f64);
+    metrics.gauge("roboscapesim_api_rooms_active", "Known rooms that are not hibernating", ROOMS.len().saturating_sub(hibernating) as f64);
+    metrics.gauge("roboscapesim_api_rooms_hibernating", "Known rooms that are hibernating", hibernating as f64);
+    metrics.gauge("roboscapesim_api_environments", "Known environments", ENVIRONMENTS.len() as f64);
+
+    metrics.describe("roboscapesim_api_server_rooms_active", MetricType::Gauge, "Rooms that are not hibernating, by server");
+    for (server, count) in get_active_rooms_per_server() {
+        metrics.sample("roboscapesim_api_server_rooms_active", &[("server", server.as_str())], count as f64);
+    }
+
+    metrics.describe("roboscapesim_api_room_requests_total", MetricType::Counter, "Room create and clone requests received");
+    metrics.sample("roboscapesim_api_room_requests_total", &[("kind", "create")], CREATE_REQUESTS.load(Ordering::Relaxed) as f64);
+    metrics.sample("roboscapesim_api_room_requests_total", &[("kind", "clone")], CLONE_REQUESTS.load(Ordering::Relaxed) as f64);
+    metrics.counter("roboscapesim_api_room_requests_failed_total", "Room create and clone requests that no server could handle", FAILED_REQUESTS.load(Ordering::Relaxed));
+
+    ([(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)], metrics.finish())
+}
+
 /// Get number of non-hibernating rooms per server
 fn get_active_rooms_per_server() -> HashMap<String, usize> {
     let mut active_rooms_per_server = HashMap::new();
@@ -331,11 +515,11 @@ fn get_active_rooms_per_server() -> HashMap<String, usize> {
         active_rooms_per_server.insert(server.key().clone(), 0);
     }
 
-    // Count active rooms per server
+    // Count active rooms per server, rooms refer to their server by its websocket address
     for room in ROOMS.iter() {
         if !room.value().is_hibernating {
-            let server = &room.value().server;
-            *active_rooms_per_server.entry(server.clone()).or_insert(0) += 1;
+            let server = SERVERS.iter().find(|s| s.value().ws_address == room.value().server).map(|s| s.key().clone()).unwrap_or_else(|| room.value().server.clone());
+            *active_rooms_per_server.entry(server).or_insert(0) += 1;
         }
     }
     
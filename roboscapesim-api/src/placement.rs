@@ -0,0 +1,222 @@
+use std::fmt::Display;
+
+use once_cell::sync::Lazy;
+use rand::Rng;
+use roboscapesim_common::api::{CreateRoomRequestData, ServerInfo};
+
+use crate::{ROOMS, SERVERS};
+
+/// Weight of a hibernating room compared to an active room when estimating how busy a server is
+const HIBERNATING_ROOM_WEIGHT: f64 = 0.1;
+
+/// Servers whose scores are this close to the best are picked between randomly, to spread rooms out
+const SCORE_TOLERANCE: f64 = 0.05;
+
+/// Amount taken off the score of servers already hosting the user's rooms, so they are preferred unless much busier
+const STICKY_BONUS: f64 = 0.25;
+
+/// Policy used to pick servers for new rooms
+pub(crate) static PLACEMENT_POLICY: Lazy<Box<dyn PlacementPolicy>> = Lazy::new(|| Box::new(LeastLoadedPolicy));
+
+/// A server that could host a new room
+#[derive(Debug, Clone)]
+pub(crate) struct Candidate {
+    /// API address of the server
+    pub address: String,
+    pub info: ServerInfo,
+    pub active_rooms: usize,
+    pub hibernating_rooms: usize,
+    /// Rooms on the server created by the user requesting the room
+    pub user_rooms: usize,
+}
+
+impl Candidate {
+    /// Whether the server can take another room, it must not be draining or have as many active rooms as it allows
+    pub fn has_capacity(&self) -> bool {
+        !self.info.draining && self.active_rooms < self.info.max_rooms
+    }
+}
+
+/// Why no server could be picked for a room
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum PlacementError {
+    NoServers,
+    AllDraining,
+    NoMatchingTags(Vec<String>),
+    AllFull,
+}
+
+impl Display for PlacementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlacementError::NoServers => write!(f, "No servers are available"),
+            PlacementError::AllDraining => write!(f, "All servers are restarting, try again shortly"),
+            PlacementError::NoMatchingTags(tags) => write!(f, "No server has the tags {}", tags.join(", ")),
+            PlacementError::AllFull => write!(f, "All servers are full, try again later"),
+        }
+    }
+}
+
+/// Picks which server a new room is created on
+pub(crate) trait PlacementPolicy: Send + Sync {
+    /// Pick a server from the candidates, returning its API address
+    fn place(&self, request: &CreateRoomRequestData, candidates: &[Candidate]) -> Result<String, PlacementError>;
+}
+
+/// Filters out servers that are draining, full, missing a requested tag or missing the environment,
+/// then picks the least loaded server, preferring servers already hosting the user's rooms.
+///
+/// Only active rooms count towards a server's capacity, hibernating rooms use little of it until they wake up.
+pub(crate) struct LeastLoadedPolicy;
+
+impl LeastLoadedPolicy {
+    /// How busy a server is, from its share of rooms used and its reported tick load, lower is better
+    fn score(candidate: &Candidate) -> f64 {
+        let rooms = candidate.active_rooms as f64 + candidate.hibernating_rooms as f64 * HIBERNATING_ROOM_WEIGHT;
+        let sticky_bonus = if candidate.user_rooms > 0 { STICKY_BONUS } else { 0.0 };
+        rooms / candidate.info.max_rooms.max(1) as f64 + candidate.info.tick_load - sticky_bonus
+    }
+}
+
+impl PlacementPolicy for LeastLoadedPolicy {
+    fn place(&self, request: &CreateRoomRequestData, candidates: &[Candidate]) -> Result<String, PlacementError> {
+        if candidates.is_empty() {
+            return Err(PlacementError::NoServers);
+        }
+
+        // Each filter reports its own reason if it rules out every server left
+        let candidates = candidates.iter().filter(|c| !c.info.draining).collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(PlacementError::AllDraining);
+        }
+
+        let candidates = candidates.into_iter().filter(|c| request.tags.iter().all(|t| c.info.tags.contains(t))).collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(PlacementError::NoMatchingTags(request.tags.clone()));
+        }
+
+        // Remote projects can be loaded by any server, local environments are kept to servers that have them,
+        // unless none do, since servers load the default project for environments they do not have
+        let environment = request.environment.as_ref().map(|e| e.trim().to_lowercase()).filter(|e| !e.is_empty() && !e.contains('/'));
+        let candidates = match environment {
+            Some(environment) if candidates.iter().any(|c| c.info.environments.contains(&environment)) => {
+                candidates.into_iter().filter(|c| c.info.environments.is_empty() || c.info.environments.contains(&environment)).collect::<Vec<_>>()
+            },
+            _ => candidates,
+        };
+
+        let candidates = candidates.into_iter().filter(|c| c.active_rooms < c.info.max_rooms).collect::<Vec<_>>();
+        if candidates.is_empty() {
+            return Err(PlacementError::AllFull);
+        }
+
+        let best_score = candidates.iter().map(|c| Self::score(c)).fold(f64::INFINITY, f64::min);
+        let best = candidates.into_iter().filter(|c| Self::score(c) <= best_score + SCORE_TOLERANCE).collect::<Vec<_>>();
+
+        let index = rand::rng().random_range(0..best.len());
+        Ok(best[index].address.clone())
+    }
+}
+
+/// Get all known servers as candidates for a room requested by a user
+pub(crate) fn get_candidates(username: &str) -> Vec<Candidate> {
+    SERVERS.iter().map(|server| {
+        let info = server.value().clone();
+        let rooms = ROOMS.iter().filter(|r| r.value().server == info.ws_address).map(|r| r.value().clone()).collect::<Vec<_>>();
+
+        Candidate {
+            address: server.key().clone(),
+            active_rooms: rooms.iter().filter(|r| !r.is_hibernating).count(),
+            hibernating_rooms: rooms.iter().filter(|r| r.is_hibernating).count(),
+            user_rooms: rooms.iter().filter(|r| r.creator == username).count(),
+            info,
+        }
+    }).collect()
+}
+
+#[cfg(test)]
+fn test_request(environment: &str, tags: &[&str]) -> CreateRoomRequestData {
+    CreateRoomRequestData {
+        username: "user".to_owned(),
+        password: None,
+        edit_mode: false,
+        environment: Some(environment.to_owned()),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+    }
+}
+
+#[cfg(test)]
+fn test_candidate(address: &str, max_rooms: usize, active_rooms: usize, user_rooms: usize) -> Candidate {
+    Candidate {
+        address: address.to_owned(),
+        info: ServerInfo {
+            address: address.to_owned(),
+            ws_address: format!("wss://{address}"),
+            max_rooms,
+            last_update: std::time::SystemTime::now(),
+            draining: false,
+            tick_load: 0.0,
+            tags: vec![],
+            environments: vec![],
+        },
+        active_rooms,
+        hibernating_rooms: 0,
+        user_rooms,
+    }
+}
+
+#[test]
+fn test_placement_errors() {
+    let policy = LeastLoadedPolicy;
+    let request = test_request("Default", &[]);
+    assert_eq!(policy.place(&request, &[]), Err(PlacementError::NoServers));
+
+    let mut draining = test_candidate("a", 4, 0, 0);
+    draining.info.draining = true;
+    assert_eq!(policy.place(&request, &[draining]), Err(PlacementError::AllDraining));
+
+    let request_tagged = test_request("Default", &["eu"]);
+    assert_eq!(policy.place(&request_tagged, &[test_candidate("a", 4, 0, 0)]), Err(PlacementError::NoMatchingTags(vec!["eu".to_owned()])));
+
+    assert_eq!(policy.place(&request, &[test_candidate("a", 4, 4, 0)]), Err(PlacementError::AllFull));
+}
+
+#[test]
+fn test_placement_capacity() {
+    let policy = LeastLoadedPolicy;
+    let request = test_request("Default", &[]);
+
+    // Hibernating rooms do not fill a server
+    let mut hibernating = test_candidate("a", 4, 3, 0);
+    hibernating.hibernating_rooms = 20;
+    assert_eq!(policy.place(&request, &[hibernating.clone()]), Ok("a".to_owned()));
+    assert!(hibernating.has_capacity());
+    assert!(!test_candidate("a", 4, 4, 0).has_capacity());
+
+    // Less busy servers are preferred, by rooms and by tick load
+    assert_eq!(policy.place(&request, &[test_candidate("a", 4, 3, 0), test_candidate("b", 4, 0, 0)]), Ok("b".to_owned()));
+    let mut busy = test_candidate("a", 4, 0, 0);
+    busy.info.tick_load = 0.9;
+    assert_eq!(policy.place(&request, &[busy, test_candidate("b", 4, 2, 0)]), Ok("b".to_owned()));
+
+    // Servers with the environment are preferred to those that would load the default project instead
+    let mut other = test_candidate("a", 4, 0, 0);
+    other.info.environments = vec!["other".to_owned()];
+    let mut has_environment = test_candidate("b", 4, 3, 0);
+    has_environment.info.environments = vec!["default".to_owned()];
+    assert_eq!(policy.place(&request, &[other, has_environment]), Ok("b".to_owned()));
+}
+
+#[test]
+fn test_placement_sticky() {
+    let policy = LeastLoadedPolicy;
+    let request = test_request("Default", &[]);
+
+    // A server already hosting the user's rooms is preferred when it is a little busier
+    assert_eq!(policy.place(&request, &[test_candidate("a", 10, 1, 1), test_candidate("b", 10, 0, 0)]), Ok("a".to_owned()));
+
+    // But not when it is overloaded
+    let mut overloaded = test_candidate("a", 10, 2, 1);
+    overloaded.info.tick_load = 1.5;
+    assert_eq!(policy.place(&request, &[overloaded, test_candidate("b", 10, 0, 0)]), Ok("b".to_owned()));
+}
@@ -0,0 +1,156 @@
+use std::{fs, path::PathBuf, sync::atomic::{AtomicBool, Ordering}, time::Duration};
+
+use log::{error, info};
+use once_cell::sync::Lazy;
+use roboscapesim_common::api::{EnvironmentInfo, RoomInfo, ServerInfo};
+use serde::{Deserialize, Serialize};
+
+use crate::{ENVIRONMENTS, REQWEST_CLIENT, ROOMS, SERVERS};
+
+/// How often the registry is saved, if it has changed
+const SAVE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// File the registry is saved to, the registry is only kept in memory if it is not configured
+static REGISTRY_FILE: Lazy<Option<PathBuf>> = Lazy::new(|| std::env::var("REGISTRY_FILE").ok().filter(|f| !f.trim().is_empty()).map(PathBuf::from));
+
+/// Whether the registry has changed since it was last saved
+static CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// Held while writing the registry file, so the final save on shutdown does not race a periodic save
+static SAVE_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Saved servers, rooms and environments
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegistrySnapshot {
+    servers: Vec<ServerInfo>,
+    rooms: Vec<RoomInfo>,
+    environments: Vec<EnvironmentInfo>,
+}
+
+/// Note that servers, rooms or environments changed, so the registry is saved
+pub(crate) fn mark_changed() {
+    CHANGED.store(true, Ordering::Relaxed);
+}
+
+/// Save the registry, if a registry file is configured
+async fn save() {
+    let Some(path) = REGISTRY_FILE.as_ref() else {
+        return;
+    };
+
+    let _lock = SAVE_LOCK.lock().await;
+
+    let snapshot = RegistrySnapshot {
+        servers: SERVERS.iter().map(|s| s.value().clone()).collect(),
+        rooms: ROOMS.iter().map(|r| r.value().clone()).collect(),
+        environments: ENVIRONMENTS.iter().map(|e| e.value().clone()).collect(),
+    };
+
+    let result = match serde_json::to_vec(&snapshot) {
+        Ok(data) => {
+            // Write to a temporary file first so a crash does not leave a partial file
+            let tmp = path.with_extension("tmp");
+            match tokio::fs::write(&tmp, data).await {
+                Ok(()) => tokio::fs::rename(tmp, path).await,
+                Err(e) => Err(e),
+            }
+        },
+        Err(e) => Err(e.into()),
+    };
+
+    if let Err(e) = result {
+        error!("Failed to save registry to {}: {:?}", path.display(), e);
+    }
+}
+
+/// Save the registry regularly while it is changing
+pub(crate) async fn save_periodically() {
+    if REGISTRY_FILE.is_none() {
+        return;
+    }
+
+    let mut interval = tokio::time::interval(SAVE_INTERVAL);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+
+        if CHANGED.swap(false, Ordering::Relaxed) {
+            save().await;
+        }
+    }
+}
+
+/// Save the registry one last time before exiting, if it changed since it was last saved
+pub(crate) async fn save_on_shutdown() {
+    if CHANGED.swap(false, Ordering::Relaxed) {
+        info!("Saving registry before exiting");
+        save().await;
+    }
+}
+
+/// Load the saved registry, if a registry file is configured, so rooms can be found before their servers announce again
+pub(crate) fn restore() {
+    let Some(path) = REGISTRY_FILE.as_ref() else {
+        return;
+    };
+
+    let snapshot = match fs::read(path).map_err(|e| e.to_string()).and_then(|data| serde_json::from_slice::<RegistrySnapshot>(&data).map_err(|e| e.to_string())) {
+        Ok(snapshot) => snapshot,
+        Err(e) => {
+            info!("No registry loaded from {}: {}", path.display(), e);
+            return;
+        }
+    };
+
+    info!("Restoring {} servers, {} rooms and {} environments", snapshot.servers.len(), snapshot.rooms.len(), snapshot.environments.len());
+
+    for server in snapshot.servers {
+        SERVERS.insert(server.address.clone(), server);
+    }
+
+    for room in snapshot.rooms {
+        ROOMS.insert(room.id.clone(), room);
+    }
+
+    for environment in snapshot.environments {
+        ENVIRONMENTS.insert(environment.id.clone(), environment);
+    }
+}
+
+/// Check restored servers against what they are running now, forgetting servers that do not respond
+pub(crate) async fn reconcile() {
+    let servers = SERVERS.iter().map(|s| (s.key().clone(), s.value().ws_address.clone())).collect::<Vec<_>>();
+
+    for (server, ws_address) in servers {
+        let rooms = REQWEST_CLIENT
+            .get(format!("{}/rooms/list", server))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        let rooms = match rooms {
+            Ok(response) => response.json::<Vec<RoomInfo>>().await,
+            Err(e) => Err(e),
+        };
+
+        // Replace the server's rooms with those it is running now
+        match rooms {
+            Ok(rooms) => {
+                info!("Server {} has {} rooms", server, rooms.len());
+                ROOMS.retain(|id, room| room.server != ws_address || rooms.iter().any(|r| &r.id == id));
+
+                for room in rooms {
+                    ROOMS.insert(room.id.clone(), room);
+                }
+            },
+            Err(e) => {
+                info!("Removing restored server {}, it did not respond: {}", server, e);
+                SERVERS.remove(&server);
+                ROOMS.retain(|_, room| room.server != ws_address);
+            }
+        }
+    }
+
+    mark_changed();
+}
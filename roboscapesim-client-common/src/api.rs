@@ -13,8 +13,9 @@ pub const API_SERVER: &str = "http://localhost:5001/";
 #[cfg(not(debug_assertions))]
 pub const API_SERVER: &str = "https://roboscapeonlineapi2.netsblox.org/";
 
-/// Request a new room from the main API server
-pub async fn request_room(username: String, password: Option<String>, edit_mode: bool, environment: Option<String>) -> Result<CreateRoomResponseData, reqwest::Error> {
+/// Request a new room from the main API server, on a server with all of the tags given.
+/// Errors are explanations that can be shown to the user.
+pub async fn request_room(username: String, password: Option<String>, edit_mode: bool, environment: Option<String>, tags: Vec<String>) -> Result<CreateRoomResponseData, String> {
     let mut client_clone = Default::default();
     REQWEST_CLIENT.with(|client| {
         client_clone = client.clone();
@@ -25,10 +26,35 @@ pub async fn request_room(username: String, password: Option<String>, edit_mode:
         username,
         password,
         edit_mode,
-        environment
+        environment,
+        tags,
+    }).send().await.map_err(|e| format!("Could not reach the RoboScape Online server: {e}"))?;
+
+    // The API server explains why no room could be created
+    if !response.status().is_success() {
+        let status = response.status();
+        return Err(response.text().await.ok().filter(|t| !t.trim().is_empty()).unwrap_or_else(|| format!("Error creating room ({status})")));
+    }
+
+    response.json().await.map_err(|e| format!("Invalid response from the RoboScape Online server: {e}"))
+}
+
+/// Request a copy of an existing room from the main API server
+pub async fn request_clone_room(room_id: String, username: String, password: Option<String>, new_password: Option<String>, keep_robot_ids: bool) -> Result<CreateRoomResponseData, reqwest::Error> {
+    let mut client_clone = Default::default();
+    REQWEST_CLIENT.with(|client| {
+        client_clone = client.clone();
+    });
+
+    let response = client_clone.post(format!("{}rooms/clone", API_SERVER)).json(&CloneRoomRequestData {
+        room_id,
+        username,
+        password,
+        new_password,
+        keep_robot_ids
     }).send().await?;
 
-    response.json().await
+    response.error_for_status()?.json().await
 }
 
 /// Query the main API server for room info for a given room ID
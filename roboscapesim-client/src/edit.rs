@@ -0,0 +1,131 @@
+use std::cell::RefCell;
+
+use js_sys::{eval, Object};
+use nalgebra::{Point3, Quaternion, Vector3};
+use roboscapesim_client_common::{console_log, util::*};
+use roboscapesim_common::{ClientMessage, Orientation, Transform};
+use wasm_bindgen::{prelude::Closure, JsValue};
+
+use crate::{send_message, GAME};
+
+thread_local! {
+    /// Babylon GizmoManager used to move entities in edit mode rooms, if enabled
+    static GIZMO_MANAGER: RefCell<Option<JsValue>> = RefCell::new(None);
+}
+
+/// Turn the edit mode gizmo on or off, returning whether it is now enabled
+pub(crate) fn toggle_gizmo() -> bool {
+    GIZMO_MANAGER.with(|manager| {
+        if let Some(manager) = manager.borrow_mut().take() {
+            js_call_member(&manager, "dispose", &[]).unwrap_or_default();
+            return false;
+        }
+
+        let gizmo_manager = eval("
+            (() => {
+                const scene = BABYLON.Engine.LastCreatedEngine.scenes[0];
+                const manager = new BABYLON.GizmoManager(scene);
+                manager.positionGizmoEnabled = true;
+                manager.rotationGizmoEnabled = true;
+                manager.scaleGizmoEnabled = true;
+                manager.usePointerToAttachGizmos = true;
+                return manager;
+            })()
+        ").unwrap();
+
+        // Picking may select part of a model, so move the gizmo to the whole entity
+        let manager_clone = gizmo_manager.clone();
+        js_call_member(&js_get(&gizmo_manager, "onAttachedToMeshObservable").unwrap(), "add", &[&Closure::<dyn Fn(JsValue)>::new(move |mesh: JsValue| {
+            if mesh.is_null() || mesh.is_undefined() {
+                return;
+            }
+
+            if let Some(root) = entity_name_for_mesh(&mesh).and_then(|name| entity_mesh(&name)) {
+                if !Object::is(&root, &mesh) {
+                    js_call_member(&manager_clone, "attachToMesh", &[&root]).unwrap();
+                }
+            }
+        }).into_js_value()]).unwrap();
+
+        let gizmos = js_get(&gizmo_manager, "gizmos").unwrap();
+        for gizmo in ["positionGizmo", "rotationGizmo", "scaleGizmo"] {
+            let gizmo = js_get(&gizmos, gizmo).unwrap();
+
+            // Keep server updates from moving the entity while it is dragged
+            let manager_clone = gizmo_manager.clone();
+            js_call_member(&js_get(&gizmo, "onDragStartObservable").unwrap(), "add", &[&Closure::<dyn Fn()>::new(move || {
+                let name = attached_mesh(&manager_clone).and_then(|mesh| entity_name_for_mesh(&mesh));
+                GAME.with(|game| game.borrow().editing_entity.replace(name));
+            }).into_js_value()]).unwrap();
+
+            let manager_clone = gizmo_manager.clone();
+            js_call_member(&js_get(&gizmo, "onDragEndObservable").unwrap(), "add", &[&Closure::<dyn Fn()>::new(move || {
+                let name = GAME.with(|game| game.borrow().editing_entity.borrow_mut().take());
+
+                if let (Some(name), Some(mesh)) = (name, attached_mesh(&manager_clone)) {
+                    if let Some(transform) = mesh_transform(&mesh) {
+                        console_log!("Moving {} to {:?}", &name, &transform);
+                        send_message(&ClientMessage::SetEntityTransform(name, transform));
+                    }
+                }
+            }).into_js_value()]).unwrap();
+        }
+
+        manager.replace(Some(gizmo_manager));
+        true
+    })
+}
+
+/// Remove the gizmo, if enabled
+pub(crate) fn disable_gizmo() {
+    if GIZMO_MANAGER.with(|manager| manager.borrow().is_some()) {
+        toggle_gizmo();
+    }
+}
+
+fn attached_mesh(manager: &JsValue) -> Option<JsValue> {
+    js_get(manager, "attachedMesh").ok().filter(|mesh| !mesh.is_null() && !mesh.is_undefined())
+}
+
+/// Get the mesh of an entity by name
+fn entity_mesh(name: &str) -> Option<JsValue> {
+    GAME.with(|game| game.borrow().models.borrow().get(name).map(|m| m.get_mesh_as_js_value()))
+}
+
+/// Find the entity a mesh belongs to, checking its parents
+fn entity_name_for_mesh(mesh: &JsValue) -> Option<String> {
+    GAME.with(|game| {
+        let game = game.borrow();
+        let models = game.models.borrow();
+
+        let mut current = mesh.clone();
+        while !current.is_null() && !current.is_undefined() {
+            if let Some((name, _)) = models.iter().find(|(_, m)| Object::is(&m.get_mesh_as_js_value(), &current)) {
+                return Some(name.clone());
+            }
+
+            current = js_get(&current, "parent").ok()?;
+        }
+
+        None
+    })
+}
+
+/// Read the transform of a mesh
+fn mesh_transform(mesh: &JsValue) -> Option<Transform> {
+    let position = js_get(mesh, "position").ok()?;
+    let scaling = js_get(mesh, "scaling").ok()?;
+    let mut rotation = js_get(mesh, "rotationQuaternion").ok()?;
+
+    if rotation.is_null() || rotation.is_undefined() {
+        rotation = js_call_member(&js_get(mesh, "rotation").ok()?, "toQuaternion", &[]).ok()?;
+    }
+
+    let component = |value: &JsValue, name: &str| js_get(value, name).ok().and_then(|v| v.as_f64()).unwrap_or_default() as f32;
+
+    Some(Transform {
+        position: Point3::new(component(&position, "x"), component(&position, "y"), component(&position, "z")),
+        rotation: Orientation::Quaternion(Quaternion::new(component(&rotation, "w"), component(&rotation, "x"), component(&rotation, "y"), component(&rotation, "z"))),
+        scaling: Vector3::new(component(&scaling, "x"), component(&scaling, "y"), component(&scaling, "z")),
+    })
+}
@@ -1,13 +1,13 @@
-use std::{cell::{Cell, RefCell}, collections::HashMap, rc::Rc, sync::Arc};
+use std::{cell::{Cell, RefCell}, collections::{HashMap, HashSet}, rc::Rc, sync::Arc};
 use js_helpers::js;
 use js_sys::{Reflect, Function};
 use neo_babylon::prelude::*;
 use roboscapesim_client_common::{console_log, util::{js_call_member, js_set}};
-use roboscapesim_common::{ObjectData, RoomState};
+use roboscapesim_common::{ObjectData, RoomRole, RoomState};
 use wasm_bindgen::{JsValue, JsCast};
 use web_sys::{HtmlElement, window, Node};
 
-use crate::{ui::{clear_robots_menu, update_robot_buttons_visibility, create_label, TEXT_BLOCKS}, util::get_nb_externalvar};
+use crate::{edit::disable_gizmo, replay::show_replay_controls, ui::{clear_chat, clear_robots_menu, update_robot_buttons_visibility, create_label, show_keep_alive, TEXT_BLOCKS}, util::get_nb_externalvar};
 
 /// Stores information relevant to the current state
 pub struct Game {
@@ -29,6 +29,30 @@ pub struct Game {
     pub follow_camera: Rc<FollowCamera>,
     pub first_person_camera: Rc<UniversalCamera>,
     pub robot_claims: Rc<RefCell<HashMap<String, String>>>,
+    /// Password used to join the current room
+    pub room_password: Rc<RefCell<Option<String>>>,
+    /// Owner tokens of rooms created by this user, by room ID, kept after leaving so owners can rejoin
+    pub owner_tokens: Rc<RefCell<HashMap<String, String>>>,
+    /// Roles of users in the room
+    pub user_roles: Rc<RefCell<HashMap<String, RoomRole>>>,
+    /// Robots locked by the room owner
+    pub locked_robots: Rc<RefCell<HashSet<String>>>,
+    /// Names of objects by the numeric IDs used in compact updates
+    pub object_ids: Rc<RefCell<HashMap<u32, String>>>,
+    /// Token for resuming the session if the connection drops
+    pub resume_token: Rc<RefCell<Option<String>>>,
+    /// Whether a dropped connection is being reestablished
+    pub reconnecting: Rc<Cell<bool>>,
+    /// Seconds to wait before the first reconnection attempt, set when the server is restarting
+    pub reconnect_delay: Rc<Cell<f64>>,
+    /// Entity being dragged with the edit mode gizmo, which should not follow server updates
+    pub editing_entity: Rc<RefCell<Option<String>>>,
+    /// Address of the server currently connected to
+    pub server_address: Rc<RefCell<Option<String>>>,
+    /// ID of the room's recording in progress
+    pub recording: Rc<RefCell<Option<String>>>,
+    /// Time the room will hibernate without interaction, while the server is warning about it
+    pub idle_deadline: Rc<Cell<Option<f64>>>,
 }
 
 impl Game {
@@ -100,6 +124,18 @@ impl Game {
             follow_camera,
             first_person_camera,
             robot_claims: Rc::new(RefCell::new(HashMap::new())),
+            room_password: Rc::new(RefCell::new(None)),
+            owner_tokens: Rc::new(RefCell::new(HashMap::new())),
+            user_roles: Rc::new(RefCell::new(HashMap::new())),
+            locked_robots: Rc::new(RefCell::new(HashSet::new())),
+            object_ids: Rc::new(RefCell::new(HashMap::new())),
+            resume_token: Rc::new(RefCell::new(None)),
+            reconnecting: Rc::new(Cell::new(false)),
+            reconnect_delay: Rc::new(Cell::new(0.0)),
+            editing_entity: Rc::new(RefCell::new(None)),
+            server_address: Rc::new(RefCell::new(None)),
+            recording: Rc::new(RefCell::new(None)),
+            idle_deadline: Rc::new(Cell::new(None)),
         }
     }
 
@@ -209,13 +245,28 @@ impl Game {
         self.last_state_server_time.set(0.0);
         self.room_state.borrow_mut().take();
         self.robot_claims.borrow_mut().clear();
+        self.room_password.borrow_mut().take();
+        self.user_roles.borrow_mut().clear();
+        self.locked_robots.borrow_mut().clear();
+        self.object_ids.borrow_mut().clear();
+        self.resume_token.borrow_mut().take();
+        self.reconnecting.set(false);
+        self.reconnect_delay.set(0.0);
+        self.editing_entity.borrow_mut().take();
+        self.server_address.borrow_mut().take();
+        self.recording.borrow_mut().take();
+        self.idle_deadline.set(None);
 
         // UI cleanup
         TEXT_BLOCKS.with(|text_blocks| {
             text_blocks.borrow_mut().clear();
         });
         clear_robots_menu();
+        clear_chat();
+        disable_gizmo();
+        show_replay_controls(false);
         update_robot_buttons_visibility();
+        show_keep_alive(false);
         self.reset_camera();
     }
 
@@ -1,5 +1,7 @@
 #![allow(dead_code)]
+pub mod edit;
 pub mod game;
+pub mod replay;
 pub mod ui;
 pub mod util;
 
@@ -9,7 +11,7 @@ use js_helpers::js;
 use js_sys::{Reflect, Array, Uint8Array};
 use netsblox_extension_macro::*;
 use netsblox_extension_util::*;
-use roboscapesim_common::{UpdateMessage, ClientMessage, Interpolatable};
+use roboscapesim_common::{api::CreateRoomResponseData, UpdateMessage, ClientMessage, Interpolatable, RoomRole, PROTOCOL_VERSION, MIN_SERVER_PROTOCOL_VERSION, CAPABILITY_COMPACT_UPDATES};
 use roboscapesim_client_common::{api::*, console_log, ASSETS_DIR, util::*};
 use wasm_bindgen::{prelude::{wasm_bindgen, Closure}, JsValue, JsCast};
 use web_sys::{window, WebSocket, Node, HtmlDataListElement};
@@ -74,6 +76,11 @@ async fn main() {
                 if !game_clone.borrow().models.borrow().contains_key(name) {
                     continue;
                 }
+
+                // Entity is being moved by the user
+                if game_clone.borrow().editing_entity.borrow().as_ref() == Some(name) {
+                    continue;
+                }
                 
                 // Don't update objects not loaded yet
                 if last_state.borrow().contains_key(name) {
@@ -90,6 +97,10 @@ async fn main() {
                     apply_transform(game_clone.borrow().models.borrow().get(name).unwrap().clone(), update_obj.transform);
                 }
             }
+
+            if let Some(deadline) = game_clone.borrow().idle_deadline.get() {
+                update_idle_countdown(deadline);
+            }
         });
         game.borrow().scene.borrow().add_before_render_observable(before_render);
         ui::init_ui();
@@ -212,9 +223,109 @@ fn handle_update_message(msg: Result<UpdateMessage, rmp_serde::decode::Error>, g
         },
         Ok(UpdateMessage::FatalError(msg)) => {
             console_log!("Fatal Error: {}", msg);
+            game.borrow().resume_token.borrow_mut().take();
             show_message("Error", &msg);
             hide_3d_view();
         },
+        Ok(UpdateMessage::UserRole(user, role)) => {
+            console_log!("User {} is {:?}", &user, role);
+            game.borrow().user_roles.borrow_mut().insert(user, role);
+
+            update_robot_buttons_visibility();
+        },
+        Ok(UpdateMessage::UserKicked(user, banned)) => {
+            console_log!("User {} was {}", &user, if banned { "banned" } else { "kicked" });
+            game.borrow().user_roles.borrow_mut().remove(&user);
+        },
+        Ok(UpdateMessage::RobotLocked(robot, locked)) => {
+            console_log!("Robot {} {}", &robot, if locked { "locked" } else { "unlocked" });
+            if locked {
+                game.borrow().locked_robots.borrow_mut().insert(robot);
+            } else {
+                game.borrow().locked_robots.borrow_mut().remove(&robot);
+            }
+
+            update_robot_buttons_visibility();
+        },
+        Ok(UpdateMessage::Hello(version, capabilities)) => {
+            console_log!("Server protocol version {} with capabilities {:?}", version, capabilities);
+            if version < MIN_SERVER_PROTOCOL_VERSION {
+                show_message("Error", "This server is running an older version of RoboScape Online that is not compatible with this extension");
+                hide_3d_view();
+            }
+        },
+        Ok(UpdateMessage::Chat(user, text)) => {
+            add_chat_message(&user, &text);
+        },
+        Ok(UpdateMessage::Annotation(user, position)) => {
+            show_annotation(&user, position);
+        },
+        Ok(UpdateMessage::EntityExport(entities)) => {
+            window().unwrap().prompt_with_message_and_default("Entities as addEntity arguments (type, x, y, z, rotation, options):", &entities).unwrap_or_default();
+        },
+        Ok(UpdateMessage::ResumeToken(token)) => {
+            game.borrow().resume_token.replace(Some(token));
+        },
+        Ok(UpdateMessage::Recording(id)) => {
+            match &id {
+                Some(id) => {
+                    let server = game.borrow().server_address.borrow().clone().unwrap_or_default();
+                    add_or_update_text(&format!("Recording room, watch later with code {id}@{server}"), "recording", None);
+                },
+                None => add_or_update_text("Recording stopped", "recording", Some(5.0)),
+            }
+            game.borrow().recording.replace(id);
+        },
+        Ok(UpdateMessage::ReplayStatus(position, duration, paused, speed)) => {
+            replay::update_replay_status(position, duration, paused, speed);
+        },
+        Ok(UpdateMessage::ServerShutdown(msg, reconnect_after)) => {
+            console_log!("Server shutting down: {}", msg);
+
+            // Rooms only survive the restart if the server saves them, in which case it says when to reconnect
+            if let (Some(reconnect_after), true) = (reconnect_after, game.borrow().resume_token.borrow().is_some()) {
+                // Reconnect once the server is back, closing now lets it shut down sooner
+                let delay = reconnect_after.max(0.0);
+                game.borrow().reconnect_delay.set(delay);
+                add_or_update_text(&format!("{msg}, reconnecting in {} seconds", delay.ceil()), "shutdown", Some(delay + 5.0));
+                WEBSOCKET.with(|socket| {
+                    if let Some(s) = socket.borrow().clone() {
+                        let _ = s.borrow().close();
+                    }
+                });
+            } else {
+                show_message("Server restarting", &msg);
+            }
+        },
+        Ok(UpdateMessage::IdleWarning(remaining)) => {
+            console_log!("Room idle, {} seconds left", remaining);
+            game.borrow().idle_deadline.set(Some(instant::now() + remaining * 1000.0));
+            show_keep_alive(true);
+        },
+        Ok(UpdateMessage::IdleWarningCleared) => {
+            game.borrow().idle_deadline.set(None);
+            remove_text("idle");
+            show_keep_alive(false);
+        },
+        Ok(UpdateMessage::ObjectIds(ids)) => {
+            game.borrow().object_ids.borrow_mut().extend(ids.into_iter().map(|(name, id)| (id, name)));
+        },
+        Ok(UpdateMessage::CompactUpdate(t, updates)) => {
+            // Expand into a regular update using the last known state of each object
+            let roomdata = {
+                let game = game.borrow();
+                let object_ids = game.object_ids.borrow();
+                let state = game.state.borrow();
+                updates.iter().filter_map(|update| {
+                    let name = object_ids.get(&update.id)?;
+                    let mut obj = state.get(name)?.clone();
+                    update.apply(&mut obj);
+                    Some((name.clone(), obj))
+                }).collect()
+            };
+
+            handle_update_message(Ok(UpdateMessage::Update(t, false, roomdata)), game);
+        },
         #[allow(unreachable_patterns)]
         Ok(update) => {
             console_log!("Unhandled update: {:?}", update);
@@ -401,34 +512,213 @@ pub async fn join_sim_menu() {
     });
 }
 
+#[netsblox_extension_menu_item("Clone room...")]
+#[wasm_bindgen]
+pub async fn clone_room_menu() {
+    let (room_id, password) = GAME.with(|game| {
+        let game = game.borrow();
+        let room_id = game.room_state.borrow().as_ref().map(|s| s.name.clone());
+        (room_id, game.room_password.borrow().clone())
+    });
+
+    let Some(room_id) = room_id else {
+        show_message("Error", "Join a room before cloning it");
+        return;
+    };
+
+    let window = window().unwrap();
+    let new_password = match window.prompt_with_message("Password for the new room (leave blank for none):") {
+        Ok(Some(p)) => if p.trim().is_empty() { None } else { Some(p) },
+        // Cancelled
+        _ => return,
+    };
+    let keep_robot_ids = window.confirm_with_message("Keep the same robot IDs? Robots in both rooms would then share IDs.").unwrap_or(false);
+
+    clone_room(room_id, password, new_password, keep_robot_ids).await;
+}
+
+#[netsblox_extension_menu_item("Set server tags...")]
+#[wasm_bindgen]
+pub async fn set_server_tags_menu() {
+    let current = get_server_tags().join(", ");
+    if let Ok(Some(tags)) = window().unwrap().prompt_with_message_and_default("Tags new rooms' servers must have, such as a region (comma separated, blank for any server):", &current) {
+        if let Ok(Some(storage)) = window().unwrap().local_storage() {
+            let _ = storage.set_item(SERVER_TAGS_KEY, &tags);
+        }
+    }
+}
+
+#[netsblox_extension_menu_item("Set user role...")]
+#[wasm_bindgen]
+pub async fn set_role_menu() {
+    let window = window().unwrap();
+    let Ok(Some(username)) = window.prompt_with_message("Username:") else {
+        return;
+    };
+
+    let role = match window.prompt_with_message("Role (spectator or collaborator):") {
+        Ok(Some(role)) => match role.trim().to_lowercase().as_str() {
+            "spectator" => RoomRole::Spectator,
+            "collaborator" => RoomRole::Collaborator,
+            _ => {
+                show_message("Error", "Role must be spectator or collaborator");
+                return;
+            }
+        },
+        _ => return,
+    };
+
+    send_message(&ClientMessage::SetRole(username.trim().to_owned(), role));
+}
+
+#[netsblox_extension_menu_item("Change room password...")]
+#[wasm_bindgen]
+pub async fn change_password_menu() {
+    if let Ok(Some(password)) = window().unwrap().prompt_with_message("New password (leave blank to remove):") {
+        let password = if password.trim().is_empty() { None } else { Some(password) };
+        send_message(&ClientMessage::ChangePassword(password.clone()));
+        GAME.with(|game| {
+            game.borrow().room_password.replace(password);
+        });
+    }
+}
+
+#[netsblox_extension_menu_item("Kick user...")]
+#[wasm_bindgen]
+pub async fn kick_user_menu() {
+    if let Ok(Some(username)) = window().unwrap().prompt_with_message("Username to remove from the room:") {
+        send_message(&ClientMessage::KickUser(username.trim().to_owned()));
+    }
+}
+
+#[netsblox_extension_menu_item("Ban user...")]
+#[wasm_bindgen]
+pub async fn ban_user_menu() {
+    if let Ok(Some(username)) = window().unwrap().prompt_with_message("Username to ban from the room:") {
+        send_message(&ClientMessage::BanUser(username.trim().to_owned()));
+    }
+}
+
+#[netsblox_extension_menu_item("Force unclaim selected robot")]
+#[wasm_bindgen]
+pub async fn force_unclaim_menu() {
+    match get_selected_robot() {
+        Some(robot) => send_message(&ClientMessage::ForceUnclaimRobot(robot)),
+        None => show_message("Error", "Select a robot first"),
+    }
+}
+
+#[netsblox_extension_menu_item("Toggle entity editing gizmo")]
+#[wasm_bindgen]
+pub fn toggle_gizmo_menu() {
+    if edit::toggle_gizmo() {
+        show_message("Edit Mode", "Click an entity to move, rotate or scale it. Changes are only kept in edit mode rooms.");
+    }
+}
+
+#[netsblox_extension_menu_item("Export entities")]
+#[wasm_bindgen]
+pub fn export_entities_menu() {
+    send_message(&ClientMessage::ExportEntities);
+}
+
+#[netsblox_extension_menu_item("Start/stop recording room")]
+#[wasm_bindgen]
+pub fn toggle_recording_menu() {
+    let recording = GAME.with(|game| game.borrow().recording.borrow().is_some());
+    send_message(&ClientMessage::SetRecording(!recording));
+}
+
+#[netsblox_extension_menu_item("Watch recording...")]
+#[wasm_bindgen]
+pub async fn watch_recording_menu() {
+    if let Ok(Some(code)) = window().unwrap().prompt_with_message("Recording code:") {
+        watch_recording(code).await;
+    }
+}
+
+#[netsblox_extension_menu_item("Lock/unlock selected robot")]
+#[wasm_bindgen]
+pub async fn lock_robot_menu() {
+    match get_selected_robot() {
+        Some(robot) => {
+            let locked = GAME.with(|game| game.borrow().locked_robots.borrow().contains(&robot));
+            send_message(&ClientMessage::LockRobot(robot, !locked));
+        },
+        None => show_message("Error", "Select a robot first"),
+    }
+}
+
+pub async fn clone_room(room_id: String, password: Option<String>, new_password: Option<String>, keep_robot_ids: bool) {
+    set_title("Connecting...");
+    let response = request_clone_room(room_id, get_username(), password, new_password.clone(), keep_robot_ids).await;
+
+    if let Ok(response) = response {
+        connect(&response.server).await;
+        let owner_token = remember_owner_token(&response);
+        send_message(&ClientMessage::join_room(response.room_id, get_username(), new_password.clone(), owner_token));
+        GAME.with(|game| {
+            game.borrow().in_room.replace(true);
+            game.borrow().room_password.replace(new_password);
+        });
+        show_3d_view();
+    } else if let Err(e) = response {
+        show_message("Error", "Error cloning room");
+        console_log!("Error cloning room: {:?}", e);
+    }
+}
+
 pub async fn new_room(environment: Option<String>, password: Option<String>, edit_mode: bool) {
     set_title("Connecting...");
-    let response = request_room(get_username(), password.clone(), edit_mode, environment).await;
+    let response = request_room(get_username(), password.clone(), edit_mode, environment, get_server_tags()).await;
 
     if let Ok(response) = response {
         connect(&response.server).await;
-        send_message(&ClientMessage::JoinRoom(response.room_id, get_username(), password.clone()));
+        let owner_token = remember_owner_token(&response);
+        send_message(&ClientMessage::join_room(response.room_id, get_username(), password.clone(), owner_token));
         GAME.with(|game| {
             game.borrow().in_room.replace(true);
+            game.borrow().room_password.replace(password);
         });
         show_3d_view();
     } else if let Err(e) = response {
-        show_message("Error", "Error creating room");
+        show_message("Error", &e);
         console_log!("Error creating room: {:?}", e);
         // Reopen new dialog
         new_sim_menu().await;
     }
 }
 
+/// Local storage key of the tags new rooms' servers must have
+const SERVER_TAGS_KEY: &str = "roboscapesim-server-tags";
+
+/// Get the tags new rooms' servers must have, as set with the "Set server tags..." menu item
+fn get_server_tags() -> Vec<String> {
+    window().unwrap().local_storage().ok().flatten()
+        .and_then(|storage| storage.get_item(SERVER_TAGS_KEY).ok().flatten())
+        .map(|tags| tags.split(',').map(|t| t.trim().to_owned()).filter(|t| !t.is_empty()).collect())
+        .unwrap_or_default()
+}
+
+/// Keep the owner token of a room this user created, so they can rejoin it as its owner
+fn remember_owner_token(response: &CreateRoomResponseData) -> Option<String> {
+    if let Some(token) = &response.owner_token {
+        GAME.with(|game| game.borrow().owner_tokens.borrow_mut().insert(response.room_id.clone(), token.clone()));
+    }
+    response.owner_token.clone()
+}
+
 pub async fn join_room(id: String, password: Option<String>) {
     set_title("Connecting...");
     let response = request_room_info(&id).await;
 
     if let Ok(response) = response {
         connect(&response.server).await;
-        send_message(&ClientMessage::JoinRoom(id, get_username(), password.clone()));
+        let owner_token = GAME.with(|game| game.borrow().owner_tokens.borrow().get(&id).cloned());
+        send_message(&ClientMessage::join_room(id, get_username(), password.clone(), owner_token));
         GAME.with(|game| {
             game.borrow().in_room.replace(true);
+            game.borrow().room_password.replace(password);
         });
         show_3d_view();
     } else if let Err(_) = response {
@@ -438,6 +728,23 @@ pub async fn join_room(id: String, password: Option<String>) {
     }
 }
 
+/// Watch a recording, given its code (recording id@server address)
+pub async fn watch_recording(code: String) {
+    let Some((id, server)) = code.trim().split_once('@') else {
+        show_message("Error", "Invalid recording code");
+        return;
+    };
+
+    set_title("Connecting...");
+    connect(&server.to_owned()).await;
+    send_message(&ClientMessage::WatchRecording(id.to_owned()));
+    GAME.with(|game| {
+        game.borrow().in_room.replace(true);
+    });
+    replay::show_replay_controls(true);
+    show_3d_view();
+}
+
 pub async fn connect(server: &String) {
     // Disconnect if already connected
     GAME.with(|game| {
@@ -451,7 +758,64 @@ pub async fn connect(server: &String) {
             });
         }
     });
-    
+
+    GAME.with(|game| game.borrow().server_address.replace(Some(server.clone())));
+    open_socket(server).await;
+}
+
+/// Number of times to try reconnecting after the connection drops
+const RECONNECT_ATTEMPTS: u32 = 10;
+
+/// Try to reconnect to the server and resume the session after the connection drops
+async fn reconnect(server: String) {
+    let delay = GAME.with(|game| {
+        let game = game.borrow();
+        game.reconnecting.set(true);
+        game.reconnect_delay.replace(0.0)
+    });
+    set_title("Reconnecting...");
+
+    if delay > 0.0 {
+        sleep(Duration::from_secs_f64(delay)).await;
+    }
+
+    for attempt in 0..RECONNECT_ATTEMPTS {
+        sleep(Duration::from_millis(500 * 2u64.pow(attempt.min(4)))).await;
+
+        if open_socket(&server).await {
+            let resume_msg = GAME.with(|game| {
+                let game = game.borrow();
+                game.reconnecting.set(false);
+
+                // Server sends current claims, locks and roles after resuming
+                game.robot_claims.borrow_mut().clear();
+                game.locked_robots.borrow_mut().clear();
+                game.user_roles.borrow_mut().clear();
+
+                let room_id = game.room_state.borrow().as_ref().map(|state| state.name.clone());
+                let token = game.resume_token.borrow().clone();
+                room_id.zip(token).map(|(room_id, token)| ClientMessage::ResumeSession(room_id, token, game.state_server_time.get()))
+            });
+
+            if let Some(resume_msg) = resume_msg {
+                send_message(&resume_msg);
+            }
+            return;
+        }
+
+        console_log!("Reconnection attempt {} failed", attempt + 1);
+    }
+
+    GAME.with(|game| {
+        set_title("Disconnected");
+        game.borrow().cleanup();
+    });
+}
+
+/// Open a websocket to the server, returning true if it connected
+async fn open_socket(server: &String) -> bool {
+    let server_address = server.clone();
+
     // Connect to websocket
     WEBSOCKET.with(|socket| {
         let s = WebSocket::new(server);
@@ -496,13 +860,26 @@ pub async fn connect(server: &String) {
         s.borrow().set_onclose(Some(&Closure::<dyn Fn() -> _ + 'static>::new(move ||{
             GAME.with(|game| { 
                 let gc = game.clone();
+
+                // Failed attempts are retried by reconnect
+                if gc.borrow().reconnecting.get() {
+                    return;
+                }
+
+                if gc.borrow().resume_token.borrow().is_some() {
+                    spawn_local(reconnect(server_address.clone()));
+                    return;
+                }
+
                 set_title("Disconnected");
                 gc.borrow().cleanup();
             }); 
         }).into_js_value().unchecked_ref()));
         s.borrow().set_onerror(Some(&Closure::<dyn Fn() -> _ + 'static>::new(||{
             console_log!("error");
-            show_message("Error", "Failed to connect to server");
+            if !GAME.with(|game| game.borrow().reconnecting.get()) {
+                show_message("Error", "Failed to connect to server");
+            }
         }).into_js_value().unchecked_ref()));
         s.borrow().set_onopen(Some(&Closure::<dyn Fn() -> _ + 'static>::new(||{
             console_log!("open");
@@ -520,7 +897,11 @@ pub async fn connect(server: &String) {
         });
 
         if status != WebSocket::CONNECTING {
-            break;
+            if status == WebSocket::OPEN {
+                send_message(&ClientMessage::Hello(PROTOCOL_VERSION, vec![CAPABILITY_COMPACT_UPDATES.to_owned()]));
+                return true;
+            }
+            return false;
         }
     }
 }
@@ -0,0 +1,122 @@
+use std::cell::Cell;
+
+use js_sys::eval;
+use roboscapesim_client_common::util::*;
+use roboscapesim_common::{ClientMessage, ReplayControl};
+use wasm_bindgen::{prelude::Closure, JsCast};
+use web_sys::{Event, HtmlElement, HtmlInputElement};
+
+use crate::send_message;
+
+/// Playback speeds the speed button cycles through
+const SPEEDS: [f64; 4] = [0.5, 1.0, 2.0, 4.0];
+
+thread_local! {
+    /// Whether playback was paused in the last status from the server
+    static PAUSED: Cell<bool> = Cell::new(false);
+    /// Playback speed in the last status from the server
+    static SPEED: Cell<f64> = Cell::new(1.0);
+    /// Whether the seek slider is being dragged, so status updates should not move it
+    static SEEKING: Cell<bool> = Cell::new(false);
+}
+
+/// Add the hidden replay controls above the 3D view button bar
+pub(crate) fn init_replay_panel() {
+    let document = document();
+    let buttonbar = document.get_element_by_id("roboscapebuttonbar").unwrap();
+
+    let panel: HtmlElement = document.create_element("div").unwrap().unchecked_into();
+    panel.set_id("roboscapereplay");
+    panel.style().set_property("display", "none").unwrap();
+    panel.style().set_property("align-items", "center").unwrap();
+
+    let play = create_panel_button("Pause", Closure::new(|| {
+        let control = if PAUSED.with(|p| p.get()) { ReplayControl::Resume } else { ReplayControl::Pause };
+        send_message(&ClientMessage::ReplayControl(control));
+    }));
+    play.set_id("roboscapereplayplay");
+    panel.append_child(&play).unwrap();
+
+    let slider: HtmlInputElement = document.create_element("input").unwrap().unchecked_into();
+    slider.set_id("roboscapereplayslider");
+    slider.set_type("range");
+    slider.set_min("0");
+    slider.set_step("0.1");
+    slider.style().set_property("flex", "1").unwrap();
+
+    // Keep NetsBlox from dragging the dialog instead of the slider
+    slider.set_onpointerdown(Some(&eval("(e) => { e.stopPropagation(); disableDrag(); }").unwrap().unchecked_into()));
+    slider.add_event_listener_with_callback("input", &Closure::<dyn Fn(Event)>::new(|_: Event| {
+        SEEKING.with(|s| s.set(true));
+    }).into_js_value().into()).unwrap();
+    let slider_clone = slider.clone();
+    slider.add_event_listener_with_callback("change", &Closure::<dyn Fn(Event)>::new(move |_: Event| {
+        SEEKING.with(|s| s.set(false));
+        send_message(&ClientMessage::ReplayControl(ReplayControl::Seek(slider_clone.value_as_number())));
+    }).into_js_value().into()).unwrap();
+    panel.append_child(&slider).unwrap();
+
+    let time = document.create_element("span").unwrap();
+    time.set_id("roboscapereplaytime");
+    panel.append_child(&time).unwrap();
+
+    let speed = create_panel_button("1x", Closure::new(|| {
+        // Go to the next speed, wrapping around to the slowest
+        let current = SPEED.with(|s| s.get());
+        let next = SPEEDS.iter().copied().find(|s| *s > current).unwrap_or(SPEEDS[0]);
+        send_message(&ClientMessage::ReplayControl(ReplayControl::Speed(next)));
+    }));
+    speed.set_id("roboscapereplayspeed");
+    panel.append_child(&speed).unwrap();
+
+    buttonbar.parent_node().unwrap().insert_before(&panel, Some(&buttonbar)).unwrap();
+}
+
+fn create_panel_button(text: &str, callback: Closure<dyn Fn()>) -> HtmlElement {
+    let button: HtmlElement = document().create_element("button").unwrap().unchecked_into();
+    button.set_text_content(Some(text));
+    button.add_event_listener_with_callback("click", &callback.into_js_value().into()).unwrap();
+    button.add_event_listener_with_callback("mousedown", &Closure::<dyn Fn(Event)>::new(|e: Event| { e.prevent_default(); }).into_js_value().into()).unwrap();
+    button
+}
+
+/// Show or hide the replay controls
+pub(crate) fn show_replay_controls(visible: bool) {
+    if let Some(panel) = document().get_element_by_id("roboscapereplay") {
+        panel.unchecked_into::<HtmlElement>().style().set_property("display", if visible { "flex" } else { "none" }).unwrap();
+    }
+}
+
+/// Show the playback state sent by the server
+pub(crate) fn update_replay_status(position: f64, duration: f64, paused: bool, speed: f64) {
+    PAUSED.with(|p| p.set(paused));
+    SPEED.with(|s| s.set(speed));
+
+    let document = document();
+
+    if let Some(play) = document.get_element_by_id("roboscapereplayplay") {
+        play.set_text_content(Some(if paused { "Play" } else { "Pause" }));
+    }
+
+    if let Some(speed_button) = document.get_element_by_id("roboscapereplayspeed") {
+        speed_button.set_text_content(Some(&format!("{speed}x")));
+    }
+
+    if let Some(slider) = document.get_element_by_id("roboscapereplayslider") {
+        let slider = slider.unchecked_into::<HtmlInputElement>();
+        slider.set_max(&duration.to_string());
+        if !SEEKING.with(|s| s.get()) {
+            slider.set_value_as_number(position);
+        }
+    }
+
+    if let Some(time) = document.get_element_by_id("roboscapereplaytime") {
+        time.set_text_content(Some(&format!("{} / {}", format_time(position), format_time(duration))));
+    }
+}
+
+/// Format seconds as minutes:seconds
+fn format_time(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
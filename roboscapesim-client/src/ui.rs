@@ -3,9 +3,10 @@ use std::{collections::BTreeMap, cell::{RefCell, Cell}, rc::Rc};
 use js_helpers::js;
 use neo_babylon::prelude::{Color3, Vector3};
 use roboscapesim_client_common::console_log;
-use roboscapesim_common::ClientMessage;
+use nalgebra::Point3;
+use roboscapesim_common::{ClientMessage, RoomRole, MAX_CHAT_LENGTH};
 use wasm_bindgen_futures::spawn_local;
-use web_sys::{window, Event, HtmlElement, HtmlInputElement};
+use web_sys::{window, Event, HtmlElement, HtmlInputElement, KeyboardEvent};
 use roboscapesim_client_common::util::*;
 use js_sys::eval;
 use wasm_bindgen::{prelude::Closure, JsValue, JsCast};
@@ -94,8 +95,44 @@ pub(crate) fn init_ui() {
         })));
         
         game.borrow().ui_elements.borrow_mut().insert("claim_text".into(), create_text("Claimed by: None"));
+
+        game.borrow().ui_elements.borrow_mut().insert("point".into(), create_button("Point", Closure::new(|| { 
+            console_log!("Point");
+            POINTING.with(|pointing| pointing.set(true));
+        })));
+
+        game.borrow().ui_elements.borrow_mut().insert("keepalive".into(), create_button("Keep Alive", Closure::new(|| { 
+            console_log!("Keep Alive");
+            send_message(&ClientMessage::KeepAlive);
+        })));
     });
 
+    show_keep_alive(false);
+
+    init_chat_panel();
+    crate::replay::init_replay_panel();
+
+    // Clicking the view after pressing Point shares the picked position
+    let canvas = document().get_element_by_id("roboscape-canvas").unwrap();
+    canvas.add_event_listener_with_callback("pointerup", &Closure::<dyn Fn()>::new(|| {
+        if !POINTING.with(|pointing| pointing.replace(false)) {
+            return;
+        }
+
+        let picked = eval("
+            (() => {
+                const scene = BABYLON.Engine.LastCreatedEngine.scenes[0];
+                const pick = scene.pick(scene.pointerX, scene.pointerY);
+                return pick.hit ? [pick.pickedPoint.x, pick.pickedPoint.y, pick.pickedPoint.z] : null;
+            })()
+        ").unwrap();
+
+        if let Some(point) = picked.dyn_ref::<js_sys::Array>() {
+            let coords: Vec<f32> = point.iter().map(|v| v.as_f64().unwrap_or_default() as f32).collect();
+            send_message(&ClientMessage::Annotate(Point3::new(coords[0], coords[1], coords[2])));
+        }
+    }).into_js_value().into()).unwrap();
+
     
     let robotmenu: HtmlElement = get_nb_externalvar("roboscapedialog-robotmenu").unwrap().unchecked_into();
     robotmenu.set_onchange(Some(Closure::<dyn Fn() >::new(|| {
@@ -236,6 +273,107 @@ pub(crate) fn create_text(text: &str) -> web_sys::HtmlElement {
     span.unchecked_into()
 }
 
+/// Number of chat messages shown in the chat panel
+const CHAT_PANEL_LENGTH: u32 = 100;
+
+/// Seconds an annotation stays visible
+const ANNOTATION_DURATION: f64 = 10.0;
+
+thread_local! {
+    /// Whether the next click on the 3D view should be shared as an annotation
+    static POINTING: Cell<bool> = Cell::new(false);
+}
+
+/// Add the chat panel above the 3D view button bar
+fn init_chat_panel() {
+    let document = document();
+    let buttonbar = document.get_element_by_id("roboscapebuttonbar").unwrap();
+
+    let panel: HtmlElement = document.create_element("div").unwrap().unchecked_into();
+    panel.set_id("roboscapechat");
+    panel.style().set_property("flex", "0 1").unwrap();
+    panel.style().set_property("display", "flex").unwrap();
+    panel.style().set_property("flex-flow", "column").unwrap();
+
+    let log: HtmlElement = document.create_element("div").unwrap().unchecked_into();
+    log.set_id("roboscapechatlog");
+    log.style().set_property("max-height", "6em").unwrap();
+    log.style().set_property("overflow-y", "auto").unwrap();
+    panel.append_child(&log).unwrap();
+
+    let input: HtmlInputElement = document.create_element("input").unwrap().unchecked_into();
+    input.set_class_name("inset");
+    input.set_placeholder("Chat");
+    input.set_max_length(MAX_CHAT_LENGTH as i32);
+
+    // Keep NetsBlox from handling keys and drags meant for the input
+    input.set_onpointerdown(Some(&eval("(e) => { e.stopPropagation(); disableDrag(); }").unwrap().unchecked_into()));
+    let input_clone = input.clone();
+    input.add_event_listener_with_callback("keydown", &Closure::<dyn Fn(KeyboardEvent)>::new(move |e: KeyboardEvent| {
+        e.stop_propagation();
+
+        if e.key() == "Enter" {
+            let text = input_clone.value();
+            if !text.trim().is_empty() {
+                send_message(&ClientMessage::Chat(text));
+            }
+            input_clone.set_value("");
+        }
+    }).into_js_value().into()).unwrap();
+    panel.append_child(&input).unwrap();
+
+    buttonbar.parent_node().unwrap().insert_before(&panel, Some(&buttonbar)).unwrap();
+}
+
+/// Show a chat message in the chat panel
+pub(crate) fn add_chat_message(username: &str, text: &str) {
+    let document = document();
+    let log = document.get_element_by_id("roboscapechatlog").unwrap();
+
+    let line = document.create_element("div").unwrap();
+    let name = document.create_element("b").unwrap();
+    name.set_text_content(Some(&(username.to_owned() + ": ")));
+    line.append_child(&name).unwrap();
+    let message = document.create_element("span").unwrap();
+    message.set_text_content(Some(text));
+    line.append_child(&message).unwrap();
+    log.append_child(&line).unwrap();
+
+    while log.child_element_count() > CHAT_PANEL_LENGTH {
+        log.first_element_child().unwrap().remove();
+    }
+
+    log.set_scroll_top(log.scroll_height());
+}
+
+/// Remove all messages from the chat panel
+pub(crate) fn clear_chat() {
+    if let Some(log) = document().get_element_by_id("roboscapechatlog") {
+        log.set_inner_html("");
+    }
+}
+
+/// Show a marker where a user pointed, labelled with their username
+pub(crate) fn show_annotation(username: &str, position: Point3<f32>) {
+    let label = create_label(username, None, Some("#ffdd00"), Some(true));
+    js!(label.billboardMode = window.BABYLON.TransformNode.BILLBOARDMODE_ALL).unwrap();
+
+    let label_scaling = js!(label.scaling).unwrap();
+    js_set(&label_scaling, "x", 0.04).unwrap();
+    js_set(&label_scaling, "y", 0.035).unwrap();
+    let label_position = js!(label.position).unwrap();
+    js_set(&label_position, "x", position.x as f64).unwrap();
+    js_set(&label_position, "y", position.y as f64 + 0.1).unwrap();
+    js_set(&label_position, "z", position.z as f64).unwrap();
+
+    window().unwrap().set_timeout_with_callback_and_timeout_and_arguments_0(
+        Closure::<dyn Fn()>::new(move || {
+            js_call_member(&label, "dispose", &[]).unwrap_or_default();
+        }).into_js_value().unchecked_ref(),
+        (ANNOTATION_DURATION * 1000.0) as i32
+    ).unwrap();
+}
+
 /// Set title of 3D view
 pub(crate) fn set_title(title: &str) {
     let dialog = get_nb_externalvar("roboscapedialog").unwrap();
@@ -324,6 +462,14 @@ pub(crate) fn add_or_update_text(text: &str, id: &str, timeout: Option<f64>) {
     });
 }
 
+/// Remove a TextBlock created with `add_or_update_text`
+pub(crate) fn remove_text(id: &str) {
+    let id = "textblock_".to_owned() + id;
+    TEXT_BLOCKS.with(|text_blocks| {
+        text_blocks.borrow_mut().remove(&("textblock_".to_owned() + &id));
+    });
+}
+
 /**
  * Removes all TextBlocks from the 3D view's overlay
  */
@@ -413,6 +559,13 @@ pub(crate) fn update_robot_buttons_visibility() {
                 game.borrow().ui_elements.borrow().get("encrypt").unwrap().style().remove_property("display").unwrap();
                 game.borrow().ui_elements.borrow().get("claim").unwrap().style().remove_property("display").unwrap();
 
+                // Spectators cannot claim robots, and locked robots cannot be claimed
+                let selected = get_selected_robot().unwrap_or_default();
+                let locked = game.borrow().locked_robots.borrow().contains(&selected) && game.borrow().robot_claims.borrow().get(&selected) != Some(&get_username());
+                if locked || game.borrow().user_roles.borrow().get(&get_username()) == Some(&RoomRole::Spectator) {
+                    game.borrow().ui_elements.borrow().get("claim").unwrap().style().set_property("display", "none").unwrap();
+                }
+
                 let claimant = game.borrow().robot_claims.borrow().get(&get_selected_robot().unwrap_or_default()).unwrap_or(&"None".to_owned()).clone();
 
                 if claimant == get_username() {
@@ -429,6 +582,25 @@ pub(crate) fn update_robot_buttons_visibility() {
     });
 }
 
+/// Show or hide the Keep Alive button, shown while the room is about to hibernate
+pub(crate) fn show_keep_alive(visible: bool) {
+    GAME.with(|game| {
+        if let Some(button) = game.borrow().ui_elements.borrow().get("keepalive") {
+            if visible {
+                button.style().remove_property("display").unwrap();
+            } else {
+                button.style().set_property("display", "none").unwrap();
+            }
+        }
+    });
+}
+
+/// Show the time left before the room hibernates, while the server is warning about it
+pub(crate) fn update_idle_countdown(deadline: f64) {
+    let remaining = ((deadline - instant::now()) / 1000.0).max(0.0).ceil() as u64;
+    add_or_update_text(&format!("Room will hibernate in {}:{:02} without activity, press Keep Alive to stay", remaining / 60, remaining % 60), "idle", None);
+}
+
 pub(crate) fn clear_robots_menu() {
     let robotmenu: HtmlElement = get_nb_externalvar("roboscapedialog-robotmenu").unwrap().unchecked_into();
     robotmenu.set_inner_html("<option></option>");
@@ -8,12 +8,31 @@ pub struct CreateRoomRequestData {
     pub password: Option<String>,
     pub edit_mode: bool,
     pub environment: Option<String>,
+    /// Tags the server the room is created on must have, such as its region
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct CloneRoomRequestData {
+    /// Room to copy
+    pub room_id: String,
+    pub username: String,
+    /// Password of the room being copied
+    pub password: Option<String>,
+    /// Password for the new room
+    pub new_password: Option<String>,
+    /// Give the new room's robots the same IDs as the original's
+    pub keep_robot_ids: bool,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct CreateRoomResponseData {
     pub server: String,
-    pub room_id: String
+    pub room_id: String,
+    /// Secret the creator joins with to be the room's owner
+    #[serde(default)]
+    pub owner_token: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -25,6 +44,18 @@ pub struct ServerStatus {
     #[serde(rename = "maxRooms")]
     pub max_rooms: usize,
     pub address: String,
+    /// Server is shutting down and should not be given new rooms
+    #[serde(default)]
+    pub draining: bool,
+    /// Share of the server's room update time in use, 1.0 being fully busy
+    #[serde(rename = "tickLoad", default)]
+    pub tick_load: f64,
+    /// Tags describing the server for room placement, such as its region
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// IDs of the local environments the server has, in lowercase
+    #[serde(default)]
+    pub environments: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -50,6 +81,20 @@ pub struct EnvironmentInfo {
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ServerInfo {
     pub address: String,
+    /// Websocket address clients connect to, as used in RoomInfo
+    #[serde(default)]
+    pub ws_address: String,
     pub max_rooms: usize,
     pub last_update: SystemTime,
+    /// Server is shutting down and should not be given new rooms
+    #[serde(default)]
+    pub draining: bool,
+    /// Share of the server's room update time in use, 1.0 being fully busy
+    #[serde(default)]
+    pub tick_load: f64,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// IDs of the local environments the server has, in lowercase, all environments are assumed available if empty
+    #[serde(default)]
+    pub environments: Vec<String>,
 }
\ No newline at end of file
@@ -0,0 +1,144 @@
+//! Compact encoding of object updates, sent instead of `UpdateMessage::Update` to clients that request it
+
+use std::f32::consts::FRAC_1_SQRT_2;
+
+use nalgebra::{Point3, Quaternion, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{ObjectData, Orientation, Transform};
+
+/// Quantization steps per unit of position
+pub const POSITION_SCALE: f32 = 1000.0;
+
+/// Bits used for each of the three smallest quaternion components
+const COMPONENT_BITS: u32 = 10;
+const COMPONENT_MAX: u32 = (1 << COMPONENT_BITS) - 1;
+
+/// Update for a single object, referenced by the numeric ID sent in `UpdateMessage::ObjectIds`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CompactObjectUpdate {
+    #[serde(rename="i")]
+    pub id: u32,
+    /// Position, quantized with `quantize_position`
+    #[serde(rename="p")]
+    pub position: [i32; 3],
+    /// Rotation, packed with `pack_quaternion`
+    #[serde(rename="r")]
+    pub rotation: u32,
+    /// Scale, only included if changed since the last update
+    #[serde(rename="s")]
+    pub scaling: Option<Vector3<f32>>,
+    /// Kinematic flag, only included if changed since the last update
+    #[serde(rename="k")]
+    pub is_kinematic: Option<bool>,
+}
+
+impl CompactObjectUpdate {
+    /// Encode an object, including scale and kinematic flag only if they differ from the previously sent values
+    pub fn new(id: u32, object: &ObjectData, previous: Option<(Vector3<f32>, bool)>) -> Self {
+        let (scaling, is_kinematic) = match previous {
+            Some((scaling, is_kinematic)) => (
+                (scaling != object.transform.scaling).then_some(object.transform.scaling),
+                (is_kinematic != object.is_kinematic).then_some(object.is_kinematic),
+            ),
+            None => (Some(object.transform.scaling), Some(object.is_kinematic)),
+        };
+
+        CompactObjectUpdate {
+            id,
+            position: quantize_position(object.transform.position),
+            rotation: pack_quaternion(object.transform.rotation.to_quaternion()),
+            scaling,
+            is_kinematic,
+        }
+    }
+
+    /// Apply this update to the last known state of an object
+    pub fn apply(&self, object: &mut ObjectData) {
+        object.transform = Transform {
+            position: dequantize_position(self.position),
+            rotation: Orientation::Quaternion(*unpack_quaternion(self.rotation).quaternion()),
+            scaling: self.scaling.unwrap_or(object.transform.scaling),
+        };
+
+        if let Some(is_kinematic) = self.is_kinematic {
+            object.is_kinematic = is_kinematic;
+        }
+    }
+}
+
+/// Combine a newer update into an older one, keeping scale and kinematic changes only present in the older update
+pub fn merge_compact_updates(older: &mut Vec<CompactObjectUpdate>, newer: Vec<CompactObjectUpdate>) {
+    for update in newer {
+        if let Some(existing) = older.iter_mut().find(|o| o.id == update.id) {
+            existing.position = update.position;
+            existing.rotation = update.rotation;
+            existing.scaling = update.scaling.or(existing.scaling);
+            existing.is_kinematic = update.is_kinematic.or(existing.is_kinematic);
+        } else {
+            older.push(update);
+        }
+    }
+}
+
+pub fn quantize_position(position: Point3<f32>) -> [i32; 3] {
+    [
+        (position.x * POSITION_SCALE).round() as i32,
+        (position.y * POSITION_SCALE).round() as i32,
+        (position.z * POSITION_SCALE).round() as i32,
+    ]
+}
+
+pub fn dequantize_position(position: [i32; 3]) -> Point3<f32> {
+    Point3::new(position[0] as f32 / POSITION_SCALE, position[1] as f32 / POSITION_SCALE, position[2] as f32 / POSITION_SCALE)
+}
+
+/// Pack a quaternion into 32 bits with the smallest three method: 2 bits for the index of the largest component,
+/// then 10 bits for each of the other three, which are always within ±1/√2
+pub fn pack_quaternion(rotation: UnitQuaternion<f32>) -> u32 {
+    let mut components = rotation.quaternion().coords;
+
+    let largest = components.iamax();
+
+    // q and -q are the same rotation, so make the dropped component positive
+    if components[largest] < 0.0 {
+        components = -components;
+    }
+
+    let mut packed = (largest as u32) << (COMPONENT_BITS * 3);
+    let mut shift = COMPONENT_BITS * 2;
+    for (i, c) in components.iter().enumerate() {
+        if i == largest {
+            continue;
+        }
+
+        let normalized = ((c / FRAC_1_SQRT_2 + 1.0) / 2.0).clamp(0.0, 1.0);
+        packed |= ((normalized * COMPONENT_MAX as f32).round() as u32) << shift;
+        shift = shift.saturating_sub(COMPONENT_BITS);
+    }
+
+    packed
+}
+
+/// Unpack a quaternion packed with `pack_quaternion`
+pub fn unpack_quaternion(packed: u32) -> UnitQuaternion<f32> {
+    let largest = (packed >> (COMPONENT_BITS * 3)) as usize;
+
+    let mut components = [0.0f32; 4];
+    let mut shift = COMPONENT_BITS * 2;
+    let mut sum_squares = 0.0;
+    for (i, c) in components.iter_mut().enumerate() {
+        if i == largest {
+            continue;
+        }
+
+        let value = (packed >> shift) & COMPONENT_MAX;
+        *c = (value as f32 / COMPONENT_MAX as f32 * 2.0 - 1.0) * FRAC_1_SQRT_2;
+        sum_squares += *c * *c;
+        shift = shift.saturating_sub(COMPONENT_BITS);
+    }
+    components[largest] = (1.0 - sum_squares).max(0.0).sqrt();
+
+    // Quaternion coords are stored as (i, j, k, w)
+    UnitQuaternion::from_quaternion(Quaternion::new(components[3], components[0], components[1], components[2]))
+}
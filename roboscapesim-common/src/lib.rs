@@ -3,6 +3,29 @@ use nalgebra::{Quaternion, Vector3, vector, Point3, UnitQuaternion};
 use serde::{Deserialize, Serialize};
 
 pub mod api;
+pub mod compact;
+pub mod metrics;
+pub mod signing;
+
+/// Version of the client/server protocol, increase when `UpdateMessage` or `ClientMessage` change incompatibly,
+/// including adding variants, since older clients and servers cannot decode messages with variants they do not know
+///
+/// 1. Protocol handshake
+/// 2. Session resuming, chat and annotations, edit mode gizmo, recordings, shutdown notices and idle warnings
+/// 3. Owner token when joining rooms
+pub const PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest client protocol version the server accepts
+pub const MIN_CLIENT_PROTOCOL_VERSION: u32 = 3;
+
+/// Oldest server protocol version the client accepts
+pub const MIN_SERVER_PROTOCOL_VERSION: u32 = 3;
+
+/// Maximum length of a chat message, in characters
+pub const MAX_CHAT_LENGTH: usize = 500;
+
+/// Capability for receiving `UpdateMessage::CompactUpdate` instead of incremental `UpdateMessage::Update`
+pub const CAPABILITY_COMPACT_UPDATES: &str = "compact";
 
 /// Trait for interpolating between two values
 pub trait Interpolatable<T> 
@@ -91,6 +114,16 @@ impl Default for Orientation {
     }
 }
 
+impl Orientation {
+    /// Convert to a quaternion, Euler angles are (roll, pitch, yaw) as used by the physics simulation
+    pub fn to_quaternion(self) -> UnitQuaternion<f32> {
+        match self {
+            Orientation::Euler(e) => UnitQuaternion::from_euler_angles(e.x, e.y, e.z),
+            Orientation::Quaternion(q) => UnitQuaternion::from_quaternion(q),
+        }
+    }
+}
+
 impl From<(f32, f32, f32)> for Orientation {
     fn from(value: (f32, f32, f32)) -> Self {
         Self::Euler(vector![value.0, value.1, value.2])
@@ -203,6 +236,21 @@ pub struct RoomState {
     pub users: Vec<String>,
 }
 
+/// Permission level of a user in a room, ordered from least to most permissions
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum RoomRole {
+    /// Can watch, but not claim or reset robots
+    #[serde(rename="s")]
+    Spectator,
+    /// Can claim, reset and encrypt robots
+    #[default]
+    #[serde(rename="c")]
+    Collaborator,
+    /// Creator of the room, can also reset the room, assign roles and change the password
+    #[serde(rename="o")]
+    Owner,
+}
+
 /// Struct containing possible message types sent to the client
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UpdateMessage {
@@ -245,6 +293,51 @@ pub enum UpdateMessage {
     /// Generic error that kills simulation
     #[serde(rename="fe")]
     FatalError(String),
+    /// Role of a user in the room (username, role)
+    #[serde(rename="ur")]
+    UserRole(String, RoomRole),
+    /// User was removed from the room by the owner (username, banned)
+    #[serde(rename="uk")]
+    UserKicked(String, bool),
+    /// Robot was locked or unlocked by the owner, locked robots cannot be claimed
+    #[serde(rename="rl")]
+    RobotLocked(String, bool),
+    /// Numeric IDs used to refer to objects in compact updates
+    #[serde(rename="oi")]
+    ObjectIds(HashMap<String, u32>),
+    /// Incremental update using the compact encoding, only sent to clients with `CAPABILITY_COMPACT_UPDATES` (time, updates)
+    #[serde(rename="cu")]
+    CompactUpdate(f64, Vec<compact::CompactObjectUpdate>),
+    /// Reply to `ClientMessage::Hello` (protocol version, capabilities enabled for this client)
+    #[serde(rename="hi")]
+    Hello(u32, Vec<String>),
+    /// Token for resuming the session with `ClientMessage::ResumeSession` after a disconnect
+    #[serde(rename="rt")]
+    ResumeToken(String),
+    /// Chat message from a user in the room (username, text)
+    #[serde(rename="ch")]
+    Chat(String, String),
+    /// User pointed at a position in the world (username, position)
+    #[serde(rename="an")]
+    Annotation(String, Point3<f32>),
+    /// Room's entities as a JSON list of `addEntity` arguments, in reply to `ClientMessage::ExportEntities`
+    #[serde(rename="ee")]
+    EntityExport(String),
+    /// Room recording started (recording id) or stopped (None)
+    #[serde(rename="rec")]
+    Recording(Option<String>),
+    /// Playback state of a recording being watched (position, duration, paused, speed)
+    #[serde(rename="rps")]
+    ReplayStatus(f64, f64, bool, f64),
+    /// Server is shutting down (message, seconds to wait before reconnecting, None if the room will not be kept)
+    #[serde(rename="sd")]
+    ServerShutdown(String, Option<f64>),
+    /// Room will hibernate without interaction soon (seconds left)
+    #[serde(rename="iw")]
+    IdleWarning(f64),
+    /// Room is no longer about to hibernate, after an interaction
+    #[serde(rename="iwc")]
+    IdleWarningCleared,
 }
 
 /// Struct containing possible message types sent to the server
@@ -271,4 +364,80 @@ pub enum ClientMessage {
     /// Joining Room (room id, username, password)
     #[serde(rename="j")]
     JoinRoom(String, String, Option<String>),
+    /// Joining Room as the user who created it (room id, username, password, owner token given when the room was created)
+    #[serde(rename="jo")]
+    JoinRoomAsOwner(String, String, Option<String>, String),
+    /// Set the role of a user, owner only (username, role)
+    #[serde(rename="sr")]
+    SetRole(String, RoomRole),
+    /// Change or remove the room password, owner only
+    #[serde(rename="cp")]
+    ChangePassword(Option<String>),
+    /// Remove a user from the room, owner only
+    #[serde(rename="ku")]
+    KickUser(String),
+    /// Remove a user from the room and prevent them from rejoining, owner only
+    #[serde(rename="bu")]
+    BanUser(String),
+    /// Release another user's claim on a robot, owner only
+    #[serde(rename="fu")]
+    ForceUnclaimRobot(String),
+    /// Lock or unlock a robot, owner only (robot id, locked)
+    #[serde(rename="lr")]
+    LockRobot(String, bool),
+    /// First message on a connection (protocol version, requested capabilities)
+    #[serde(rename="hi")]
+    Hello(u32, Vec<String>),
+    /// Rejoin a room after a disconnect (room id, resume token, roomtime of last update received)
+    #[serde(rename="rs")]
+    ResumeSession(String, String, f64),
+    /// Send a chat message to the room
+    #[serde(rename="ch")]
+    Chat(String),
+    /// Point at a position in the world for other users
+    #[serde(rename="an")]
+    Annotate(Point3<f32>),
+    /// Move, rotate and resize an entity, edit mode owner only (entity name, transform)
+    #[serde(rename="set")]
+    SetEntityTransform(String, Transform),
+    /// Request the room's entities as `addEntity` arguments, owner only
+    #[serde(rename="ee")]
+    ExportEntities,
+    /// Start or stop recording the room, owner only
+    #[serde(rename="rec")]
+    SetRecording(bool),
+    /// Watch a recording instead of joining a room (recording id)
+    #[serde(rename="wr")]
+    WatchRecording(String),
+    /// Control playback of the recording being watched
+    #[serde(rename="rpc")]
+    ReplayControl(ReplayControl),
+    /// Keep the room from hibernating, counting as an interaction
+    #[serde(rename="ka")]
+    KeepAlive,
+}
+
+impl ClientMessage {
+    /// Message joining a room, as its owner if an owner token is given
+    pub fn join_room(room_id: String, username: String, password: Option<String>, owner_token: Option<String>) -> Self {
+        match owner_token {
+            Some(token) => ClientMessage::JoinRoomAsOwner(room_id, username, password, token),
+            None => ClientMessage::JoinRoom(room_id, username, password),
+        }
+    }
+}
+
+/// Playback controls for a recording being watched
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ReplayControl {
+    #[serde(rename="p")]
+    Pause,
+    #[serde(rename="r")]
+    Resume,
+    /// Jump to a time in the recording, in seconds
+    #[serde(rename="s")]
+    Seek(f64),
+    /// Change playback speed, 1.0 being real time
+    #[serde(rename="sp")]
+    Speed(f64),
 }
\ No newline at end of file
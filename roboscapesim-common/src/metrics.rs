@@ -0,0 +1,140 @@
+//! Metrics in the Prometheus text exposition format, served on `/metrics` by the room servers and the API server
+
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Content type of the text exposition format
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Kind of a metric, as written in its `# TYPE` line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl MetricType {
+    fn as_str(self) -> &'static str {
+        match self {
+            MetricType::Counter => "counter",
+            MetricType::Gauge => "gauge",
+            MetricType::Histogram => "histogram",
+        }
+    }
+}
+
+/// Histogram of durations with fixed buckets, safe to update from multiple threads
+#[derive(Debug)]
+pub struct Histogram {
+    /// Upper bounds of the buckets, in seconds
+    bounds: &'static [f64],
+    /// Number of observations in each bucket (not cumulative), the last one being for observations above all bounds
+    counts: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+}
+
+/// Bucket bounds for durations from a tenth of a millisecond to a second
+pub const DEFAULT_DURATION_BUCKETS: &[f64] = &[0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Histogram {
+            bounds,
+            counts: (0..=bounds.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let bucket = self.bounds.iter().position(|b| seconds <= *b).unwrap_or(self.bounds.len());
+        self.counts[bucket].fetch_add(1, Ordering::Relaxed);
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new(DEFAULT_DURATION_BUCKETS)
+    }
+}
+
+/// Builds the text of a metrics response
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    output: String,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Write the `# HELP` and `# TYPE` lines of a metric, which must come before its samples
+    pub fn describe(&mut self, name: &str, kind: MetricType, help: &str) {
+        writeln!(self.output, "# HELP {} {}", name, help.replace('\\', "\\\\").replace('\n', "\\n")).unwrap();
+        writeln!(self.output, "# TYPE {} {}", name, kind.as_str()).unwrap();
+    }
+
+    /// Write a sample of a metric
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.output.push_str(name);
+
+        if !labels.is_empty() {
+            let labels = labels.iter().map(|(k, v)| format!("{}=\"{}\"", k, escape_label_value(v))).collect::<Vec<_>>().join(",");
+            write!(self.output, "{{{}}}", labels).unwrap();
+        }
+
+        writeln!(self.output, " {}", format_value(value)).unwrap();
+    }
+
+    /// Write a gauge with a single unlabelled sample
+    pub fn gauge(&mut self, name: &str, help: &str, value: f64) {
+        self.describe(name, MetricType::Gauge, help);
+        self.sample(name, &[], value);
+    }
+
+    /// Write a counter with a single unlabelled sample
+    pub fn counter(&mut self, name: &str, help: &str, value: u64) {
+        self.describe(name, MetricType::Counter, help);
+        self.sample(name, &[], value as f64);
+    }
+
+    /// Write the samples of a histogram, after it was described
+    pub fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &Histogram) {
+        let bucket_name = format!("{name}_bucket");
+        let mut cumulative = 0;
+
+        for (i, count) in histogram.counts.iter().enumerate() {
+            cumulative += count.load(Ordering::Relaxed);
+            let le = histogram.bounds.get(i).map(|b| format_value(*b)).unwrap_or("+Inf".to_owned());
+
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", &le));
+            self.sample(&bucket_name, &bucket_labels, cumulative as f64);
+        }
+
+        self.sample(&format!("{name}_sum"), labels, histogram.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0);
+        self.sample(&format!("{name}_count"), labels, cumulative as f64);
+    }
+
+    pub fn finish(self) -> String {
+        self.output
+    }
+}
+
+fn escape_label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_owned()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_owned()
+    } else {
+        value.to_string()
+    }
+}
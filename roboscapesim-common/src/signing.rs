@@ -0,0 +1,67 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Header holding the hex HMAC-SHA256 of a signed request
+pub const SIGNATURE_HEADER: &str = "X-RoboScapeSim-Signature";
+
+/// Header holding the Unix timestamp a request was signed at
+pub const TIMESTAMP_HEADER: &str = "X-RoboScapeSim-Timestamp";
+
+/// Header holding a value unique to each signed request, so identical requests sent in the same second can be told apart from replays
+pub const NONCE_HEADER: &str = "X-RoboScapeSim-Nonce";
+
+/// Oldest (or furthest in the future) a signed request may be, in seconds, to limit replaying captured requests
+pub const MAX_SIGNATURE_AGE_SECS: i64 = 300;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The parts of a request covered by its signature
+#[derive(Debug, Clone, Copy)]
+pub struct SignedRequest<'a> {
+    /// Unix timestamp the request was signed at
+    pub timestamp: i64,
+    /// Value not reused between requests, it is joined to the rest of the signed data with `.`, so it must not contain one
+    pub nonce: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub body: &'a [u8],
+}
+
+impl SignedRequest<'_> {
+    /// HMAC of the request, covering when it was sent, its nonce, where it was sent to and its body
+    fn mac(&self, key: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(key.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(format!("{}.{}.{}.{}.", self.timestamp, self.nonce, self.method.to_uppercase(), self.path).as_bytes());
+        mac.update(self.body);
+        mac
+    }
+}
+
+/// Sign a request, giving the signature as hex
+pub fn sign(key: &str, request: &SignedRequest) -> String {
+    request.mac(key).finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Check the signature of a request, and that it was signed recently relative to `now`
+///
+/// This does not catch a valid request being replayed within the allowed age, callers should also reject nonces they have already seen.
+pub fn verify(key: &str, request: &SignedRequest, now: i64, signature: &str) -> bool {
+    if (now - request.timestamp).abs() > MAX_SIGNATURE_AGE_SECS || request.nonce.contains('.') {
+        return false;
+    }
+
+    let Some(signature) = decode_hex(signature) else {
+        return false;
+    };
+
+    // verify_slice compares in constant time
+    request.mac(key).verify_slice(&signature).is_ok()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+}
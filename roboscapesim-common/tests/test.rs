@@ -1,4 +1,126 @@
 #[cfg(test)]
 mod tests {
-    
+    use nalgebra::{point, vector, UnitQuaternion};
+    use roboscapesim_common::{compact::*, ObjectData, Orientation, Transform, VisualInfo};
+
+    #[test]
+    fn test_quaternion_packing() {
+        let rotations = [
+            UnitQuaternion::identity(),
+            UnitQuaternion::from_euler_angles(0.3, -1.2, 2.5),
+            UnitQuaternion::from_euler_angles(-3.0, 0.1, -0.7),
+            UnitQuaternion::from_axis_angle(&nalgebra::Vector3::y_axis(), std::f32::consts::PI),
+        ];
+
+        for rotation in rotations {
+            let unpacked = unpack_quaternion(pack_quaternion(rotation));
+            assert!(rotation.angle_to(&unpacked) < 0.005, "{:?} unpacked as {:?}", rotation, unpacked);
+        }
+    }
+
+    #[test]
+    fn test_position_quantization() {
+        let position = point![1.2345, -0.0004, 25.0];
+        assert_eq!(quantize_position(position), [1235, 0, 25000]);
+        assert!((dequantize_position(quantize_position(position)) - position).norm() < 0.001);
+    }
+
+    #[test]
+    fn test_compact_update() {
+        let mut object = ObjectData {
+            name: "block".to_owned(),
+            transform: Transform { position: point![1.0, 2.0, 3.0], rotation: Orientation::Quaternion(*UnitQuaternion::from_euler_angles(0.0, 1.0, 0.0).quaternion()), scaling: vector![1.0, 1.0, 1.0] },
+            visual_info: Some(VisualInfo::default()),
+            is_kinematic: false,
+            updated: true,
+        };
+
+        // Unchanged scale and kinematic flag are left out
+        let update = CompactObjectUpdate::new(3, &object, Some((vector![1.0, 1.0, 1.0], false)));
+        assert_eq!(update.scaling, None);
+        assert_eq!(update.is_kinematic, None);
+
+        let first = CompactObjectUpdate::new(3, &object, None);
+        assert_eq!(first.scaling, Some(vector![1.0, 1.0, 1.0]));
+
+        let mut received = object.clone();
+        object.transform.position = point![-1.0, 0.5, 0.25];
+        object.transform.scaling = vector![2.0, 2.0, 2.0];
+        CompactObjectUpdate::new(3, &object, Some((vector![1.0, 1.0, 1.0], false))).apply(&mut received);
+        assert_eq!(received.transform.position, object.transform.position);
+        assert_eq!(received.transform.scaling, object.transform.scaling);
+
+        // Merging keeps the older scale change
+        let mut older = vec![CompactObjectUpdate::new(3, &object, None)];
+        merge_compact_updates(&mut older, vec![update, CompactObjectUpdate::new(4, &object, None)]);
+        assert_eq!(older.len(), 2);
+        assert_eq!(older[0].scaling, Some(vector![2.0, 2.0, 2.0]));
+        assert_eq!(older[0].position, update.position);
+    }
+
+    #[test]
+    fn test_metrics_format() {
+        use roboscapesim_common::metrics::*;
+        use std::time::Duration;
+
+        let histogram = Histogram::new(&[0.01, 0.1]);
+        histogram.observe(Duration::from_millis(5));
+        histogram.observe(Duration::from_millis(50));
+        histogram.observe(Duration::from_secs(2));
+
+        let mut metrics = MetricsWriter::new();
+        metrics.gauge("rooms", "Active rooms", 2.0);
+        metrics.describe("tick_seconds", MetricType::Histogram, "Tick duration");
+        metrics.histogram("tick_seconds", &[("room", "Room\"1")], &histogram);
+
+        assert_eq!(metrics.finish(), r#"# HELP rooms Active rooms
+# TYPE rooms gauge
+rooms 2
+# HELP tick_seconds Tick duration
+# TYPE tick_seconds histogram
+tick_seconds_bucket{room="Room\"1",le="0.01"} 1
+tick_seconds_bucket{room="Room\"1",le="0.1"} 2
+tick_seconds_bucket{room="Room\"1",le="+Inf"} 3
+tick_seconds_sum{room="Room\"1"} 2.055
+tick_seconds_count{room="Room\"1"} 3
+"#);
+    }
+
+    #[test]
+    fn test_orientation_to_quaternion() {
+        // Euler angles are (roll, pitch, yaw), the same as UnitQuaternion::euler_angles
+        let rotation = Orientation::Euler(vector![0.1, 0.2, 0.3]).to_quaternion();
+        let (roll, pitch, yaw) = rotation.euler_angles();
+        assert!((roll - 0.1).abs() < 1e-5 && (pitch - 0.2).abs() < 1e-5 && (yaw - 0.3).abs() < 1e-5);
+
+        let quaternion = *rotation.quaternion();
+        assert_eq!(Orientation::Quaternion(quaternion).to_quaternion(), rotation);
+    }
+
+    #[test]
+    fn test_signing() {
+        use roboscapesim_common::signing::*;
+
+        let request = SignedRequest { timestamp: 1000, nonce: "n1", method: "POST", path: "/server/announce", body: b"[]" };
+        let signature = sign("key", &request);
+        assert_eq!(signature.len(), 64);
+        assert!(verify("key", &request, 1010, &signature));
+        assert!(verify("key", &SignedRequest { method: "post", ..request }, 1010, &signature.to_uppercase()));
+        assert!(!verify("other", &request, 1010, &signature));
+        assert!(!verify("key", &SignedRequest { body: b"[1]", ..request }, 1010, &signature));
+        assert!(!verify("key", &SignedRequest { method: "PUT", ..request }, 1010, &signature));
+        assert!(!verify("key", &SignedRequest { path: "/server/rooms", ..request }, 1010, &signature));
+        assert!(!verify("key", &request, 1000 + MAX_SIGNATURE_AGE_SECS + 1, &signature));
+        assert!(!verify("key", &request, 1010, &signature[..62]));
+        assert!(!verify("key", &request, 1010, "not hex"));
+
+        // Identical requests signed in the same second differ by their nonces
+        let other_nonce = SignedRequest { nonce: "n2", ..request };
+        assert!(!verify("key", &other_nonce, 1010, &signature));
+        assert_ne!(signature, sign("key", &other_nonce));
+
+        // Nonces cannot move signed data between fields
+        let signature = sign("key", &SignedRequest { nonce: "", ..request });
+        assert!(!verify("key", &SignedRequest { nonce: ".POST", method: "", ..request }, 1010, &signature));
+    }
 }
@@ -3,7 +3,7 @@ use std::{rc::Rc, cell::RefCell};
 use gloo_timers::future::sleep;
 use web_time::Duration;
 use js_sys::Uint8Array;
-use roboscapesim_common::{api::CreateRoomResponseData, UpdateMessage, ClientMessage};
+use roboscapesim_common::{api::CreateRoomResponseData, UpdateMessage, ClientMessage, PROTOCOL_VERSION};
 use wasm_bindgen::prelude::*;
 
 use roboscapesim_client_common::{api::*, console_log, ASSETS_DIR, util::js_get};
@@ -40,7 +40,7 @@ thread_local! {
 /// Test ability to create a room
 pub async fn step2() -> Result<(), JsValue> {
     // Test create room
-    let response = request_room("test".to_string(), None, false, None).await.map_err(|err| JsValue::from_str(&err.to_string()))?;
+    let response = request_room("test".to_string(), None, false, None, vec![]).await.map_err(|err| JsValue::from_str(&err))?;
 
     ROOM_CREATE_RESPONSE.with(|r| {
         *r.borrow_mut() = Some(response);
@@ -135,13 +135,16 @@ pub async fn step3() -> Result<(), JsValue> {
         return Err(JsValue::from_str("Failed to connect to websocket"));
     }
 
-    // Send room join message
-    let join_msg = ClientMessage::JoinRoom(response.room_id.clone(), "test".to_owned(), None);
+    // Send handshake and room join message
+    let hello_msg = ClientMessage::Hello(PROTOCOL_VERSION, vec![]);
+    let join_msg = ClientMessage::join_room(response.room_id.clone(), "test".to_owned(), None, response.owner_token.clone());
     WEBSOCKET.with(|socket| {
         let socket = socket.borrow().clone().unwrap();
         let socket = socket.borrow();
-        let buf = rmp_serde::to_vec(&join_msg).unwrap();
-        socket.send_with_u8_array(&buf).unwrap();
+        for msg in [hello_msg, join_msg] {
+            let buf = rmp_serde::to_vec(&msg).unwrap();
+            socket.send_with_u8_array(&buf).unwrap();
+        }
     });
 
     Ok(())
@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use axum::{extract::{Query, Request}, http::{header, StatusCode}, middleware::{self, Next}, response::{IntoResponse, Response}, routing::{get, post}, Json, Router};
+use axum_macros::debug_handler;
+use log::info;
+use once_cell::sync::Lazy;
+use roboscapesim_common::{api::RoomInfo, UpdateMessage};
+use serde::{Deserialize, Serialize};
+
+use crate::config::config;
+use crate::room::{persistence::delete_room, timing::RoomTickTiming};
+use crate::ROOMS;
+
+/// Token required to use the admin API, the admin API is disabled if it is not configured
+static ADMIN_TOKEN: Lazy<Option<String>> = Lazy::new(|| config().admin_token.clone());
+
+/// Full detail of a room for admins
+#[derive(Debug, Serialize)]
+pub struct AdminRoomInfo {
+    #[serde(flatten)]
+    pub info: RoomInfo,
+    pub edit_mode: bool,
+    /// Connected clients, by username
+    pub clients: HashMap<String, usize>,
+    pub robots: Vec<AdminRobotInfo>,
+    pub entities: usize,
+    pub timing: RoomTickTiming,
+    /// ID of the recording in progress
+    pub recording: Option<String>,
+    /// Unix timestamp of the last user interaction
+    pub last_interaction: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminRobotInfo {
+    pub id: String,
+    pub claimed_by: Option<String>,
+}
+
+/// Message to show to everyone in a room
+#[derive(Debug, Deserialize)]
+pub struct AdminMessageRequest {
+    pub room: String,
+    pub text: String,
+    /// Send as an error instead of text shown over the 3D view
+    #[serde(default)]
+    pub error: bool,
+    /// Seconds to show the text for, shown until cleared if not set
+    pub timeout: Option<f64>,
+}
+
+/// Routes of the admin API, to be nested under `/admin`
+pub fn admin_routes() -> Router {
+    Router::new()
+        .route("/rooms", get(get_admin_rooms))
+        .route("/rooms/events", get(get_room_events))
+        .route("/rooms/hibernate", post(post_hibernate_room))
+        .route("/rooms/close", post(post_close_room))
+        .route("/rooms/reset", post(post_reset_room))
+        .route("/rooms/message", post(post_room_message))
+        .layer(middleware::from_fn(require_admin))
+}
+
+/// Reject requests without the admin token as a bearer token
+async fn require_admin(request: Request, next: Next) -> Response {
+    let Some(token) = ADMIN_TOKEN.as_ref() else {
+        return (StatusCode::FORBIDDEN, "Admin API is not enabled on this server").into_response();
+    };
+
+    let provided = request.headers().get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    match provided {
+        Some(provided) if tokens_match(provided, token) => next.run(request).await,
+        _ => (StatusCode::UNAUTHORIZED, "Invalid admin token").into_response(),
+    }
+}
+
+/// Compare tokens in time independent of where they differ
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[debug_handler]
+/// Get full detail of all rooms
+pub(crate) async fn get_admin_rooms() -> impl IntoResponse {
+    let rooms = ROOMS.iter().map(|r| {
+        let room = r.value();
+        AdminRoomInfo {
+            info: room.metadata.get_room_info(),
+            edit_mode: room.metadata.edit_mode,
+            clients: room.clients_manager.sockets.iter().map(|c| (c.key().clone(), c.value().len())).collect(),
+            robots: room.robots.iter().map(|r| AdminRobotInfo { id: r.key().clone(), claimed_by: r.value().claimed_by.clone() }).collect(),
+            entities: room.objects.len(),
+            timing: room.tick_timing.report(r.key()),
+            recording: room.recorder.id(),
+            last_interaction: room.last_interaction_time.load(std::sync::atomic::Ordering::Relaxed),
+        }
+    }).collect::<Vec<_>>();
+
+    Json(rooms)
+}
+
+#[debug_handler]
+/// Get the event log of a room, optionally only events at or after a Unix timestamp (`since`)
+pub(crate) async fn get_room_events(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+    let room_id = params.get("id").unwrap_or(&"INVALID".to_owned()).clone();
+    let since = params.get("since").and_then(|s| s.parse::<i64>().ok()).unwrap_or(i64::MIN);
+
+    match ROOMS.get(&room_id) {
+        Some(room) => (StatusCode::OK, Json(Some(room.events.events_since(since)))),
+        None => (StatusCode::NOT_FOUND, Json(None)),
+    }
+}
+
+#[debug_handler]
+/// Put a room into hibernation, sending everyone in it out
+pub(crate) async fn post_hibernate_room(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+    let room_id = params.get("id").unwrap_or(&"INVALID".to_owned()).clone();
+
+    match ROOMS.get(&room_id) {
+        Some(room) => {
+            info!("Admin hibernating room {}", room_id);
+            room.hibernate();
+            StatusCode::OK
+        },
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+#[debug_handler]
+/// Close a room, sending everyone in it out and deleting it
+pub(crate) async fn post_close_room(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+    let room_id = params.get("id").unwrap_or(&"INVALID".to_owned()).clone();
+
+    match ROOMS.remove(&room_id) {
+        Some((_, room)) => {
+            info!("Admin closing room {}", room_id);
+            room.close("This room was closed by an administrator");
+            delete_room(&room_id);
+            StatusCode::OK
+        },
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+#[debug_handler]
+/// Reset a room on its next update
+pub(crate) async fn post_reset_room(Query(params): Query<HashMap<String, String>>) -> impl IntoResponse {
+    let room_id = params.get("id").unwrap_or(&"INVALID".to_owned()).clone();
+
+    match ROOMS.get(&room_id) {
+        Some(room) => {
+            info!("Admin resetting room {}", room_id);
+            room.reset_requested.store(true, std::sync::atomic::Ordering::Relaxed);
+            StatusCode::OK
+        },
+        None => StatusCode::NOT_FOUND,
+    }
+}
+
+#[debug_handler]
+/// Show a message to everyone in a room
+pub(crate) async fn post_room_message(Json(request): Json<AdminMessageRequest>) -> impl IntoResponse {
+    let Some(room) = ROOMS.get(&request.room) else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let msg = if request.error {
+        UpdateMessage::NonFatalError(request.text)
+    } else {
+        UpdateMessage::DisplayText("admin".to_owned(), request.text, request.timeout)
+    };
+
+    room.clients_manager.send_to_all_clients(&msg);
+    StatusCode::OK
+}
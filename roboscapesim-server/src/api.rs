@@ -1,13 +1,16 @@
 use axum::{Json, response::IntoResponse, extract::Query};
 use log::{error, info};
 use once_cell::sync::Lazy;
-use roboscapesim_common::api::{CreateRoomRequestData, CreateRoomResponseData, ServerStatus, RoomInfo, EnvironmentInfo};
+use roboscapesim_common::metrics::METRICS_CONTENT_TYPE;
+use roboscapesim_common::api::{CloneRoomRequestData, CreateRoomRequestData, CreateRoomResponseData, ServerStatus, RoomInfo, EnvironmentInfo};
+use roboscapesim_common::signing::{sign, SignedRequest, NONCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use serde::Serialize;
 use std::{net::SocketAddr, collections::HashMap, sync::Mutex};
 use axum_macros::debug_handler;
 use axum::{routing::{post, get}, Router, http::{Method, header}};
 use tower_http::{cors::{Any, CorsLayer}, timeout::TimeoutLayer};
 
-use crate::{ROOMS, MAX_ROOMS, room::management::create_room, scenarios::{DEFAULT_SCENARIOS_FILE, LOCAL_SCENARIOS}};
+use crate::{admin::admin_routes, config::config, metrics, DRAINING, ROOMS, room::{management::{clone_room, create_room, CloneRoomError}, tick_load}, scenarios::{DEFAULT_SCENARIOS_FILE, LOCAL_SCENARIOS}, util::util::{bytes_to_hex_string, get_timestamp}};
 
 pub static EXTERNAL_IP: Mutex<Option<String>> = Mutex::new(None);
 
@@ -17,33 +20,46 @@ pub static LAUNCH_TIME: Lazy<std::time::SystemTime> = Lazy::new(|| std::time::Sy
 const ANNOUNCEMENT_INTERVAL_SECS: u64 = 60 * 5;
 const HEALTHCHECK_MINIMUM_UPTIME: u64 = 60 * 60 * 16;
 
-pub static API_PORT: Lazy<u16> = Lazy::new(|| std::env::var("LOCAL_API_PORT")
-    .unwrap_or_else(|_| "3000".to_string())
-    .parse::<u16>()
-    .expect("PORT must be a number")
-);
+pub static API_PORT: Lazy<u16> = Lazy::new(|| config().network.api_port);
 
 /// Shared reqwest client for making HTTP requests
 pub(crate) static REQWEST_CLIENT: Lazy<reqwest::Client> = Lazy::new(|| 
     reqwest::ClientBuilder::new().timeout(std::time::Duration::from_secs(2)).build().unwrap()
 );
 
+/// Send JSON to the main API server, signed with the announce key if one is configured
+pub(crate) async fn send_to_main_api<T: Serialize>(method: reqwest::Method, path: &str, data: &T) -> Result<reqwest::Response, reqwest::Error> {
+    let body = serde_json::to_vec(data).unwrap_or_default();
+    let method_name = method.as_str().to_owned();
+    let mut request = REQWEST_CLIENT.request(method, format!("{}{}", get_main_api_server(), path))
+        .header(reqwest::header::CONTENT_TYPE, "application/json");
+
+    if let Some(key) = &config().announce_key {
+        let timestamp = get_timestamp();
+        let nonce = bytes_to_hex_string(&rand::random::<[u8; 16]>());
+        let signature = sign(key, &SignedRequest { timestamp, nonce: &nonce, method: &method_name, path, body: &body });
+        request = request
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(NONCE_HEADER, nonce)
+            .header(SIGNATURE_HEADER, signature);
+    }
+
+    request.body(body).send().await
+}
+
 /// Announce server to main API server
 pub async fn announce_api() {
     // Every 5 minutes, announce to main server
-    let url = format!("{}/server/announce", get_main_api_server());
     let server = get_local_api_server();
-    let max_rooms = MAX_ROOMS;
 
     // Send initial announcement
     let data = (server.clone(), ServerStatus {
         active_rooms: 0,
         hibernating_rooms: 0,
-        max_rooms,
-        address: get_server(),
+        ..current_status()
     });
     
-    let res = REQWEST_CLIENT.post(&url).json(&data).send().await;
+    let res = send_to_main_api(reqwest::Method::POST, "/server/announce", &data).await;
     match res {
         Ok(response) => {
             if !response.status().is_success() {
@@ -54,9 +70,7 @@ pub async fn announce_api() {
     }
 
     // Send environment list
-    let res = REQWEST_CLIENT.put(format!("{}/server/environments", get_main_api_server()))
-        .json(&LOCAL_SCENARIOS.values().cloned().map(|s| s.into()).collect::<Vec<EnvironmentInfo>>())
-        .send().await;
+    let res = send_to_main_api(reqwest::Method::PUT, "/server/environments", &LOCAL_SCENARIOS.values().cloned().map(|s| s.into()).collect::<Vec<EnvironmentInfo>>()).await;
     match res {
         Ok(response) => {
             if !response.status().is_success() {
@@ -69,19 +83,27 @@ pub async fn announce_api() {
     // Loop sending announcement every 5 minutes
     loop {
         tokio::time::sleep(std::time::Duration::from_secs(ANNOUNCEMENT_INTERVAL_SECS)).await;
-        let data = (server.clone(), ServerStatus {
-            active_rooms: ROOMS.len(),
-            hibernating_rooms: ROOMS.iter().filter(|r| r.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed)).count(),
-            max_rooms,
-            address: get_server(),
-        });
-        let res = REQWEST_CLIENT.post(&url).json(&data).send().await;
+        let data = (server.clone(), current_status());
+        let res = send_to_main_api(reqwest::Method::POST, "/server/announce", &data).await;
         if let Err(err) = res {
             error!("Error announcing to main server: {}", err);
         }
     }
 }
 
+/// Tell the main API server this server is shutting down, so it stops sending new rooms here
+pub async fn announce_draining() {
+    let data = (get_local_api_server(), ServerStatus {
+        draining: true,
+        ..current_status()
+    });
+
+    let res = send_to_main_api(reqwest::Method::POST, "/server/announce", &data).await;
+    if let Err(err) = res {
+        error!("Error announcing shutdown to main server: {}", err);
+    }
+}
+
 /// Create API server with routes
 pub async fn create_api() {
     let addr = SocketAddr::from(([0, 0, 0, 0], API_PORT.clone()));
@@ -90,15 +112,19 @@ pub async fn create_api() {
     .route("/server/status", get(server_status))
     .route("/rooms/list", get(get_rooms_list))
     .route("/rooms/create", post(post_create))
+    .route("/rooms/clone", post(post_clone))
     .route("/rooms/info", get(get_room_info))
+    .route("/rooms/timing", get(get_rooms_timing))
     .route("/environments/list", get(get_environments_list))
     .route("/server/healthcheck", get(get_healthcheck))
+    .route("/metrics", get(get_metrics))
+    .nest("/admin", admin_routes())
 	.layer(CorsLayer::new()
         // allow `GET` and `POST` when accessing the resource
         .allow_methods([Method::GET, Method::POST])
         // allow requests from any origin
         .allow_origin(Any)
-	    .allow_headers([header::CONTENT_TYPE]))
+	    .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]))
     .layer(TimeoutLayer::with_status_code(axum::http::StatusCode::REQUEST_TIMEOUT, std::time::Duration::from_secs(5)));
     
     let listener = tokio::net::TcpListener::bind(addr).await.expect("Failed to bind port");
@@ -115,22 +141,23 @@ pub async fn create_api() {
     }
 }
 
-/// Get status of rooms on server
-pub(crate) async fn server_status() -> impl IntoResponse {
-    let mut hibernating_rooms: usize = 0;
-
-    for r in ROOMS.iter() {
-        if r.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed) {
-            hibernating_rooms += 1;
-        }
-    }
-
-    Json(ServerStatus {
+/// Current status of this server, as announced to the main API server
+fn current_status() -> ServerStatus {
+    ServerStatus {
         active_rooms: ROOMS.len(),
-        hibernating_rooms,
-        max_rooms: MAX_ROOMS,
+        hibernating_rooms: ROOMS.iter().filter(|r| r.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed)).count(),
+        max_rooms: config().max_rooms,
         address: get_server(),
-    })
+        draining: DRAINING.load(std::sync::atomic::Ordering::Relaxed),
+        tick_load: tick_load(),
+        tags: config().tags.clone(),
+        environments: LOCAL_SCENARIOS.keys().cloned().collect(),
+    }
+}
+
+/// Get status of rooms on server
+pub(crate) async fn server_status() -> impl IntoResponse {
+    Json(current_status())
 }
 
 #[debug_handler]
@@ -150,21 +177,21 @@ pub(crate) async fn get_room_info(Query(params): Query<HashMap<String, String>>)
         return (axum::http::StatusCode::NOT_FOUND,Json(None));    
     }
 
-    let server = get_server().to_owned();
-    let room = room.unwrap().clone();
+    (axum::http::StatusCode::OK, Json(Some(room.unwrap().metadata.get_room_info())))
+}
 
-    let visitors = room.metadata.visitors.clone().into_iter().collect();
-    
-    
-    (axum::http::StatusCode::OK, Json(Some(RoomInfo{
-        id: room.metadata.name.clone(),
-        environment: room.metadata.environment.clone(),
-        server,
-        creator: "TODO".to_owned(),
-        has_password: room.metadata.password.is_some(),
-        is_hibernating: room.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed),
-        visitors,
-    })))
+#[debug_handler]
+/// Get tick timing of all rooms, most expensive first
+pub(crate) async fn get_rooms_timing() -> impl IntoResponse {
+    let mut timings = ROOMS.iter().map(|r| r.tick_timing.report(r.key())).collect::<Vec<_>>();
+    timings.sort_by(|a, b| b.average_ms.total_cmp(&a.average_ms));
+    Json(timings)
+}
+
+#[debug_handler]
+/// Get server metrics in the Prometheus text format
+pub(crate) async fn get_metrics() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, METRICS_CONTENT_TYPE)], metrics::render())
 }
 
 /// Get list of rooms, optionally filtering to a specific user
@@ -199,17 +226,56 @@ pub(crate) async fn get_healthcheck() -> impl IntoResponse {
     (axum::http::StatusCode::OK, "Rooms active, don't restart")
 }
 
+/// Whether new rooms can be created, rooms are refused while draining or once the server has as many active rooms as it allows
+fn accepting_rooms() -> bool {
+    // Hibernating rooms do not count towards capacity
+    let active_rooms = ROOMS.iter().filter(|r| !r.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed)).count();
+    !DRAINING.load(std::sync::atomic::Ordering::Relaxed) && active_rooms < config().max_rooms
+}
+
 #[debug_handler]
 pub(crate) async fn post_create(Json(request): Json<CreateRoomRequestData>) -> impl IntoResponse {
-    let room_id = create_room(request.environment, request.password, request.edit_mode).await;
+    if !accepting_rooms() {
+        return (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(None));
+    }
+
+    let (room_id, owner_token) = create_room(request.environment, request.password, request.username, request.edit_mode).await;
 
     // Send room info to API (force announcement when room is created)
     ROOMS.get(&room_id).unwrap().value().announce(true);
 
-    Json(CreateRoomResponseData {
+    (axum::http::StatusCode::OK, Json(Some(CreateRoomResponseData {
+        server: get_server(),
+        room_id,
+        owner_token: Some(owner_token),
+    })))
+}
+
+#[debug_handler]
+pub(crate) async fn post_clone(Json(request): Json<CloneRoomRequestData>) -> impl IntoResponse {
+    if !accepting_rooms() {
+        return (axum::http::StatusCode::SERVICE_UNAVAILABLE, Json(None));
+    }
+
+    let (room_id, owner_token) = match clone_room(request).await {
+        Ok(created) => created,
+        Err(e) => {
+            info!("Failed to clone room: {:?}", e);
+            let status = match e {
+                CloneRoomError::NotFound => axum::http::StatusCode::NOT_FOUND,
+                CloneRoomError::WrongPassword | CloneRoomError::Banned => axum::http::StatusCode::FORBIDDEN,
+            };
+            return (status, Json(None));
+        }
+    };
+
+    ROOMS.get(&room_id).unwrap().value().announce(true);
+
+    (axum::http::StatusCode::OK, Json(Some(CreateRoomResponseData {
         server: get_server(),
-        room_id
-    })
+        room_id,
+        owner_token: Some(owner_token),
+    })))
 }
 
 #[debug_handler]
@@ -236,6 +302,10 @@ pub(crate) async fn get_external_ip() -> Result<String, reqwest::Error> {
 
 /// Get main API server URL
 pub(crate) fn get_main_api_server() -> String {
+    if let Some(server) = &config().network.main_api_server {
+        return server.clone();
+    }
+
     #[cfg(debug_assertions)]
     {
         "http://127.0.0.1:5001".to_owned()
@@ -0,0 +1,168 @@
+use std::{collections::BTreeMap, fs, sync::atomic::Ordering, time::{Duration, Instant}};
+
+use log::{info, warn};
+use netsblox_vm::real_time::OffsetDateTime;
+use serde::Serialize;
+use tokio::time::sleep;
+
+use crate::{room::{events::RoomEventKind, RoomData}, scenarios::ALLOW_PROJECT_FILES, services::service_struct::ANNOUNCE_SERVICES, config::config};
+
+/// Simulated duration used if none is specified, in seconds
+const DEFAULT_DURATION_SECS: f64 = 60.0;
+
+/// How long to wait for the VM to load and start the project before running anyway
+const VM_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of simulation steps to run between yields to the async runtime
+const STEPS_PER_YIELD: usize = 16;
+
+/// Options for a headless batch run
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchOptions {
+    /// Environment to load, may also be a path to a project XML file
+    pub environment: String,
+    /// Simulated time to run for, in seconds
+    pub duration: f64,
+    /// File to write the report to, stdout if None
+    pub output: Option<String>,
+}
+
+impl BatchOptions {
+    /// Parse batch options from command line arguments, returns None if batch mode was not requested
+    pub fn from_args(args: &[String]) -> Result<Option<BatchOptions>, String> {
+        let mut environment = None;
+        let mut duration = DEFAULT_DURATION_SECS;
+        let mut output = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--batch" => {
+                    environment = Some(args.next().ok_or("--batch requires an environment")?.to_owned());
+                },
+                "--duration" => {
+                    duration = args.next().ok_or("--duration requires a value")?.parse::<f64>().map_err(|e| format!("Invalid duration: {e}"))?;
+
+                    if !duration.is_finite() || duration <= 0.0 {
+                        return Err("Duration must be a positive number of seconds".to_owned());
+                    }
+                },
+                "--output" => {
+                    output = Some(args.next().ok_or("--output requires a file path")?.to_owned());
+                },
+                _ => {}
+            }
+        }
+
+        Ok(environment.map(|environment| BatchOptions { environment, duration, output }))
+    }
+}
+
+/// A trigger enter or exit event recorded during a headless run
+#[derive(Debug, Clone, Serialize)]
+pub struct TriggerEvent {
+    /// Simulated time of the event
+    pub roomtime: f64,
+    pub trigger: String,
+    pub entity: String,
+    /// True for enter, false for exit
+    pub entered: bool,
+}
+
+/// Final pose of an entity
+#[derive(Debug, Clone, Serialize)]
+pub struct EntityPose {
+    pub position: [f32; 3],
+    /// Euler angles (roll, pitch, yaw) in radians
+    pub rotation: [f32; 3],
+}
+
+/// Report written at the end of a batch run
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchReport {
+    pub environment: String,
+    /// Simulated time covered by the run, in seconds
+    pub simulated_time: f64,
+    /// Wall-clock time taken by the run, in seconds
+    pub wall_time: f64,
+    pub entities: BTreeMap<String, EntityPose>,
+    pub trigger_events: Vec<TriggerEvent>,
+    pub scores: BTreeMap<String, f64>,
+}
+
+/// Run an environment without clients as fast as possible for the requested simulated duration, then write a report.
+///
+/// Physics steps at a fixed 1/update_fps regardless of wall-clock time, and the VM runs one iteration after each step
+/// so the project keeps pace with the simulation. The VM's clock cannot follow simulated time, so blocks that measure
+/// real time (e.g. wait) are not accelerated.
+pub async fn run(options: BatchOptions) -> Result<(), String> {
+    ALLOW_PROJECT_FILES.store(true, Ordering::Relaxed);
+
+    // The run's services are only used by its own project
+    ANNOUNCE_SERVICES.store(false, Ordering::Relaxed);
+
+    info!("Starting batch run of {} for {}s", options.environment, options.duration);
+    let start = Instant::now();
+
+    let room = RoomData::new(None, Some(options.environment.clone()), None, None, false, true).await;
+
+    // Wait for project to start so its setup scripts can create the world
+    let vm_started = room.vm_manager.get().unwrap().started.clone();
+    while !vm_started.load(Ordering::Relaxed) {
+        if start.elapsed() > VM_START_TIMEOUT {
+            warn!("VM did not start within {:?}, running anyway", VM_START_TIMEOUT);
+            break;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+
+    let vm = room.vm_manager.get().unwrap().clone();
+    let delta_time = 1.0 / config().update_fps;
+    let mut steps = 0;
+    while *room.roomtime.read().unwrap() < options.duration {
+        room.update_with_delta(delta_time, OffsetDateTime::now_utc());
+
+        // Let the project respond to this step before the next
+        if vm_started.load(Ordering::Relaxed) {
+            vm.step();
+        }
+
+        // Let networking tasks handle messages
+        steps += 1;
+        if steps % STEPS_PER_YIELD == 0 {
+            tokio::task::yield_now().await;
+        }
+    }
+
+    let report = BatchReport {
+        environment: options.environment.clone(),
+        simulated_time: *room.roomtime.read().unwrap(),
+        wall_time: start.elapsed().as_secs_f64(),
+        entities: room.objects.iter().map(|o| {
+            let transform = o.value().transform;
+            let rotation: (f32, f32, f32) = transform.rotation.into();
+            (o.key().clone(), EntityPose {
+                position: [transform.position.x, transform.position.y, transform.position.z],
+                rotation: [rotation.0, rotation.1, rotation.2],
+            })
+        }).collect(),
+        trigger_events: room.events.events().into_iter().filter_map(|e| match e.kind {
+            RoomEventKind::Trigger { trigger, entity, entered } => Some(TriggerEvent { roomtime: e.roomtime, trigger, entity, entered }),
+            _ => None,
+        }).collect(),
+        scores: room.scores.iter().map(|s| (s.key().clone(), *s.value())).collect(),
+    };
+
+    room.is_alive.store(false, Ordering::Relaxed);
+
+    info!("Batch run finished in {:.2}s", report.wall_time);
+
+    let report = serde_json::to_string_pretty(&report).map_err(|e| format!("Failed to serialize report: {e}"))?;
+    match options.output {
+        Some(path) => fs::write(&path, report).map_err(|e| format!("Failed to write report to {path}: {e}")),
+        None => {
+            println!("{report}");
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,315 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use once_cell::sync::OnceCell;
+use serde::Deserialize;
+
+/// Config file read when ROBOSCAPESIM_CONFIG is not set, the defaults are used if it does not exist
+const DEFAULT_CONFIG_FILE: &str = "roboscapesim.toml";
+
+static CONFIG: OnceCell<ServerConfig> = OnceCell::new();
+
+/// Server settings, read from a TOML file with environment variables taking precedence
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Number of non-hibernating rooms reported to the API server as this server's capacity (MAX_ROOMS)
+    pub max_rooms: usize,
+    /// Room updates per second (UPDATE_FPS)
+    pub update_fps: f64,
+    /// Threads used for room updates, 0 for one per CPU (ROOM_UPDATE_THREADS)
+    pub room_update_threads: usize,
+    /// Token for the admin API, which is disabled if not set (ADMIN_TOKEN)
+    pub admin_token: Option<String>,
+    /// Key shared with the main API server to sign announcements, which are sent unsigned if not set (ANNOUNCE_KEY)
+    pub announce_key: Option<String>,
+    /// Tags the main API server can place rooms by, such as the server's region (SERVER_TAGS, comma separated)
+    pub tags: Vec<String>,
+    pub network: NetworkConfig,
+    pub limits: LimitsConfig,
+    pub timeouts: TimeoutsConfig,
+    pub storage: StorageConfig,
+}
+
+/// Ports and the servers this server talks to
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    /// Port of the HTTP API (LOCAL_API_PORT)
+    pub api_port: u16,
+    /// Port clients connect to with WebSockets (LOCAL_WS_PORT)
+    pub ws_port: u16,
+    /// Main API server to announce to, depends on the build if not set (MAIN_API_SERVER)
+    pub main_api_server: Option<String>,
+    /// NetsBlox cloud server environments and projects are loaded from (CLOUD_BASE)
+    pub cloud_base: String,
+    /// Host of the NetsBlox RoboScape UDP server (ROBOSCAPE_SERVER)
+    pub roboscape_server: String,
+    /// (ROBOSCAPE_PORT)
+    pub roboscape_port: u16,
+    /// Host of the NetsBlox IoTScape UDP server (IOTSCAPE_SERVER)
+    pub iotscape_server: String,
+    /// (IOTSCAPE_PORT)
+    pub iotscape_port: u16,
+    /// (IOTSCAPE_ANNOUNCE_ENDPOINT)
+    pub iotscape_announce_endpoint: String,
+    /// (IOTSCAPE_RESPONSE_ENDPOINT)
+    pub iotscape_response_endpoint: String,
+}
+
+/// Limits on what environments can add to a room
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    /// (ROBOT_LIMIT)
+    pub robots: usize,
+    /// (DYNAMIC_ENTITY_LIMIT)
+    pub dynamic_entities: usize,
+    /// Kinematic entities and triggers (KINEMATIC_ENTITY_LIMIT)
+    pub kinematic_entities: usize,
+    /// Entities without colliders (VISUAL_ONLY_ENTITY_LIMIT)
+    pub visual_only_entities: usize,
+}
+
+/// Timeouts, in seconds
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeoutsConfig {
+    /// Time without interaction before a room hibernates (HIBERNATE_TIMEOUT_SECS)
+    pub hibernate: i64,
+    /// Time without interaction before an edit mode room hibernates (EDIT_MODE_HIBERNATE_TIMEOUT_SECS)
+    pub edit_mode_hibernate: i64,
+    /// Time without interaction before a room is removed (ROOM_TIMEOUT_SECS)
+    pub room: i64,
+    /// Longest to wait for clients to disconnect when shutting down (SHUTDOWN_TIMEOUT_SECS)
+    pub shutdown: u64,
+}
+
+/// Where rooms and recordings are saved, each is disabled if not set
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StorageConfig {
+    /// (ROOM_DATA_DIR)
+    pub room_data_dir: Option<PathBuf>,
+    /// (RECORDING_DIR)
+    pub recording_dir: Option<PathBuf>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            max_rooms: 64,
+            update_fps: 60.0,
+            room_update_threads: 0,
+            admin_token: None,
+            announce_key: None,
+            tags: vec![],
+            network: NetworkConfig::default(),
+            limits: LimitsConfig::default(),
+            timeouts: TimeoutsConfig::default(),
+            storage: StorageConfig::default(),
+        }
+    }
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        NetworkConfig {
+            api_port: 3000,
+            ws_port: 5000,
+            main_api_server: None,
+            cloud_base: "https://cloud.netsblox.org".to_owned(),
+            roboscape_server: "52.73.65.98".to_owned(),
+            roboscape_port: 1973,
+            iotscape_server: "52.73.65.98".to_owned(),
+            iotscape_port: 1978,
+            iotscape_announce_endpoint: "https://services.netsblox.org/routes/iotscape/announce".to_owned(),
+            iotscape_response_endpoint: "https://services.netsblox.org/routes/iotscape/response".to_owned(),
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            robots: 4,
+            dynamic_entities: 25,
+            kinematic_entities: 100,
+            visual_only_entities: 250,
+        }
+    }
+}
+
+impl Default for TimeoutsConfig {
+    fn default() -> Self {
+        TimeoutsConfig {
+            hibernate: 60 * 15,
+            edit_mode_hibernate: 60 * 30,
+            room: 9 * 60 * 60,
+            shutdown: 10,
+        }
+    }
+}
+
+impl ServerConfig {
+    /// Read the config file, if any, then apply environment variables and check the result
+    pub fn load() -> Result<ServerConfig, String> {
+        let path = std::env::var("ROBOSCAPESIM_CONFIG").ok().filter(|p| !p.trim().is_empty());
+
+        let mut config = match &path {
+            Some(path) => Self::from_file(Path::new(path))?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => ServerConfig::default(),
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<ServerConfig, String> {
+        let contents = std::fs::read_to_string(path).map_err(|e| format!("Could not read config file {}: {}", path.display(), e))?;
+        toml::from_str(&contents).map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    fn apply_env(&mut self) -> Result<(), String> {
+        env_override("MAX_ROOMS", &mut self.max_rooms)?;
+        env_override("UPDATE_FPS", &mut self.update_fps)?;
+        env_override("ROOM_UPDATE_THREADS", &mut self.room_update_threads)?;
+        env_override_option("ADMIN_TOKEN", &mut self.admin_token)?;
+        env_override_option("ANNOUNCE_KEY", &mut self.announce_key)?;
+        if let Ok(tags) = std::env::var("SERVER_TAGS") {
+            self.tags = tags.split(',').map(|t| t.trim().to_owned()).filter(|t| !t.is_empty()).collect();
+        }
+
+        let network = &mut self.network;
+        env_override("LOCAL_API_PORT", &mut network.api_port)?;
+        env_override("LOCAL_WS_PORT", &mut network.ws_port)?;
+        env_override_option("MAIN_API_SERVER", &mut network.main_api_server)?;
+        env_override("CLOUD_BASE", &mut network.cloud_base)?;
+        env_override("ROBOSCAPE_SERVER", &mut network.roboscape_server)?;
+        env_override("ROBOSCAPE_PORT", &mut network.roboscape_port)?;
+        env_override("IOTSCAPE_SERVER", &mut network.iotscape_server)?;
+        env_override("IOTSCAPE_PORT", &mut network.iotscape_port)?;
+        env_override("IOTSCAPE_ANNOUNCE_ENDPOINT", &mut network.iotscape_announce_endpoint)?;
+        env_override("IOTSCAPE_RESPONSE_ENDPOINT", &mut network.iotscape_response_endpoint)?;
+
+        env_override("ROBOT_LIMIT", &mut self.limits.robots)?;
+        env_override("DYNAMIC_ENTITY_LIMIT", &mut self.limits.dynamic_entities)?;
+        env_override("KINEMATIC_ENTITY_LIMIT", &mut self.limits.kinematic_entities)?;
+        env_override("VISUAL_ONLY_ENTITY_LIMIT", &mut self.limits.visual_only_entities)?;
+
+        env_override("HIBERNATE_TIMEOUT_SECS", &mut self.timeouts.hibernate)?;
+        env_override("EDIT_MODE_HIBERNATE_TIMEOUT_SECS", &mut self.timeouts.edit_mode_hibernate)?;
+        env_override("ROOM_TIMEOUT_SECS", &mut self.timeouts.room)?;
+        env_override("SHUTDOWN_TIMEOUT_SECS", &mut self.timeouts.shutdown)?;
+
+        env_override_option("ROOM_DATA_DIR", &mut self.storage.room_data_dir)?;
+        env_override_option("RECORDING_DIR", &mut self.storage.recording_dir)?;
+        Ok(())
+    }
+
+    /// Check that values are usable, the error describes every problem found
+    pub fn validate(&self) -> Result<(), String> {
+        let mut errors = vec![];
+
+        if self.max_rooms == 0 {
+            errors.push("max_rooms must be at least 1".to_owned());
+        }
+
+        if !(1.0..=240.0).contains(&self.update_fps) {
+            errors.push("update_fps must be between 1 and 240".to_owned());
+        }
+
+        if self.network.api_port == self.network.ws_port {
+            errors.push("network.api_port and network.ws_port must be different".to_owned());
+        }
+
+        for (name, url) in [
+            ("network.main_api_server", self.network.main_api_server.as_deref().unwrap_or("http://")),
+            ("network.cloud_base", &self.network.cloud_base),
+            ("network.iotscape_announce_endpoint", &self.network.iotscape_announce_endpoint),
+            ("network.iotscape_response_endpoint", &self.network.iotscape_response_endpoint),
+        ] {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                errors.push(format!("{name} must be an http:// or https:// URL"));
+            }
+        }
+
+        for (name, host) in [("network.roboscape_server", &self.network.roboscape_server), ("network.iotscape_server", &self.network.iotscape_server)] {
+            if host.trim().is_empty() {
+                errors.push(format!("{name} must not be empty"));
+            }
+        }
+
+        if self.limits.robots == 0 {
+            errors.push("limits.robots must be at least 1".to_owned());
+        }
+
+        for (name, timeout) in [("timeouts.hibernate", self.timeouts.hibernate), ("timeouts.edit_mode_hibernate", self.timeouts.edit_mode_hibernate), ("timeouts.room", self.timeouts.room)] {
+            if timeout <= 0 {
+                errors.push(format!("{name} must be positive"));
+            }
+        }
+
+        if self.timeouts.hibernate.max(self.timeouts.edit_mode_hibernate) > self.timeouts.room {
+            errors.push("timeouts.hibernate and timeouts.edit_mode_hibernate must not be longer than timeouts.room".to_owned());
+        }
+
+        if self.admin_token.as_ref().is_some_and(|t| t.trim().is_empty()) {
+            errors.push("admin_token must not be empty".to_owned());
+        }
+
+        if self.announce_key.as_ref().is_some_and(|k| k.trim().is_empty()) {
+            errors.push("announce_key must not be empty".to_owned());
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("Invalid configuration:\n  {}", errors.join("\n  ")))
+        }
+    }
+}
+
+/// Replace a setting with an environment variable, if it is set
+fn env_override<T: FromStr>(name: &str, value: &mut T) -> Result<(), String> where T::Err: std::fmt::Display {
+    if let Some(env) = std::env::var(name).ok().filter(|v| !v.trim().is_empty()) {
+        *value = env.trim().parse().map_err(|e| format!("Invalid value for {name}: {e}"))?;
+    }
+    Ok(())
+}
+
+fn env_override_option<T: FromStr>(name: &str, value: &mut Option<T>) -> Result<(), String> where T::Err: std::fmt::Display {
+    if let Some(env) = std::env::var(name).ok().filter(|v| !v.trim().is_empty()) {
+        *value = Some(env.trim().parse().map_err(|e| format!("Invalid value for {name}: {e}"))?);
+    }
+    Ok(())
+}
+
+/// Load the configuration, must be called before `config`
+pub fn init() -> Result<(), String> {
+    let config = ServerConfig::load()?;
+    let _ = CONFIG.set(config);
+    Ok(())
+}
+
+/// Server configuration, the defaults if `init` was not called (such as in tests)
+pub fn config() -> &'static ServerConfig {
+    CONFIG.get_or_init(ServerConfig::default)
+}
+
+#[test]
+fn test_config_file() {
+    let config: ServerConfig = toml::from_str("max_rooms = 8\n[limits]\nrobots = 10\n[timeouts]\nhibernate = 60\n").unwrap();
+    assert_eq!(config.max_rooms, 8);
+    assert_eq!(config.limits.robots, 10);
+    assert_eq!(config.limits.dynamic_entities, LimitsConfig::default().dynamic_entities);
+    assert!(config.validate().is_ok());
+
+    let config: ServerConfig = toml::from_str("update_fps = 0\n[timeouts]\nhibernate = 100000\n").unwrap();
+    let error = config.validate().unwrap_err();
+    assert!(error.contains("update_fps") && error.contains("timeouts.hibernate"));
+
+    assert!(toml::from_str::<ServerConfig>("max_room = 8").is_err());
+}
@@ -1,8 +1,9 @@
-use std::sync::atomic::Ordering;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use dashmap::DashMap;
-use log::info;
+use log::{error, info};
+use roboscapesim_common::UpdateMessage;
 use once_cell::sync::Lazy;
 use room::RoomData;
 use room::SHARED_CLOCK;
@@ -14,10 +15,17 @@ use tokio::{
 };
 use util::util::get_timestamp;
 
-use crate::api::{create_api, get_external_ip, EXTERNAL_IP};
+use crate::config::config;
+use crate::api::{announce_draining, create_api, get_external_ip, EXTERNAL_IP};
+use crate::room::clients::ClientsManager;
 use crate::socket::{ws_accept, ws_rx, ws_tx};
 
+mod admin;
 mod api;
+mod batch;
+mod config;
+mod metrics;
+mod replay;
 mod robot;
 mod room;
 mod simulation;
@@ -31,14 +39,14 @@ mod util;
 #[path = "./services/mod.rs"]
 mod services;
 
-pub const MAX_ROOMS: usize = 64;
-pub const UPDATE_FPS: f64 = 60.0;
-const UPDATE_INTERVAL_MILLIS: u64 = (1000.0 / UPDATE_FPS) as u64;
 const ROOM_CLEANUP_INTERVAL_SECS: u64 = 120; // 2 minutes
 
 pub static ROOMS: Lazy<DashMap<String, Arc<RoomData>>> = Lazy::new(|| DashMap::new());
 pub static CLIENTS: Lazy<DashMap<u128, SocketInfo>> = Lazy::new(|| DashMap::new());
 
+/// Set when the server starts shutting down, no new rooms are created or joined after this
+pub static DRAINING: AtomicBool = AtomicBool::new(false);
+
 #[tokio::main]
 async fn main() {
     // Load environment variables
@@ -52,6 +60,30 @@ async fn main() {
         .env()
         .init()
         .unwrap();
+
+    if let Err(e) = config::init() {
+        error!("{e}");
+        std::process::exit(1);
+    }
+
+    // Run a single environment without clients if requested
+    match batch::BatchOptions::from_args(&std::env::args().collect::<Vec<_>>()) {
+        Ok(Some(options)) => {
+            let _update_loop = task::spawn(update_fn());
+
+            if let Err(e) = batch::run(options).await {
+                error!("{e}");
+                std::process::exit(1);
+            }
+            return;
+        },
+        Ok(None) => {},
+        Err(e) => {
+            error!("{e}");
+            std::process::exit(1);
+        }
+    }
+
     info!("Starting RoboScape Online Server...");
     
     if let Ok(ip) = get_external_ip().await {
@@ -71,16 +103,73 @@ async fn main() {
     // Cleanup dead rooms
     let _cleanup_loop = task::spawn(cleanup_dead_rooms());
 
+    // Restore rooms saved before the last shutdown
+    room::persistence::restore_rooms().await;
+    let _save_loop = task::spawn(room::persistence::save_rooms_periodically());
+
     // Announce to master server
     let _announce_api = task::spawn(api::announce_api());
 
-    // Start API server
-    let api = create_api();
-    api.await;
+    // Start API server, which keeps running while draining
+    let api = task::spawn(create_api());
+    tokio::select! {
+        _ = api => {},
+        _ = shutdown_signal() => {
+            shutdown().await;
+        }
+    }
+}
+
+/// Wait for Ctrl+C or SIGTERM
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => { signal.recv().await; },
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {:?}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate => {},
+    }
+}
+
+/// Stop taking new rooms, tell the API server and clients, save rooms, then wait for clients to leave until the timeout
+async fn shutdown() {
+    info!("Shutting down...");
+    DRAINING.store(true, Ordering::Relaxed);
+
+    announce_draining().await;
+
+    // Save now in case the process is killed before clients leave
+    room::persistence::save_all_rooms().await;
+
+    // Clients can only reconnect to their rooms if they were saved
+    let timeout = config().timeouts.shutdown;
+    let reconnect_after = room::persistence::DATA_DIR.is_some().then_some(timeout as f64);
+    let msg = UpdateMessage::ServerShutdown("The server is restarting".to_owned(), reconnect_after);
+    for client in CLIENTS.iter() {
+        ClientsManager::send_to_client(&msg, *client.key());
+    }
+
+    let deadline = time::Instant::now() + Duration::from_secs(timeout);
+    while !CLIENTS.is_empty() && time::Instant::now() < deadline {
+        time::sleep(Duration::from_millis(250)).await;
+    }
+
+    room::persistence::save_all_rooms().await;
+    info!("Shutdown complete, {} clients still connected", CLIENTS.len());
 }
 
 async fn update_fn() {
-    let mut interval = time::interval(Duration::from_millis(UPDATE_INTERVAL_MILLIS));
+    let mut interval = time::interval(Duration::from_secs_f64(1.0 / config().update_fps));
     interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
     
     loop {
@@ -95,21 +184,60 @@ async fn cleanup_dead_rooms() {
     
     loop {
         interval.tick().await;
-        
-        let mut dead_rooms = vec![];
-        let timestamp = get_timestamp();
-        for kvp in ROOMS.iter() {
-            let room = kvp.value();
-
-            if timestamp - room.last_interaction_time.load(Ordering::Relaxed) > room.metadata.full_timeout {
-                dead_rooms.push(kvp.key().clone());
-                room.is_alive.store(false, Ordering::Relaxed);
-            }
+
+        for room in remove_dead_rooms(&ROOMS, get_timestamp()) {
+            crate::room::persistence::delete_room(&room);
         }
+    }
+}
 
-        for room in dead_rooms {
-            info!("Room {} has timed out and will be removed", room);
-            ROOMS.remove(&room);
+/// Remove rooms that have timed out or stopped, giving the names of those whose saved state should be deleted
+///
+/// Rooms stopped by a panic keep their saved state, so they can be restored after a restart instead of being lost to one bad update.
+fn remove_dead_rooms(rooms: &DashMap<String, Arc<RoomData>>, timestamp: i64) -> Vec<String> {
+    let mut dead_rooms = vec![];
+    for kvp in rooms.iter() {
+        let room = kvp.value();
+
+        // Rooms are also stopped if they are closed or their update panics
+        if timestamp - room.last_interaction_time.load(Ordering::Relaxed) > room.metadata.full_timeout || !room.is_alive.load(Ordering::Relaxed) {
+            dead_rooms.push((kvp.key().clone(), room.panicked.load(Ordering::Relaxed)));
+            room.is_alive.store(false, Ordering::Relaxed);
         }
     }
+
+    let mut expired_rooms = vec![];
+    for (room, panicked) in dead_rooms {
+        rooms.remove(&room);
+
+        if panicked {
+            info!("Room {} panicked and will be removed, keeping its saved state", room);
+        } else {
+            info!("Room {} has timed out or stopped and will be removed", room);
+            expired_rooms.push(room);
+        }
+    }
+
+    expired_rooms
+}
+
+#[tokio::test]
+async fn test_remove_dead_rooms() {
+    services::service_struct::ANNOUNCE_SERVICES.store(false, Ordering::Relaxed);
+
+    let rooms = DashMap::new();
+    for name in ["Panicked", "Closed", "Running"] {
+        let room = RoomData::new(Some(name.to_owned()), None, None, None, false, true).await;
+        rooms.insert(name.to_owned(), room);
+    }
+
+    let panicked = rooms.get("Panicked").unwrap().clone();
+    panicked.panicked.store(true, Ordering::Relaxed);
+    panicked.is_alive.store(false, Ordering::Relaxed);
+    rooms.get("Closed").unwrap().close("Closed by test");
+
+    // Only the closed room's saved state is deleted, and the running room is left alone
+    assert_eq!(remove_dead_rooms(&rooms, get_timestamp()), vec!["Closed".to_owned()]);
+    assert_eq!(rooms.len(), 1);
+    assert!(rooms.get("Running").unwrap().is_alive.load(Ordering::Relaxed));
 }
@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use roboscapesim_common::metrics::{Histogram, MetricType, MetricsWriter};
+
+use crate::{CLIENTS, ROOMS};
+
+/// Bytes received from and sent to clients over WebSockets
+pub static WS_BYTES_IN: AtomicU64 = AtomicU64::new(0);
+pub static WS_BYTES_OUT: AtomicU64 = AtomicU64::new(0);
+
+/// RoboScape messages received by and sent from simulated robots
+pub static ROBOT_UDP_MESSAGES_IN: AtomicU64 = AtomicU64::new(0);
+pub static ROBOT_UDP_MESSAGES_OUT: AtomicU64 = AtomicU64::new(0);
+
+/// Runtime errors in environment projects
+pub static VM_ERRORS: AtomicU64 = AtomicU64::new(0);
+
+/// IoTScape requests handled, by service type
+pub static IOTSCAPE_REQUESTS: Lazy<DashMap<String, AtomicU64>> = Lazy::new(|| DashMap::new());
+
+/// Time taken to handle IoTScape requests
+pub static IOTSCAPE_LATENCY: Lazy<Histogram> = Lazy::new(|| Histogram::default());
+
+/// Time taken by physics steps, across all rooms
+pub static PHYSICS_STEP: Lazy<Histogram> = Lazy::new(|| Histogram::default());
+
+/// Count an IoTScape request to a service type
+pub fn count_iotscape_request(service: &str) {
+    if let Some(count) = IOTSCAPE_REQUESTS.get(service) {
+        count.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    IOTSCAPE_REQUESTS.entry(service.to_owned()).or_default().fetch_add(1, Ordering::Relaxed);
+}
+
+/// Render all metrics for the `/metrics` route
+pub fn render() -> String {
+    let mut metrics = MetricsWriter::new();
+
+    let hibernating = ROOMS.iter().filter(|r| r.metadata.hibernating.load(Ordering::Relaxed)).count();
+    metrics.gauge("roboscapesim_rooms_active", "Rooms on this server that are not hibernating", ROOMS.len().saturating_sub(hibernating) as f64);
+    metrics.gauge("roboscapesim_rooms_hibernating", "Rooms on this server that are hibernating", hibernating as f64);
+    metrics.gauge("roboscapesim_clients_connected", "Clients connected over WebSockets", CLIENTS.len() as f64);
+
+    metrics.describe("roboscapesim_room_tick_seconds", MetricType::Histogram, "Time taken by room update ticks");
+    for room in ROOMS.iter() {
+        metrics.histogram("roboscapesim_room_tick_seconds", &[("room", room.key().as_str())], &room.tick_timing.histogram);
+    }
+
+    metrics.describe("roboscapesim_room_skipped_ticks_total", MetricType::Counter, "Room update ticks skipped because the previous tick was still running");
+    for room in ROOMS.iter() {
+        let report = room.tick_timing.report(room.key());
+        metrics.sample("roboscapesim_room_skipped_ticks_total", &[("room", room.key().as_str())], report.skipped_ticks as f64);
+    }
+
+    metrics.describe("roboscapesim_physics_step_seconds", MetricType::Histogram, "Time taken by physics steps");
+    metrics.histogram("roboscapesim_physics_step_seconds", &[], &PHYSICS_STEP);
+
+    metrics.counter("roboscapesim_websocket_received_bytes_total", "Bytes received from clients over WebSockets", WS_BYTES_IN.load(Ordering::Relaxed));
+    metrics.counter("roboscapesim_websocket_sent_bytes_total", "Bytes sent to clients over WebSockets", WS_BYTES_OUT.load(Ordering::Relaxed));
+
+    metrics.describe("roboscapesim_iotscape_requests_total", MetricType::Counter, "IoTScape requests handled, by service type");
+    for service in IOTSCAPE_REQUESTS.iter() {
+        metrics.sample("roboscapesim_iotscape_requests_total", &[("service", service.key().as_str())], service.value().load(Ordering::Relaxed) as f64);
+    }
+
+    metrics.describe("roboscapesim_iotscape_request_seconds", MetricType::Histogram, "Time taken to handle IoTScape requests");
+    metrics.histogram("roboscapesim_iotscape_request_seconds", &[], &IOTSCAPE_LATENCY);
+
+    metrics.describe("roboscapesim_robot_udp_messages_total", MetricType::Counter, "RoboScape messages exchanged by simulated robots");
+    metrics.sample("roboscapesim_robot_udp_messages_total", &[("direction", "received")], ROBOT_UDP_MESSAGES_IN.load(Ordering::Relaxed) as f64);
+    metrics.sample("roboscapesim_robot_udp_messages_total", &[("direction", "sent")], ROBOT_UDP_MESSAGES_OUT.load(Ordering::Relaxed) as f64);
+
+    metrics.counter("roboscapesim_vm_errors_total", "Runtime errors in environment projects", VM_ERRORS.load(Ordering::Relaxed));
+
+    metrics.finish()
+}
@@ -0,0 +1,204 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use dashmap::DashMap;
+use log::info;
+use once_cell::sync::Lazy;
+use roboscapesim_common::{ObjectData, ReplayControl, RoomState, UpdateMessage};
+use tokio::time::{sleep, Duration};
+
+#[cfg(feature = "no_deadlocks")]
+use no_deadlocks::Mutex;
+#[cfg(not(feature = "no_deadlocks"))]
+use std::sync::Mutex;
+
+use crate::room::clients::ClientsManager;
+use crate::room::recording::{load_recording, RecordedEvent, RecordingHeader};
+use crate::CLIENTS;
+
+/// Time between playback steps
+const STEP_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How often the playback state is sent to the client while playing
+const STATUS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Slowest and fastest playback speeds allowed
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 16.0;
+
+/// Playback controls of a recording being watched
+#[derive(Debug)]
+struct ReplayState {
+    paused: AtomicBool,
+    speed: Mutex<f64>,
+    /// Time to jump to on the next playback step
+    seek: Mutex<Option<f64>>,
+    /// Whether the client should be sent the playback state on the next step
+    changed: AtomicBool,
+}
+
+/// Recordings being watched, by client ID
+static REPLAYS: Lazy<DashMap<u128, Arc<ReplayState>>> = Lazy::new(|| DashMap::new());
+
+/// Load a recording and stream it to a client, replacing any recording it was already watching
+pub fn start_replay(client_id: u128, recording_id: String) {
+    tokio::spawn(async move {
+        let id = recording_id.clone();
+        let recording = tokio::task::spawn_blocking(move || load_recording(&id)).await.unwrap_or_else(|e| Err(e.to_string()));
+
+        match recording {
+            Ok((header, events)) => {
+                info!("Client {} watching recording {}", client_id, recording_id);
+                run_replay(client_id, header, events).await;
+            },
+            Err(e) => {
+                info!("Error loading recording {}: {}", recording_id, e);
+                ClientsManager::send_to_client(&UpdateMessage::FatalError(e), client_id);
+            },
+        }
+    });
+}
+
+/// Change playback of the recording a client is watching
+pub fn control_replay(client_id: u128, control: ReplayControl) {
+    let Some(state) = REPLAYS.get(&client_id).map(|s| s.value().clone()) else {
+        return;
+    };
+
+    match control {
+        ReplayControl::Pause => state.paused.store(true, Ordering::Relaxed),
+        ReplayControl::Resume => state.paused.store(false, Ordering::Relaxed),
+        ReplayControl::Seek(time) if time.is_finite() => {
+            state.seek.lock().unwrap().replace(time);
+        },
+        ReplayControl::Speed(speed) if speed.is_finite() => {
+            *state.speed.lock().unwrap() = speed.clamp(MIN_SPEED, MAX_SPEED);
+        },
+        _ => {},
+    }
+
+    state.changed.store(true, Ordering::Relaxed);
+}
+
+/// Stream a recording's updates to a client with their original timing, until the client leaves or watches another recording
+async fn run_replay(client_id: u128, header: RecordingHeader, events: Vec<(f64, RecordedEvent)>) {
+    let state = Arc::new(ReplayState {
+        paused: AtomicBool::new(false),
+        speed: Mutex::new(1.0),
+        seek: Mutex::new(None),
+        changed: AtomicBool::new(true),
+    });
+    REPLAYS.insert(client_id, state.clone());
+
+    let duration = events.last().map(|(t, _)| *t).unwrap_or_default();
+    let updates: Vec<(f64, UpdateMessage)> = events.into_iter()
+        .filter_map(|(t, event)| match event {
+            RecordedEvent::Update(msg) if is_replayable(&msg) => Some((t, msg)),
+            _ => None,
+        })
+        .collect();
+
+    ClientsManager::send_to_client(&UpdateMessage::RemoveAll(), client_id);
+    ClientsManager::send_to_client(&UpdateMessage::RoomInfo(RoomState { name: format!("Replay of {}", header.room), roomtime: 0.0, users: vec![] }), client_id);
+
+    // Update times are replaced with time since playback started, so clients interpolate at the playback speed
+    let started = Instant::now();
+    let mut last_step = started;
+    let mut last_status = started;
+    let mut position = 0.0;
+    let mut next = 0;
+
+    while CLIENTS.contains_key(&client_id) && REPLAYS.get(&client_id).is_some_and(|s| Arc::ptr_eq(s.value(), &state)) {
+        let now = Instant::now();
+        let elapsed = (now - last_step).as_secs_f64();
+        let clock = (now - started).as_secs_f64();
+        last_step = now;
+
+        let seek = state.seek.lock().unwrap().take();
+        if let Some(target) = seek {
+            position = target.clamp(0.0, duration);
+            next = send_state_at(client_id, &updates, position, clock);
+        } else if !state.paused.load(Ordering::Relaxed) && duration > 0.0 {
+            if position >= duration {
+                // Start over when resumed at the end
+                position = 0.0;
+                next = send_state_at(client_id, &updates, position, clock);
+            } else {
+                position = (position + elapsed * *state.speed.lock().unwrap()).min(duration);
+
+                if position >= duration {
+                    state.paused.store(true, Ordering::Relaxed);
+                    state.changed.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+
+        while next < updates.len() && updates[next].0 <= position {
+            ClientsManager::send_to_client(&with_time(updates[next].1.clone(), clock), client_id);
+            next += 1;
+        }
+
+        if state.changed.swap(false, Ordering::Relaxed) || (!state.paused.load(Ordering::Relaxed) && now - last_status >= STATUS_INTERVAL) {
+            ClientsManager::send_to_client(&UpdateMessage::ReplayStatus(position, duration, state.paused.load(Ordering::Relaxed), *state.speed.lock().unwrap()), client_id);
+            last_status = now;
+        }
+
+        sleep(STEP_INTERVAL).await;
+    }
+
+    REPLAYS.remove_if(&client_id, |_, s| Arc::ptr_eq(s, &state));
+}
+
+/// Test if a recorded message should be shown to someone watching the recording
+fn is_replayable(msg: &UpdateMessage) -> bool {
+    !matches!(msg, UpdateMessage::Recording(_) | UpdateMessage::FatalError(_) | UpdateMessage::ResumeToken(_) | UpdateMessage::Hello(..) | UpdateMessage::ObjectIds(_) | UpdateMessage::CompactUpdate(..))
+}
+
+/// Replace the time of an update
+fn with_time(mut msg: UpdateMessage, time: f64) -> UpdateMessage {
+    if let UpdateMessage::Update(t, _, _) = &mut msg {
+        *t = time;
+    }
+    msg
+}
+
+/// Send the objects in the room at a time in the recording, returning the index of the next update to play
+fn send_state_at(client_id: u128, updates: &[(f64, UpdateMessage)], position: f64, clock: f64) -> usize {
+    // Full updates contain every object, so only later changes need to be applied
+    let mut next = updates.iter()
+        .rposition(|(t, msg)| *t <= position && matches!(msg, UpdateMessage::Update(_, true, _)))
+        .unwrap_or(0);
+
+    let mut objects: HashMap<String, ObjectData> = HashMap::new();
+    while next < updates.len() && updates[next].0 <= position {
+        match &updates[next].1 {
+            UpdateMessage::Update(_, full_update, changed) => {
+                if *full_update {
+                    objects.clear();
+                }
+
+                for (name, obj) in changed {
+                    // Incremental updates leave out visual info
+                    let visual_info = objects.get(name).and_then(|o| o.visual_info.clone());
+                    let mut obj = obj.clone();
+                    if obj.visual_info.is_none() {
+                        obj.visual_info = visual_info;
+                    }
+                    objects.insert(name.clone(), obj);
+                }
+            },
+            UpdateMessage::RemoveObject(name) => {
+                objects.remove(name);
+            },
+            UpdateMessage::RemoveAll() => objects.clear(),
+            _ => {},
+        }
+        next += 1;
+    }
+
+    ClientsManager::send_to_client(&UpdateMessage::RemoveAll(), client_id);
+    ClientsManager::send_to_client(&UpdateMessage::Update(clock, true, objects), client_id);
+    next
+}
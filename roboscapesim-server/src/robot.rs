@@ -1,5 +1,6 @@
 use std::net::UdpSocket;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::time::{SystemTime, Duration};
 
 use dashmap::{DashMap, DashSet};
@@ -8,9 +9,12 @@ use log::error;
 use roboscapesim_common::{UpdateMessage, Transform};
 use rapier3d::prelude::*;
 
+use crate::config::config;
+use crate::metrics;
 use crate::robot::messages::send_roboscape_message;
 use crate::robot::motor::RobotMotorData;
 use crate::robot::physics::RobotPhysics;
+use crate::room::recording::{RecordedEvent, Recorder};
 use crate::simulation::Simulation;
 use crate::util::traits::resettable::Resettable;
 use crate::util::util::get_timestamp;
@@ -42,6 +46,8 @@ pub struct RobotData {
     pub claimed_by: Option<String>,
     /// Whether this robot can be claimed, non-claimable robots are intended for scenario controlled robots
     pub claimable: bool,
+    /// Whether the room owner has locked this robot, locked robots cannot be claimed
+    pub locked: bool,
     pub start_time: SystemTime,
     pub last_message_time: SystemTime,
     pub min_message_spacing: u128,
@@ -49,8 +55,9 @@ pub struct RobotData {
 
 impl RobotData {
     pub fn setup_robot_socket(robot: &mut RobotData) {
-        let server = std::env::var("ROBOSCAPE_SERVER").unwrap_or("52.73.65.98".to_string());
-        let port = std::env::var("ROBOSCAPE_PORT").unwrap_or("1973".to_string());
+        let network = &config().network;
+        let server = network.roboscape_server.clone();
+        let port = network.roboscape_port.to_string();
         let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
         socket.set_nonblocking(true).unwrap();
 
@@ -68,7 +75,7 @@ impl RobotData {
         }
     }
 
-    pub fn robot_update(robot: &mut RobotData, sim: Arc<Simulation>, clients: &DashMap<String, DashSet<u128>>, dt: f64) -> (bool, Option<UpdateMessage>) {
+    pub fn robot_update(robot: &mut RobotData, sim: Arc<Simulation>, clients: &DashMap<String, DashSet<u128>>, recorder: &Recorder, dt: f64) -> (bool, Option<UpdateMessage>) {
         if robot.socket.is_none() {
             return (false, None);
         }
@@ -90,6 +97,8 @@ impl RobotData {
 
         if let Ok(size) = size {
             if size > 0 {
+                metrics::ROBOT_UDP_MESSAGES_IN.fetch_add(1, Ordering::Relaxed);
+                recorder.record(|| RecordedEvent::RoboScapeCommand(robot.id.clone(), buf[..size].to_vec()));
                 messages::process_roboscape_message(robot, buf, &mut had_messages, clients, &sim, &mut msg, size);
             }
         }
@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::time::SystemTime;
 
 use dashmap::{DashMap, DashSet};
@@ -6,6 +7,7 @@ use log::{error, trace};
 use roboscapesim_common::UpdateMessage;
 use rapier3d::prelude::*;
 
+use crate::metrics;
 use crate::robot::motor::{DriveState, SET_DISTANCE_DRIVE_SPEED};
 use crate::robot::RobotData;
 use crate::room::clients::ClientsManager;
@@ -231,5 +233,6 @@ pub fn send_roboscape_message(robot: &mut RobotData, message: &[u8]) -> Result<u
     // Message
     buf.append(&mut Vec::from(message));
 
+    metrics::ROBOT_UDP_MESSAGES_OUT.fetch_add(1, Ordering::Relaxed);
     robot.socket.as_mut().unwrap().send(buf.as_slice())
 }
\ No newline at end of file
@@ -150,6 +150,7 @@ impl RobotPhysics {
                 initial_transform: Transform { position: position.unwrap_or(box_center.to_owned().coords).into(), rotation: orientation.unwrap_or(box_rotation).into(), ..Default::default() },
                 claimed_by: None,
                 claimable: true,
+                locked: false,
                 start_time: SystemTime::now(),
                 last_message_time: SystemTime::UNIX_EPOCH,
                 min_message_spacing: 1000 / 25, // 25 messages per second
@@ -195,14 +196,7 @@ impl RobotPhysics {
 
         if let Some(rotation) = rotation {
             // Set rotation
-            match rotation {
-                Orientation::Quaternion(q) => {
-                    body.set_rotation(UnitQuaternion::new_unchecked(q), true);
-                }
-                Orientation::Euler(e) => {
-                    body.set_rotation(UnitQuaternion::from_euler_angles(e.x, e.y, e.z), true);
-                }
-            }
+            body.set_rotation(rotation.to_quaternion(), true);
         }
     }
 
@@ -1,8 +1,8 @@
-use std::collections::{HashMap, BTreeMap};
+use std::collections::{HashMap, BTreeMap, VecDeque};
 use std::rc::Rc;
 use std::thread::{self, JoinHandle};
-use std::time::Duration;
-use std::sync::atomic::{AtomicBool, Ordering, AtomicI64};
+use std::time::{Duration, Instant};
+use std::sync::atomic::{AtomicBool, Ordering, AtomicI64, AtomicU32};
 
 use dashmap::{DashMap, DashSet};
 use derivative::Derivative;
@@ -14,7 +14,7 @@ use netsblox_vm::{runtime::{SimpleValue, ErrorCause, CommandStatus, Command, Req
 use once_cell::sync::{Lazy, OnceCell};
 use rand::Rng;
 use rapier3d::geometry::ColliderHandle;
-use rapier3d::prelude::{ColliderBuilder, RigidBodyBuilder, AngVector, Real};
+use rapier3d::prelude::{ColliderBuilder, RigidBodyBuilder, RigidBodyType, AngVector, Real};
 use roboscapesim_common::*;
 use tokio::time;
 use tokio::{spawn, time::sleep};
@@ -25,20 +25,26 @@ use no_deadlocks::{Mutex, RwLock};
 #[cfg(not(feature = "no_deadlocks"))]
 use std::sync::{Mutex, RwLock};
 
+use crate::room::animation::PathAnimation;
+use crate::room::chat::ChatManager;
 use crate::room::clients::ClientsManager;
+use crate::room::events::{EventLog, RoomEventKind, MAX_EVENTS, MAX_HEADLESS_EVENTS};
+use crate::room::recording::{RecordedEvent, Recorder};
+use crate::room::timing::TickTiming;
 use crate::room::messages::MessageHandler;
-use crate::room::metadata::RoomMetadata;
+use crate::room::metadata::{hash_password, RoomMetadata};
 use crate::room::vm::VMManager;
-use crate::{services::*, UPDATE_FPS};
+use crate::config::{config, LimitsConfig};
+use crate::services::*;
 use crate::util::util::get_timestamp;
-use crate::{CLIENTS};
-use crate::api::{REQWEST_CLIENT, get_main_api_server};
-use crate::scenarios::load_environment;
+use crate::{metrics, CLIENTS, ROOMS};
+use crate::api::send_to_main_api;
+use crate::scenarios::{get_environment_limits, get_local_scenario, load_environment};
 use crate::simulation::{Simulation, SCALE};
 use crate::util::extra_rand::UpperHexadecimal;
 use crate::robot::RobotData;
 use crate::util::traits::resettable::{Resettable, RigidBodyResetter};
-use crate::vm::{STEPS_PER_IO_ITER, open_project, YIELDS_BEFORE_IDLE_SLEEP, IDLE_SLEEP_TIME, DEFAULT_BASE_URL, C, get_env};
+use crate::vm::{STEPS_PER_IO_ITER, open_project, YIELDS_BEFORE_IDLE_SLEEP, IDLE_SLEEP_TIME, C, get_env};
 pub(crate) mod netsblox_api;
 pub(crate) mod management;
 mod messages;
@@ -46,15 +52,27 @@ mod vm;
 pub(crate) mod objects;
 pub(crate) mod clients;
 pub(crate) mod metadata;
+pub(crate) mod animation;
+pub(crate) mod timing;
+pub(crate) mod persistence;
+pub(crate) mod chat;
+pub(crate) mod editing;
+pub(crate) mod recording;
+pub(crate) mod events;
 
 const COLLECT_PERIOD: Duration = Duration::from_secs(60);
 
+/// Seconds before a room hibernates or is removed that clients are warned
+const IDLE_WARNING_SECS: [i64; 2] = [120, 30];
+
 #[derive(Derivative)]
 #[derivative(Debug)]
 /// Holds the data for a single room
 pub struct RoomData {
     #[derivative(Debug = "ignore")]
     pub is_alive: Arc<AtomicBool>,
+    /// Whether the room was stopped by a panic in its update, such rooms keep their saved state
+    pub(crate) panicked: AtomicBool,
     pub objects: DashMap<String, ObjectData>,
     pub metadata: RoomMetadata,
     pub last_interaction_time: Arc<AtomicI64>,
@@ -87,6 +105,75 @@ pub struct RoomData {
     #[derivative(Debug = "ignore")]
     pub vm_manager: OnceCell<Arc<VMManager>>,
     pub clients_manager: ClientsManager,
+    /// Records the room's timeline while recording is enabled
+    pub(crate) recorder: Arc<Recorder>,
+    /// Chat messages and annotations between users
+    pub(crate) chat: ChatManager,
+    /// Path animations of kinematic entities with the body types to restore when they stop, by entity name
+    pub(crate) animations: DashMap<String, (PathAnimation, RigidBodyType)>,
+    /// Whether the room should be reset on the next update, for resets requested outside of updates
+    #[derivative(Debug = "ignore")]
+    pub(crate) reset_requested: AtomicBool,
+    /// Whether an update tick is currently running on the worker pool
+    #[derivative(Debug = "ignore")]
+    tick_running: AtomicBool,
+    /// Duration of recent update ticks
+    pub tick_timing: TickTiming,
+    /// Custom scores set by the environment through `setScore`
+    pub scores: DashMap<String, f64>,
+    /// Recent activity in the room
+    #[derivative(Debug = "ignore")]
+    pub(crate) events: Arc<EventLog>,
+    /// MAC addresses to give to new robots before generating random ones, used to keep robot IDs when restoring rooms
+    #[derivative(Debug = "ignore")]
+    pub(crate) reserved_macs: Mutex<VecDeque<[u8; 6]>>,
+    /// Numeric IDs of objects, used to refer to them in compact updates
+    pub(crate) object_ids: DashMap<String, u32>,
+    /// Next numeric object ID to use
+    next_numeric_object_id: AtomicU32,
+    /// Scale and kinematic flag of objects as last sent to clients, by numeric ID, so compact updates can leave them out if unchanged
+    #[derivative(Debug = "ignore")]
+    pub(crate) last_sent_shapes: DashMap<u32, (Vector3<f32>, bool)>,
+    /// Roomtime each object was last sent as changed, so resumed sessions only get what they missed
+    pub(crate) object_change_times: DashMap<String, f64>,
+    /// Roomtime objects were last added or removed
+    pub(crate) last_structure_change: RwLock<f64>,
+    /// Robot and entity limits, from the scenario or the server config
+    pub(crate) limits: LimitsConfig,
+    /// Smallest of `IDLE_WARNING_SECS` clients have been warned about, `i64::MAX` if there is no warning
+    #[derivative(Debug = "ignore")]
+    idle_warning: AtomicI64,
+}
+
+/// Worker pool that runs room updates, so physics does not block the async runtime or other rooms
+static ROOM_UPDATE_POOL: Lazy<rayon::ThreadPool> = Lazy::new(|| {
+    // 0 lets rayon pick based on the number of CPUs
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(config().room_update_threads)
+        .thread_name(|i| format!("room-update-{i}"))
+        // Rayon aborts the process on panics in spawned jobs without a handler, ticks catch their own panics so this is a last resort
+        .panic_handler(|_| error!("Room update job panicked"))
+        .build()
+        .expect("Failed to create room update pool")
+});
+
+/// Share of the room update pool's time used by non-hibernating rooms, from their average tick times, 1.0 being fully busy
+pub(crate) fn tick_load() -> f64 {
+    let budget_ms = 1000.0 / config().update_fps * ROOM_UPDATE_POOL.current_num_threads() as f64;
+    let used_ms = ROOMS.iter()
+        .filter(|r| !r.metadata.hibernating.load(Ordering::Relaxed))
+        .map(|r| r.tick_timing.report(r.key()).average_ms)
+        .sum::<f64>();
+    used_ms / budget_ms
+}
+
+/// Clears a room's `tick_running` flag when its tick ends, even if the tick panics
+struct TickRunningGuard<'a>(&'a RoomData);
+
+impl Drop for TickRunningGuard<'_> {
+    fn drop(&mut self) {
+        self.0.tick_running.store(false, Ordering::Release);
+    }
 }
 
 pub static SHARED_CLOCK: Lazy<Arc<Clock>> = Lazy::new(|| {
@@ -94,21 +181,34 @@ pub static SHARED_CLOCK: Lazy<Arc<Clock>> = Lazy::new(|| {
 });
 
 impl RoomData {
-    pub async fn new(name: Option<String>, environment: Option<String>, password: Option<String>, edit_mode: bool) -> Arc<RoomData> {
+    pub async fn new(name: Option<String>, environment: Option<String>, password: Option<String>, owner: Option<String>, edit_mode: bool, headless: bool) -> Arc<RoomData> {
+        let mut metadata = RoomMetadata::new(name.unwrap_or_else(|| Self::generate_room_id(None)), environment.unwrap_or("Default".to_owned()), password.map(|p| hash_password(&p)), if edit_mode { config().timeouts.edit_mode_hibernate } else { config().timeouts.hibernate }, config().timeouts.room, edit_mode, headless);
+        metadata.owner = owner;
+        Self::create(metadata, VecDeque::new()).await
+    }
+
+    /// Create a room from its metadata, with robot MAC addresses to use for the first robots added
+    pub(crate) async fn create(metadata: RoomMetadata, reserved_macs: VecDeque<[u8; 6]>) -> Arc<RoomData> {
         let (netsblox_msg_tx, netsblox_msg_rx) = mpsc::channel();
         let (iotscape_tx, iotscape_rx) = mpsc::channel();
         let netsblox_msg_rx = Arc::new(Mutex::new(netsblox_msg_rx));
         let iotscape_rx = Arc::new(Mutex::new(iotscape_rx));
+        let roomtime = Arc::new(RwLock::new(0.0));
+        let recorder = Arc::new(Recorder::new(roomtime.clone()));
+        // Batch reports need every trigger event, so headless rooms keep many more
+        let events = Arc::new(EventLog::new(roomtime.clone(), if metadata.headless { MAX_HEADLESS_EVENTS } else { MAX_EVENTS }));
+        let limits = get_environment_limits(&metadata.environment);
 
         let obj = Arc::new(RoomData {
             is_alive: Arc::new(AtomicBool::new(true)),
+            panicked: AtomicBool::new(false),
             objects: DashMap::new(),
-            metadata: RoomMetadata::new(name.clone().unwrap_or_else(|| Self::generate_room_id(None)), environment.clone().unwrap_or("Default".to_owned()), password, if edit_mode { 60 * 30 } else { 60 * 15 }, 9 * 60 * 60, edit_mode),
+            metadata,
             last_interaction_time: Arc::new(AtomicI64::new(get_timestamp())),
             last_update_run: Arc::new(RwLock::new(SHARED_CLOCK.read(netsblox_vm::runtime::Precision::Medium))),
             last_update_sent: Arc::new(RwLock::new(SHARED_CLOCK.read(netsblox_vm::runtime::Precision::Medium))),
             last_full_update_sent: Arc::new(AtomicI64::new(0)),
-            roomtime: Arc::new(RwLock::new(0.0)),
+            roomtime,
             sim: Arc::new(Simulation::new()),
             robots: Arc::new(DashMap::new()),
             reseters: DashMap::new(),
@@ -119,9 +219,32 @@ impl RoomData {
             next_object_id: Arc::new(AtomicI64::new(0)),
             message_handler: OnceCell::new(),
             vm_manager: OnceCell::new(),
-            clients_manager: clients::ClientsManager::new(),
+            clients_manager: clients::ClientsManager::new(recorder.clone()),
+            recorder,
+            chat: ChatManager::new(),
+            animations: DashMap::new(),
+            reset_requested: AtomicBool::new(false),
+            tick_running: AtomicBool::new(false),
+            tick_timing: TickTiming::default(),
+            scores: DashMap::new(),
+            events,
+            reserved_macs: Mutex::new(reserved_macs),
+            object_ids: DashMap::new(),
+            next_numeric_object_id: AtomicU32::new(0),
+            last_sent_shapes: DashMap::new(),
+            object_change_times: DashMap::new(),
+            last_structure_change: RwLock::new(0.0),
+            limits,
+            idle_warning: AtomicI64::new(i64::MAX),
         });
 
+        // Apply scenario physics settings
+        if let Some(physics) = get_local_scenario(&obj.metadata.environment).and_then(|scenario| scenario.physics.as_ref()) {
+            if let Err(e) = obj.sim.apply_settings(physics) {
+                warn!("Invalid physics settings for environment {}: {}", obj.metadata.environment, e);
+            }
+        }
+
         // Initialize message handler
         obj.message_handler.set(Arc::new(messages::MessageHandler::new(Arc::downgrade(&obj)))).unwrap();
 
@@ -156,21 +279,32 @@ impl RoomData {
     pub fn update(&self) {
         //let now = SHARED_CLOCK.read(netsblox_vm::runtime::Precision::Medium);
         let now = OffsetDateTime::now_utc();
-        
+
+        // Calculate delta time
+        let delta_time = (now - *self.last_update_run.read().unwrap()).as_seconds_f64();
+        let update_fps = config().update_fps;
+        let delta_time = delta_time.clamp(0.5 / update_fps, 2.0 / update_fps);
+
+        self.update_with_delta(delta_time, now);
+    }
+
+    /// Run a single update step of the given length, independent of wall-clock time
+    pub(crate) fn update_with_delta(&self, delta_time: f64, now: OffsetDateTime) {
         if !self.metadata.hibernating.load(Ordering::Relaxed) {
-            // Calculate delta time
-            let delta_time = (now - *self.last_update_run.read().unwrap()).as_seconds_f64();
-            let delta_time = delta_time.clamp(0.5 / UPDATE_FPS, 2.0 / UPDATE_FPS);
-            
             // Check for disconnected clients
             self.clients_manager.remove_disconnected_clients(&self);
 
             // Handle client messages
-            let mut needs_reset = false;
+            let mut needs_reset = self.reset_requested.swap(false, Ordering::Relaxed);
             let mut robot_resets = vec![];
             let msgs = self.clients_manager.get_messages();
 
             for (msg, client_username, client_id) in msgs {
+                // Passwords are left out so they are not saved in plain text
+                if !matches!(msg, ClientMessage::Heartbeat | ClientMessage::KeepAlive | ClientMessage::ChangePassword(_)) {
+                    self.recorder.record(|| RecordedEvent::ClientAction(client_username.clone(), msg.clone()));
+                }
+
                 self.message_handler.get().unwrap().handle_client_message(msg, &mut needs_reset, &mut robot_resets, &client_username, client_id);
             }
 
@@ -187,7 +321,10 @@ impl RoomData {
             // Do updates
             self.update_robots(delta_time);
             self.message_handler.get().unwrap().get_iotscape_messages();
+            self.update_animations(delta_time);
+            let physics_start = Instant::now();
             self.sim.update(delta_time);
+            metrics::PHYSICS_STEP.observe(physics_start.elapsed());
 
             // Check for trigger events, this may need to be optimized in the future, possible switching to event-based
             self.update_triggers();
@@ -229,14 +366,19 @@ impl RoomData {
             }
 
             *self.last_update_run.write().unwrap() = now;
+
+            self.check_recording_length();
         } else {
             // Still do IoTScape handling
             self.message_handler.get().unwrap().get_iotscape_messages();
         }
 
-        // Check if room empty/not empty
-        self.metadata.check_hibernation_state(&self.clients_manager);
-        self.announce(false);
+        // Headless rooms have no clients and are not listed on the API server
+        if !self.metadata.headless {
+            // Check if room empty/not empty
+            self.metadata.check_hibernation_state(&self.clients_manager);
+            self.announce(false);
+        }
     }
     
     fn update_triggers(&self) {
@@ -267,6 +409,7 @@ impl RoomData {
             for other in in_sensor.iter() {
                 // Check if object left sensor
                 if !new_in_sensor.contains(other.key()) {
+                    self.events.log(RoomEventKind::Trigger { trigger: name.clone(), entity: other.key().clone(), entered: false });
                     self.netsblox_msg_tx.send(((name.clone(), ServiceType::Trigger),  "triggerExit".into(), BTreeMap::from([("entity".to_owned(), other.key().clone()),("trigger".to_owned(), name.clone())])))
                         .map_err(|e| error!("Error sending triggerExit message: {:?}", e)).unwrap();
                 }
@@ -275,6 +418,7 @@ impl RoomData {
             for new_other in new_in_sensor.iter() {
                 // Check if new object
                 if !in_sensor.contains(new_other.key()) {
+                    self.events.log(RoomEventKind::Trigger { trigger: name.clone(), entity: new_other.key().clone(), entered: true });
                     self.netsblox_msg_tx.send(((name.clone(), ServiceType::Trigger),  "triggerEnter".into(), BTreeMap::from([("entity".to_owned(), new_other.key().clone()),("trigger".to_owned(), name.clone())])))
                         .map_err(|e| error!("Error sending triggerEnter message: {:?}", e)).unwrap();
                 }
@@ -291,18 +435,30 @@ impl RoomData {
         other_name
     }
 
+    /// Get the name of the entity a collider belongs to, including robot wheels
+    pub(crate) fn get_entity_name_from_collider(&self, c: ColliderHandle) -> Option<String> {
+        let body = self.sim.collider_set.read().unwrap().get(c)?.parent()?;
+
+        if let Some(name) = self.sim.rigid_body_labels.iter().find(|kvp| kvp.value() == &body).map(|kvp| kvp.key().clone()) {
+            return Some(name);
+        }
+
+        self.robots.iter().find(|r| r.value().physics.wheel_bodies.contains(&body)).map(|r| "robot_".to_owned() + r.key())
+    }
+
     pub(crate) fn update_robots(&self, delta_time: f64) {
         let mut any_robot_updated = false;
 
         for mut robot in self.robots.iter_mut() {
-            let (updated, msg) = RobotData::robot_update(robot.value_mut(), self.sim.clone(), &self.clients_manager.sockets, delta_time);
+            let (updated, msg) = RobotData::robot_update(robot.value_mut(), self.sim.clone(), &self.clients_manager.sockets, &self.recorder, delta_time);
     
             any_robot_updated |= updated;
 
             // Check if claimed by user not in room
             if let Some(claimant) = &robot.value().claimed_by {
-                if !self.clients_manager.sockets.contains_key(claimant) {
+                if !self.clients_manager.is_present(claimant) {
                     info!("Robot {} claimed by {} but not in room, unclaiming", robot.key(), claimant);
+                    self.events.log(RoomEventKind::RobotUnclaimed { robot: robot.key().clone(), username: claimant.clone() });
                     robot.value_mut().claimed_by = None;
                     ClientsManager::send_to_clients(&UpdateMessage::RobotClaimed(robot.key().clone(), "".to_owned()), self.clients_manager.sockets.iter().map(|c| c.value().clone().into_iter()).flatten());
                 }
@@ -326,6 +482,66 @@ impl RoomData {
         }
     }
 
+    /// Set velocities of animated kinematic bodies for the next physics step
+    fn update_animations(&self, delta_time: f64) {
+        self.animations.retain(|name, (animation, body_type)| {
+            let Some(handle) = self.sim.rigid_body_labels.get(name).map(|h| *h) else {
+                // Entity was removed
+                return false;
+            };
+
+            if let Some(body) = self.sim.rigid_body_set.write().unwrap().get_mut(handle) {
+                // Finished animations are kept so resetting the room plays them again
+                if !animation.is_finished() {
+                    let velocity = animation.step(*body.translation(), delta_time as f32);
+                    body.set_linvel(velocity, true);
+
+                    if animation.is_finished() {
+                        body.set_linvel(vector![0.0, 0.0, 0.0], true);
+                        body.set_body_type(*body_type, true);
+                    }
+                }
+                true
+            } else {
+                false
+            }
+        });
+    }
+
+    /// Start moving a kinematic entity along a path, replacing any existing animation of it
+    pub(crate) fn start_animation(&self, name: &str, animation: PathAnimation) -> Result<(), String> {
+        if !self.objects.get(name).is_some_and(|o| o.is_kinematic) {
+            return Err(format!("{name} is not a kinematic entity"));
+        }
+
+        let handle = *self.sim.rigid_body_labels.get(name).ok_or(format!("{name} is not a physics entity"))?;
+
+        // Keep the type from before any animation this replaces, which has already changed it
+        let body_type = match self.animations.remove(name) {
+            Some((_, (_, body_type))) => body_type,
+            None => self.sim.rigid_body_set.read().unwrap().get(handle).map(|b| b.body_type()).unwrap_or(RigidBodyType::KinematicPositionBased),
+        };
+
+        if let Some(body) = self.sim.rigid_body_set.write().unwrap().get_mut(handle) {
+            body.set_body_type(RigidBodyType::KinematicVelocityBased, true);
+        }
+
+        self.animations.insert(name.to_owned(), (animation, body_type));
+        Ok(())
+    }
+
+    /// Stop an entity's animation, leaving it where it is
+    pub(crate) fn stop_animation(&self, name: &str) {
+        if let Some((_, (_, body_type))) = self.animations.remove(name) {
+            if let Some(handle) = self.sim.rigid_body_labels.get(name).map(|h| *h) {
+                if let Some(body) = self.sim.rigid_body_set.write().unwrap().get_mut(handle) {
+                    body.set_linvel(vector![0.0, 0.0, 0.0], true);
+                    body.set_body_type(body_type, true);
+                }
+            }
+        }
+    }
+
     /// Reset entire room
     pub(crate) fn reset(&self){
         info!("Resetting room {}", self.metadata.name);
@@ -339,6 +555,16 @@ impl RoomData {
             resetter.value_mut().reset(self.sim.clone());
         }
 
+        // Animations start over from their first waypoint, including those that had finished
+        for mut animation in self.animations.iter_mut() {
+            animation.value_mut().0.restart();
+            if let Some(handle) = self.sim.rigid_body_labels.get(animation.key()).map(|h| *h) {
+                if let Some(body) = self.sim.rigid_body_set.write().unwrap().get_mut(handle) {
+                    body.set_body_type(RigidBodyType::KinematicVelocityBased, true);
+                }
+            }
+        }
+
         // Send
         let world_service = self.services.iter().find(|s| s.key().1 == ServiceType::World);
         if let Some(world_service) = world_service {
@@ -361,6 +587,14 @@ impl RoomData {
 
     /// Test if a client is allowed to interact with a robot (for encrypt, reset)
     pub(crate) fn is_authorized(&self, client: u128, robot_id: &str) -> bool {
+        // Spectators cannot interact with robots
+        if let Some(username) = self.clients_manager.sockets.iter().find(|c| c.value().contains(&client)).map(|c| c.key().to_owned()) {
+            if self.metadata.get_role(&username) < RoomRole::Collaborator {
+                info!("Client {} is a spectator, no robot actions allowed", username);
+                return false;
+            }
+        }
+
         let robot = self.robots.get(robot_id);
         // Require robot to exist first
         if let Some(robot) = robot {
@@ -400,7 +634,15 @@ impl RoomData {
     }
 
     pub(crate) fn remove(&self, id: &String) {
-        self.objects.remove(id);
+        if self.objects.remove(id).is_some() {
+            self.events.log(RoomEventKind::EntityRemoved { name: id.clone() });
+        }
+        if let Some((_, numeric_id)) = self.object_ids.remove(id) {
+            self.last_sent_shapes.remove(&numeric_id);
+        }
+        self.object_change_times.remove(id);
+        self.mark_structure_changed();
+        self.animations.remove(id);
 
         if self.sim.rigid_body_labels.contains_key(id) {
             let handle = *self.sim.rigid_body_labels.get(id).unwrap();
@@ -418,7 +660,13 @@ impl RoomData {
 
     pub(crate) fn remove_all(&self) {
         info!("Removing all entities from {}", self.metadata.name);
+        self.events.log(RoomEventKind::AllEntitiesRemoved);
         self.objects.clear();
+        self.object_ids.clear();
+        self.last_sent_shapes.clear();
+        self.object_change_times.clear();
+        self.mark_structure_changed();
+        self.animations.clear();
 
         // Remove non-world services
         self.services.retain(|k, _| k.1 == ServiceType::World);
@@ -440,6 +688,19 @@ impl RoomData {
         info!("All entities removed from {}", self.metadata.name);
     }
 
+    /// Add an object to be sent to clients, giving it a numeric ID for compact updates
+    pub(crate) fn insert_object(&self, name: String, object: ObjectData) {
+        self.object_ids.entry(name.clone()).or_insert_with(|| self.next_numeric_object_id.fetch_add(1, Ordering::Relaxed));
+        if self.objects.insert(name.clone(), object).is_none() {
+            self.mark_structure_changed();
+            self.events.log(RoomEventKind::EntityAdded { name });
+        }
+    }
+
+    fn mark_structure_changed(&self) {
+        *self.last_structure_change.write().unwrap() = *self.roomtime.read().unwrap();
+    }
+
     pub(crate) fn count_non_robots(&self) -> usize {
         (self.objects.len() - self.robots.len()).clamp(0, self.objects.len())
     }
@@ -452,6 +713,40 @@ impl RoomData {
         self.objects.iter().filter(|o| !o.value().is_kinematic).count() - self.robots.len()
     }
 
+    /// Count entities without colliders, which are also counted as kinematic
+    pub(crate) fn count_visual_only(&self) -> usize {
+        let rigid_body_set = self.sim.rigid_body_set.read().unwrap();
+        self.objects.iter().filter(|o| o.value().is_kinematic).filter(|o| {
+            self.sim.rigid_body_labels.get(o.key()).and_then(|handle| rigid_body_set.get(*handle)).is_some_and(|body| body.colliders().is_empty())
+        }).count()
+    }
+
+    /// Check that another robot can be added, with the limit reached as the error
+    pub(crate) fn check_robot_limit(&self) -> Result<(), String> {
+        if self.robots.len() >= self.limits.robots {
+            return Err(format!("Robot limit of {} reached", self.limits.robots));
+        }
+
+        Ok(())
+    }
+
+    /// Check that another entity can be added, with the limit reached as the error. Triggers count as kinematic.
+    pub(crate) fn check_entity_limit(&self, kinematic: bool, visual_only: bool) -> Result<(), String> {
+        let (count, limit, kind) = if visual_only {
+            (self.count_visual_only(), self.limits.visual_only_entities, "Visual only entity")
+        } else if kinematic {
+            (self.count_kinematic() - self.count_visual_only(), self.limits.kinematic_entities, "Kinematic entity")
+        } else {
+            (self.count_dynamic(), self.limits.dynamic_entities, "Dynamic entity")
+        };
+
+        if count >= limit {
+            return Err(format!("{kind} limit of {limit} reached"));
+        }
+
+        Ok(())
+    }
+
     pub fn announce(&self, force: bool) {
         // Throttle announcements to at most once every 30 seconds (unless forced)
         const ANNOUNCE_INTERVAL_SECS: i64 = 30;
@@ -469,9 +764,7 @@ impl RoomData {
             ).is_ok() {
                 let room_info = self.metadata.get_room_info();
                 tokio::task::spawn(async move {
-                    let response = REQWEST_CLIENT.put(format!("{}/server/rooms", get_main_api_server()))
-                    .json(&vec![room_info])
-                    .send().await;
+                    let response = send_to_main_api(reqwest::Method::PUT, "/server/rooms", &vec![room_info]).await;
                     
                     if let Err(e) = response {
                         error!("Error sending room info to API: {e:?}");
@@ -482,10 +775,13 @@ impl RoomData {
     }
 
     pub fn launch(room: Arc<RoomData>) {
-        let mut interval = time::interval(Duration::from_millis((1000.0 / UPDATE_FPS) as u64));
+        let mut interval = time::interval(Duration::from_secs_f64(1.0 / config().update_fps));
     
         interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
+        // Updates spawn tasks (announcements, IoTScape responses), so the pool threads need the runtime
+        let runtime = tokio::runtime::Handle::current();
+
         let m = room.clone();
         tokio::task::spawn(async move {
             loop {
@@ -494,26 +790,98 @@ impl RoomData {
                 if !m.is_alive.load(Ordering::Relaxed) {
                     break;
                 }
-        
-                let update_time = get_timestamp();
-
-                //trace!("Updating room {}", &m.name);
-                if !m.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed) {
-                    // Check timeout
-                    if update_time - m.last_interaction_time.load(Ordering::Relaxed) > m.metadata.hibernate_timeout {
-                        m.metadata.hibernating.store(true, Ordering::Relaxed);
-                        m.metadata.hibernating_since.store(get_timestamp(), Ordering::Relaxed);
-
-                        // Kick all users out
-                        m.clients_manager.send_to_all_clients(&roboscapesim_common::UpdateMessage::Hibernating);
-                        m.clients_manager.sockets.clear();
-                        info!("{} is now hibernating", &m.metadata.name);
-                    }
+
+                // Skip this tick if the previous one is still running
+                if m.tick_running.swap(true, Ordering::AcqRel) {
+                    m.tick_timing.record_skipped();
+                    continue;
                 }
-                m.update();
+
+                let room = m.clone();
+                let runtime = runtime.clone();
+                ROOM_UPDATE_POOL.spawn(move || {
+                    let _guard = runtime.enter();
+                    let _running = TickRunningGuard(&room);
+                    let start = Instant::now();
+
+                    // A panic in one room stops that room instead of taking down the pool
+                    if std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| room.tick())).is_err() {
+                        error!("Room {} panicked while updating and was stopped", room.metadata.name);
+                        room.panicked.store(true, Ordering::Relaxed);
+                        room.is_alive.store(false, Ordering::Relaxed);
+                        return;
+                    }
+
+                    room.tick_timing.record(start.elapsed());
+                });
             }
         });
     }
+
+    /// Check for hibernation timeout, then update the room
+    fn tick(&self) {
+        let update_time = get_timestamp();
+
+        //trace!("Updating room {}", &self.name);
+        if !self.metadata.hibernating.load(std::sync::atomic::Ordering::Relaxed) {
+            // Check timeout
+            if update_time - self.last_interaction_time.load(Ordering::Relaxed) > self.metadata.hibernate_timeout {
+                self.hibernate();
+            } else {
+                self.update_idle_warning(update_time);
+            }
+        }
+        self.update();
+    }
+
+    /// Warn clients as the room gets close to hibernating, and clear the warning after an interaction
+    pub(crate) fn update_idle_warning(&self, now: i64) {
+        let remaining = self.idle_time_remaining(now);
+        let warned = self.idle_warning.load(Ordering::Relaxed);
+
+        match IDLE_WARNING_SECS.iter().copied().filter(|secs| remaining <= *secs).min() {
+            Some(threshold) if threshold < warned => {
+                self.idle_warning.store(threshold, Ordering::Relaxed);
+                self.clients_manager.send_to_all_clients(&UpdateMessage::IdleWarning(remaining.max(0) as f64));
+            },
+            None if warned != i64::MAX => {
+                self.idle_warning.store(i64::MAX, Ordering::Relaxed);
+                self.clients_manager.send_to_all_clients(&UpdateMessage::IdleWarningCleared);
+            },
+            _ => {}
+        }
+    }
+
+    /// The idle warning clients have been sent, with the time left updated, if there is one
+    pub(crate) fn current_idle_warning(&self, now: i64) -> Option<UpdateMessage> {
+        (self.idle_warning.load(Ordering::Relaxed) != i64::MAX).then(|| UpdateMessage::IdleWarning(self.idle_time_remaining(now).max(0) as f64))
+    }
+
+    /// Seconds until the room hibernates without interaction
+    fn idle_time_remaining(&self, now: i64) -> i64 {
+        self.metadata.hibernate_timeout - (now - self.last_interaction_time.load(Ordering::Relaxed))
+    }
+
+    /// Put the room into hibernation, sending everyone in it out
+    pub(crate) fn hibernate(&self) {
+        self.metadata.hibernating.store(true, Ordering::Relaxed);
+        self.metadata.hibernating_since.store(get_timestamp(), Ordering::Relaxed);
+        self.idle_warning.store(i64::MAX, Ordering::Relaxed);
+
+        // Kick all users out
+        self.clients_manager.send_to_all_clients(&roboscapesim_common::UpdateMessage::Hibernating);
+        self.clients_manager.sockets.clear();
+        info!("{} is now hibernating", &self.metadata.name);
+    }
+
+    /// Stop the room for good, telling everyone in it why, it should then be removed from `ROOMS`
+    pub(crate) fn close(&self, reason: &str) {
+        self.stop_recording();
+        self.clients_manager.send_to_all_clients(&UpdateMessage::FatalError(reason.to_owned()));
+        self.clients_manager.sockets.clear();
+        self.is_alive.store(false, Ordering::Relaxed);
+        info!("{} was closed: {}", &self.metadata.name, reason);
+    }
 }
 
 fn setup_vm(iotscape_tx: &mpsc::Sender<(iotscape::Request, Option<netsblox_vm::std_util::AsyncKey<Result<SimpleValue, netsblox_vm::compact_str::CompactString>>>)>, obj: &Arc<RoomData>) {
@@ -592,7 +960,9 @@ async fn setup_world_service(obj: &Arc<RoomData>) {
 
     let service = Arc::new(WorldService::create(obj.metadata.name.as_str()).await);
     let service_id = service.get_service_info().id.clone();
-    service.get_service_info().service.announce().await.unwrap();
+    if !obj.metadata.headless {
+        service.get_service_info().service.announce().await.unwrap();
+    }
     obj.services.insert((service_id, ServiceType::World), service);
 }
 
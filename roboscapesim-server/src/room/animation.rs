@@ -0,0 +1,172 @@
+use nalgebra::Vector3;
+
+/// Distance at which a waypoint is considered reached
+const WAYPOINT_TOLERANCE: f32 = 0.001;
+
+/// What a path animation does after reaching its last waypoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationMode {
+    /// Stop at the last waypoint
+    Once,
+    /// Return to the first waypoint and repeat
+    Loop,
+    /// Reverse direction and repeat
+    PingPong,
+}
+
+impl AnimationMode {
+    pub fn parse(mode: &str) -> Option<AnimationMode> {
+        match mode.to_lowercase().as_str() {
+            "" | "once" => Some(AnimationMode::Once),
+            "loop" => Some(AnimationMode::Loop),
+            "pingpong" | "ping-pong" | "ping pong" => Some(AnimationMode::PingPong),
+            _ => None,
+        }
+    }
+}
+
+/// Moves a kinematic body along a list of waypoints by setting its velocity each tick
+#[derive(Debug, Clone)]
+pub struct PathAnimation {
+    waypoints: Vec<Vector3<f32>>,
+    /// Speed used when moving towards each waypoint
+    speeds: Vec<f32>,
+    mode: AnimationMode,
+    /// Index of waypoint currently being moved towards
+    target: usize,
+    /// Direction through the waypoints for ping-pong mode
+    forward: bool,
+    finished: bool,
+}
+
+impl PathAnimation {
+    /// Create a new animation, speeds can be a single value for all waypoints or one per waypoint
+    pub fn new(waypoints: Vec<Vector3<f32>>, speeds: Vec<f32>, mode: AnimationMode) -> Result<PathAnimation, String> {
+        if waypoints.is_empty() {
+            return Err("Animation needs at least one waypoint".to_owned());
+        }
+
+        if waypoints.iter().any(|w| !w.iter().all(|c| c.is_finite())) {
+            return Err("Waypoints must be finite".to_owned());
+        }
+
+        let speeds = match speeds.len() {
+            1 => vec![speeds[0]; waypoints.len()],
+            n if n == waypoints.len() => speeds,
+            _ => return Err("Speed must be a single number or one number per waypoint".to_owned()),
+        };
+
+        if speeds.iter().any(|s| !s.is_finite() || *s <= 0.0) {
+            return Err("Speeds must be positive".to_owned());
+        }
+
+        Ok(PathAnimation { waypoints, speeds, mode, target: 0, forward: true, finished: false })
+    }
+
+    /// Start again from the first waypoint
+    pub fn restart(&mut self) {
+        self.target = 0;
+        self.forward = true;
+        self.finished = false;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// Get the velocity to move the body at this tick, advancing to the next waypoint when the current one is reached
+    pub fn step(&mut self, position: Vector3<f32>, delta_time: f32) -> Vector3<f32> {
+        // Bounded in case all waypoints are at the current position
+        for _ in 0..=self.waypoints.len() {
+            if self.finished {
+                break;
+            }
+
+            let offset = self.waypoints[self.target] - position;
+            let distance = offset.norm();
+
+            if distance > WAYPOINT_TOLERANCE {
+                // Slow down to not overshoot the waypoint
+                let speed = self.speeds[self.target].min(distance / delta_time.max(f32::EPSILON));
+                return offset / distance * speed;
+            }
+
+            self.advance();
+        }
+
+        Vector3::zeros()
+    }
+
+    fn advance(&mut self) {
+        let last = self.waypoints.len() - 1;
+
+        match self.mode {
+            AnimationMode::Once => {
+                if self.target < last {
+                    self.target += 1;
+                } else {
+                    self.finished = true;
+                }
+            },
+            AnimationMode::Loop => {
+                self.target = if self.target < last { self.target + 1 } else { 0 };
+            },
+            AnimationMode::PingPong => {
+                if last == 0 {
+                    self.finished = true;
+                } else if self.forward {
+                    if self.target < last {
+                        self.target += 1;
+                    } else {
+                        self.forward = false;
+                        self.target -= 1;
+                    }
+                } else if self.target > 0 {
+                    self.target -= 1;
+                } else {
+                    self.forward = true;
+                    self.target += 1;
+                }
+            },
+        }
+    }
+}
+
+#[test]
+fn test_path_animation_modes() {
+    use nalgebra::vector;
+
+    let waypoints = vec![vector![0.0, 0.0, 0.0], vector![1.0, 0.0, 0.0], vector![2.0, 0.0, 0.0]];
+    let forward = vector![1.0, 0.0, 0.0];
+    let backward = vector![-1.0, 0.0, 0.0];
+
+    let mut once = PathAnimation::new(waypoints.clone(), vec![1.0], AnimationMode::Once).unwrap();
+    assert_eq!(once.step(waypoints[0], 0.1), forward);
+    assert_eq!(once.step(waypoints[1], 0.1), forward);
+    assert_eq!(once.step(waypoints[2], 0.1), Vector3::zeros());
+    assert!(once.is_finished());
+
+    let mut looping = PathAnimation::new(waypoints.clone(), vec![1.0], AnimationMode::Loop).unwrap();
+    looping.step(waypoints[0], 0.1);
+    looping.step(waypoints[1], 0.1);
+    assert_eq!(looping.step(waypoints[2], 0.1), backward);
+    assert_eq!(looping.step(waypoints[0], 0.1), forward);
+
+    let mut pingpong = PathAnimation::new(waypoints.clone(), vec![1.0], AnimationMode::PingPong).unwrap();
+    pingpong.step(waypoints[0], 0.1);
+    pingpong.step(waypoints[1], 0.1);
+    assert_eq!(pingpong.step(waypoints[2], 0.1), backward);
+    assert_eq!(pingpong.step(waypoints[1], 0.1), backward);
+    assert_eq!(pingpong.step(waypoints[0], 0.1), forward);
+    assert!(!pingpong.is_finished());
+}
+
+#[test]
+fn test_path_animation_no_overshoot() {
+    use nalgebra::vector;
+
+    let mut animation = PathAnimation::new(vec![vector![1.0, 0.0, 0.0]], vec![10.0], AnimationMode::Once).unwrap();
+    assert_eq!(animation.step(vector![0.5, 0.0, 0.0], 0.1), vector![5.0, 0.0, 0.0]);
+    assert!(PathAnimation::new(vec![], vec![1.0], AnimationMode::Once).is_err());
+    assert!(PathAnimation::new(vec![vector![1.0, 0.0, 0.0]], vec![1.0, 2.0], AnimationMode::Once).is_err());
+}
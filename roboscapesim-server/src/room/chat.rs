@@ -0,0 +1,103 @@
+use log::debug;
+use nalgebra::Point3;
+use roboscapesim_common::MAX_CHAT_LENGTH;
+
+use super::*;
+
+/// Number of chat messages and annotations a user can send within `RATE_LIMIT_PERIOD`
+const RATE_LIMIT_MESSAGES: usize = 5;
+
+/// Period for chat rate limiting
+const RATE_LIMIT_PERIOD: Duration = Duration::from_secs(10);
+
+/// Number of chat messages kept for users joining later
+const HISTORY_LENGTH: usize = 100;
+
+/// Handles chat messages and annotations sent by users in a room
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct ChatManager {
+    /// Recent chat messages (username, text)
+    #[derivative(Debug = "ignore")]
+    history: Mutex<VecDeque<(String, String)>>,
+    /// Times of each user's recent messages
+    recent_messages: DashMap<String, VecDeque<Instant>>,
+}
+
+impl ChatManager {
+    pub fn new() -> Self {
+        ChatManager {
+            history: Mutex::new(VecDeque::new()),
+            recent_messages: DashMap::new(),
+        }
+    }
+
+    /// Send a chat message from a user to everyone in the room
+    pub fn handle_chat(&self, room: &RoomData, username: &str, client_id: u128, text: String) {
+        let text = text.trim();
+
+        if text.is_empty() {
+            return;
+        }
+
+        if text.chars().count() > MAX_CHAT_LENGTH {
+            ClientsManager::send_to_client(&UpdateMessage::NonFatalError(format!("Chat messages cannot be longer than {MAX_CHAT_LENGTH} characters")), client_id);
+            return;
+        }
+
+        if !self.check_rate_limit(username, client_id) {
+            return;
+        }
+
+        debug!("Chat in {} from {}", room.metadata.name, username);
+        room.events.log(RoomEventKind::Chat { username: username.to_owned() });
+
+        {
+            let mut history = self.history.lock().unwrap();
+            history.push_back((username.to_owned(), text.to_owned()));
+            if history.len() > HISTORY_LENGTH {
+                history.pop_front();
+            }
+        }
+
+        room.clients_manager.send_to_all_clients(&UpdateMessage::Chat(username.to_owned(), text.to_owned()));
+    }
+
+    /// Show other users a position a user pointed at
+    pub fn handle_annotation(&self, room: &RoomData, username: &str, client_id: u128, position: Point3<f32>) {
+        if !(position.x.is_finite() && position.y.is_finite() && position.z.is_finite()) {
+            return;
+        }
+
+        if !self.check_rate_limit(username, client_id) {
+            return;
+        }
+
+        room.clients_manager.send_to_all_clients(&UpdateMessage::Annotation(username.to_owned(), position));
+    }
+
+    /// Send recent chat messages to a newly joined client
+    pub fn send_history_to_client(&self, client_id: u128) {
+        for (username, text) in self.history.lock().unwrap().iter() {
+            ClientsManager::send_to_client(&UpdateMessage::Chat(username.clone(), text.clone()), client_id);
+        }
+    }
+
+    /// Record a message from a user, returning false and telling them if they are sending too quickly
+    fn check_rate_limit(&self, username: &str, client_id: u128) -> bool {
+        let now = Instant::now();
+        let mut recent = self.recent_messages.entry(username.to_owned()).or_default();
+
+        while recent.front().is_some_and(|t| now.duration_since(*t) > RATE_LIMIT_PERIOD) {
+            recent.pop_front();
+        }
+
+        if recent.len() >= RATE_LIMIT_MESSAGES {
+            ClientsManager::send_to_client(&UpdateMessage::NonFatalError("You are sending messages too quickly".to_owned()), client_id);
+            return false;
+        }
+
+        recent.push_back(now);
+        true
+    }
+}
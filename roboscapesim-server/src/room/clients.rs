@@ -1,19 +1,87 @@
+use roboscapesim_common::compact::CompactObjectUpdate;
+use sha2::{Digest, Sha256};
+
+use crate::util::util::bytes_to_hex_string;
+
 use super::*;
 
-#[derive(Debug)]
+/// Seconds a disconnected user keeps their claims and can resume their session
+pub const RESUME_GRACE_PERIOD: i64 = 120;
+
+#[derive(Derivative)]
+#[derivative(Debug)]
 pub struct ClientsManager {
     pub(crate) sockets: DashMap<String, DashSet<u128>>,
+    /// Hash of the latest resume token issued to each user, see `hash_token`
+    #[derivative(Debug = "ignore")]
+    resume_tokens: DashMap<String, String>,
+    /// Users with no connected clients who can still resume, with the time their last client disconnected
+    pub(crate) disconnected_users: DashMap<String, i64>,
+    /// Recorder of the room, messages sent to everyone are part of its recordings
+    #[derivative(Debug = "ignore")]
+    recorder: Arc<Recorder>,
 }
 
 impl ClientsManager {
-    pub fn new() -> Self {
+    pub fn new(recorder: Arc<Recorder>) -> Self {
         ClientsManager {
             sockets: DashMap::new(),
+            resume_tokens: DashMap::new(),
+            disconnected_users: DashMap::new(),
+            recorder,
+        }
+    }
+
+    /// Test if a user is in the room, including users waiting to resume a dropped connection
+    pub fn is_present(&self, username: &str) -> bool {
+        self.sockets.contains_key(username) || self.disconnected_users.contains_key(username)
+    }
+
+    /// Add a client for a user, cancelling any pending removal
+    pub fn add_client(&self, username: &str, client_id: u128) {
+        self.sockets.entry(username.to_owned()).or_default().insert(client_id);
+        self.disconnected_users.remove(username);
+    }
+
+    /// Create a token a user's client can later use to resume its session, replacing any token issued to the user before
+    pub fn issue_resume_token(&self, username: &str) -> String {
+        let token: String = rand::rng()
+            .sample_iter(&UpperHexadecimal)
+            .take(32)
+            .map(char::from)
+            .collect();
+        self.resume_tokens.insert(username.to_owned(), hash_token(&token));
+        token
+    }
+
+    /// Get the user a resume token was issued to, if it is their latest token and they are connected or disconnected
+    /// less than `RESUME_GRACE_PERIOD` ago
+    pub fn get_resume_user(&self, token: &str) -> Option<String> {
+        let hash = hash_token(token);
+        let username = self.resume_tokens.iter().find(|t| *t.value() == hash).map(|t| t.key().clone())?;
+
+        let active = self.sockets.contains_key(&username) || self.disconnected_users.get(&username).is_some_and(|t| get_timestamp() - *t.value() < RESUME_GRACE_PERIOD);
+        active.then_some(username)
+    }
+
+    /// Users with the hashes of their resume tokens
+    pub(crate) fn resume_token_hashes(&self) -> Vec<(String, String)> {
+        self.resume_tokens.iter().map(|t| (t.key().clone(), t.value().clone())).collect()
+    }
+
+    /// Accept resume tokens issued before a restart, treating their users as disconnected so they keep their claims for a while
+    pub(crate) fn restore_resume_token_hashes(&self, hashes: impl IntoIterator<Item = (String, String)>) {
+        let now = get_timestamp();
+        for (username, hash) in hashes {
+            self.disconnected_users.insert(username.clone(), now);
+            self.resume_tokens.insert(username, hash);
         }
     }
 
     /// Send an UpdateMessage to all clients in the room
     pub fn send_to_all_clients(&self, msg: &UpdateMessage) {
+        self.recorder.record(|| RecordedEvent::Update(msg.clone()));
+
         for client in &self.sockets {
             for client_id in client.iter() {
                 Self::send_to_client(
@@ -62,6 +130,10 @@ impl ClientsManager {
     /// Send the room's current state data to a specific client
     pub fn send_state_to_client(&self, room: &RoomData, full_update: bool, client: u128) {
         if full_update {
+            if Self::uses_compact_updates(client) {
+                Self::send_to_client(&Self::object_ids_message(room), client);
+            }
+
             Self::send_to_client(
                 &UpdateMessage::Update(room.roomtime.read().unwrap().clone(), true, room.objects.iter().map(|kvp| (kvp.key().to_owned(), kvp.value().to_owned())).collect()),
                 client,
@@ -87,36 +159,117 @@ impl ClientsManager {
     }
 
 
-    /// Send the room's current state data to all clients
-    pub fn send_state_to_all_clients(&self, room: &RoomData, full_update: bool) {
-        let update_msg: UpdateMessage;
-        if full_update {
-            update_msg = UpdateMessage::Update(room.roomtime.read().unwrap().clone(), true, room.objects.iter().map(|kvp| (kvp.key().to_owned(), kvp.value().to_owned())).collect());
-        } else {
-            update_msg = UpdateMessage::Update(
-                room.roomtime.read().unwrap().clone(),
+    /// Send a resuming client the changes since the roomtime it last received, or everything if objects were added or removed since then
+    pub fn send_changes_since_to_client(&self, room: &RoomData, since: f64, client: u128) {
+        let roomtime = *room.roomtime.read().unwrap();
+
+        if since > roomtime || *room.last_structure_change.read().unwrap() >= since {
+            // Client may have missed removals, so start over
+            Self::send_to_client(&UpdateMessage::RemoveAll(), client);
+            self.send_state_to_client(room, true, client);
+            return;
+        }
+
+        Self::send_to_client(
+            &UpdateMessage::Update(
+                roomtime,
                 false,
                 room.objects
                     .iter()
-                    .filter(|mvp| mvp.value().updated)
+                    .filter(|mvp| room.object_change_times.get(mvp.key()).is_some_and(|t| *t > since))
                     .map(|mvp| {
                         let mut val = mvp.value().clone();
                         val.visual_info = None;
                         (mvp.key().clone(), val)
                     })
                     .collect::<HashMap<String, ObjectData>>(),
-            );
-        }
-
-        self.send_to_all_clients(
-            &update_msg
+            ),
+            client,
         );
+    }
+
+    /// Send the room's current state data to all clients
+    pub fn send_state_to_all_clients(&self, room: &RoomData, full_update: bool) {
+        let roomtime = *room.roomtime.read().unwrap();
+        let (compact_clients, other_clients): (Vec<u128>, Vec<u128>) = self.sockets.iter()
+            .flat_map(|c| c.value().clone().into_iter())
+            .partition(|c| Self::uses_compact_updates(*c));
+
+        if full_update {
+            // Compact clients need IDs for any new objects before later compact updates
+            if !compact_clients.is_empty() {
+                Self::send_to_clients(&Self::object_ids_message(room), compact_clients.iter().copied());
+            }
+
+            let update_msg = UpdateMessage::Update(roomtime, true, room.objects.iter().map(|kvp| (kvp.key().to_owned(), kvp.value().to_owned())).collect());
+            self.recorder.record(|| RecordedEvent::Update(update_msg.clone()));
+            Self::send_to_clients(&update_msg, compact_clients.into_iter().chain(other_clients));
+        } else {
+            // Recordings always use regular updates
+            if !other_clients.is_empty() || self.recorder.is_recording() {
+                let update_msg = UpdateMessage::Update(
+                    roomtime,
+                    false,
+                    room.objects
+                        .iter()
+                        .filter(|mvp| mvp.value().updated)
+                        .map(|mvp| {
+                            let mut val = mvp.value().clone();
+                            val.visual_info = None;
+                            (mvp.key().clone(), val)
+                        })
+                        .collect::<HashMap<String, ObjectData>>(),
+                );
+                self.recorder.record(|| RecordedEvent::Update(update_msg.clone()));
+                Self::send_to_clients(&update_msg, other_clients.into_iter());
+            }
+
+            if !compact_clients.is_empty() {
+                Self::send_to_clients(&Self::compact_update_message(room, roomtime), compact_clients.into_iter());
+            }
+        }
 
         for mut obj in room.objects.iter_mut() {
+            // Remember what was sent so unchanged values can be left out of compact updates
+            if full_update || obj.value().updated {
+                if let Some(id) = room.object_ids.get(obj.key()) {
+                    room.last_sent_shapes.insert(*id, (obj.value().transform.scaling, obj.value().is_kinematic));
+                }
+            }
+
+            if obj.value().updated {
+                room.object_change_times.insert(obj.key().clone(), roomtime);
+            }
+
             obj.value_mut().updated = false;
         }
     }
 
+    /// Test if a client asked for compact updates
+    fn uses_compact_updates(client_id: u128) -> bool {
+        CLIENTS.get(&client_id).is_some_and(|c| c.compact_updates.load(Ordering::Relaxed))
+    }
+
+    /// Message with the numeric IDs of all objects in the room
+    fn object_ids_message(room: &RoomData) -> UpdateMessage {
+        UpdateMessage::ObjectIds(room.object_ids.iter().map(|kvp| (kvp.key().clone(), *kvp.value())).collect())
+    }
+
+    /// Compact incremental update of objects changed since the last update
+    fn compact_update_message(room: &RoomData, roomtime: f64) -> UpdateMessage {
+        UpdateMessage::CompactUpdate(
+            roomtime,
+            room.objects
+                .iter()
+                .filter(|o| o.value().updated)
+                .filter_map(|o| {
+                    let id = *room.object_ids.get(o.key())?;
+                    Some(CompactObjectUpdate::new(id, o.value(), room.last_sent_shapes.get(&id).map(|s| *s)))
+                })
+                .collect(),
+        )
+    }
+
     /// Get all messages from all clients
     pub fn get_messages(&self) -> Vec<(ClientMessage, String, u128)> {
         let mut msgs = vec![];
@@ -136,6 +289,24 @@ impl ClientsManager {
         msgs
     }
 
+    /// Remove all of a user's clients from the room, telling them why
+    pub fn kick_user(&self, room: &RoomData, username: &str, reason: &str) {
+        self.resume_tokens.remove(username);
+        self.disconnected_users.remove(username);
+
+        if let Some((_, client_ids)) = self.sockets.remove(username) {
+            Self::send_to_clients(&UpdateMessage::FatalError(reason.to_owned()), client_ids.into_iter());
+            Self::send_user_left(room, username);
+        }
+    }
+
+    /// Send the userLeft event to the room's world service
+    fn send_user_left(room: &RoomData, username: &str) {
+        room.events.log(RoomEventKind::UserLeft { username: username.to_owned() });
+        let world_service_id = room.services.iter().find(|s| s.key().1 == ServiceType::World).unwrap().value().get_service_info().id.clone();
+        room.netsblox_msg_tx.send(((world_service_id, ServiceType::World), "userLeft".to_string(), BTreeMap::from([("username".to_owned(), username.to_owned())]))).unwrap();
+    }
+
     /// Clean up disconnected clients
     pub fn remove_disconnected_clients(&self, room: &RoomData) {
         let mut disconnected = vec![];
@@ -152,14 +323,51 @@ impl ClientsManager {
             info!("Removing client {} from room {}", client_id, &room.metadata.name);
             self.sockets.get(&username).and_then(|c| c.value().remove(&client_id));
     
-            if self.sockets.get(&username).unwrap().value().is_empty() {
+            if self.sockets.get(&username).is_some_and(|c| c.value().is_empty()) {
+                // Keep user's claims for a while in case they reconnect
                 self.sockets.remove(&username);
+                self.disconnected_users.insert(username, get_timestamp());
             }
-    
+        }
+
+        // Users who did not come back in time have left
+        let now = get_timestamp();
+        let expired: Vec<String> = self.disconnected_users.iter()
+            .filter(|u| now - *u.value() >= RESUME_GRACE_PERIOD)
+            .map(|u| u.key().clone())
+            .collect();
+
+        for username in expired {
+            info!("User {} did not resume session in room {}", username, &room.metadata.name);
+            self.disconnected_users.remove(&username);
+            self.resume_tokens.remove(&username);
+
             // Send leave message to clients
-            // TODO: handle multiple clients from one username better?
-            let world_service_id = room.services.iter().find(|s| s.key().1 == ServiceType::World).unwrap().value().get_service_info().id.clone();
-            room.netsblox_msg_tx.send(((world_service_id, ServiceType::World), "userLeft".to_string(), BTreeMap::from([("username".to_owned(), username.to_owned())]))).unwrap();
+            Self::send_user_left(room, &username);
         }
     }
-}
\ No newline at end of file
+}
+
+/// Hash a resume or owner token so saved rooms do not hold tokens that could be used directly,
+/// the tokens are random so they need no salt or slow hash
+pub(crate) fn hash_token(token: &str) -> String {
+    bytes_to_hex_string(&Sha256::digest(token))
+}
+
+#[test]
+fn test_resume_tokens() {
+    let clients_manager = ClientsManager::new(Arc::new(Recorder::new(Arc::new(RwLock::new(0.0)))));
+    clients_manager.add_client("user", 1);
+
+    // Only the latest token is accepted
+    let old_token = clients_manager.issue_resume_token("user");
+    let token = clients_manager.issue_resume_token("user");
+    assert_eq!(clients_manager.get_resume_user(&old_token), None);
+    assert_eq!(clients_manager.get_resume_user(&token), Some("user".to_owned()));
+    assert!(clients_manager.resume_token_hashes().iter().all(|(_, hash)| *hash != token));
+
+    // Tokens stop working once the grace period has passed
+    clients_manager.sockets.clear();
+    clients_manager.disconnected_users.insert("user".to_owned(), get_timestamp() - RESUME_GRACE_PERIOD);
+    assert_eq!(clients_manager.get_resume_user(&token), None);
+}
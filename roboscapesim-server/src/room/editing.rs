@@ -0,0 +1,163 @@
+use nalgebra::Isometry3;
+use rapier3d::geometry::SharedShape;
+use serde_json::Value;
+
+use crate::robot::physics::RobotPhysics;
+use crate::services::world::consts::MAX_COORD;
+
+use super::*;
+
+impl RoomData {
+    /// Move, rotate and resize an entity placed with the edit mode gizmo
+    pub(crate) fn set_entity_transform(&self, name: &str, transform: Transform) -> Result<(), String> {
+        let position = transform.position.coords.map(|c| c.clamp(-MAX_COORD, MAX_COORD));
+        let rotation = transform.rotation.to_quaternion();
+
+        if name.starts_with("wheel_") {
+            return Err("Robot wheels cannot be moved on their own".to_owned());
+        }
+
+        // Robots keep their size, only their pose can change
+        if let Some(robot_id) = name.strip_prefix("robot_") {
+            let mut robot = self.robots.get_mut(robot_id).ok_or_else(|| format!("Robot {robot_id} not found"))?;
+            RobotPhysics::update_transform(&mut robot, self.sim.clone(), Some(position), Some(Orientation::Quaternion(*rotation.quaternion())), true);
+            return Ok(());
+        }
+
+        let handle = self.sim.rigid_body_labels.get(name).map(|h| *h).ok_or_else(|| format!("Entity {name} not found"))?;
+        let mut obj = self.objects.get_mut(name).ok_or_else(|| format!("Entity {name} not found"))?;
+
+        // Keep size consistent with how add_shape treats each shape
+        let shape = match obj.visual_info {
+            Some(VisualInfo::Color(_, _, _, s)) | Some(VisualInfo::Texture(_, _, _, s)) => s,
+            _ => Shape::Box,
+        };
+        let mut size = transform.scaling.map(|s| s.clamp(0.05, 1000.0));
+        match shape {
+            Shape::Box => {},
+            Shape::Sphere => {
+                size.y = size.x;
+                size.z = size.x;
+            },
+            Shape::Cylinder | Shape::Capsule => {
+                size.z = size.x;
+            },
+        }
+
+        let colliders = {
+            let mut rigid_body_set = self.sim.rigid_body_set.write().unwrap();
+            let body = rigid_body_set.get_mut(handle).ok_or_else(|| format!("Entity {name} not found"))?;
+            body.set_position(Isometry3::from_parts(position.into(), rotation), true);
+            body.set_linvel(Vector3::zeros(), true);
+            body.set_angvel(Vector3::zeros(), true);
+            body.colliders().to_vec()
+        };
+
+        if size != obj.transform.scaling {
+            let mut collider_set = self.sim.collider_set.write().unwrap();
+            for collider in colliders {
+                if let Some(collider) = collider_set.get_mut(collider) {
+                    collider.set_shape(match shape {
+                        Shape::Box => SharedShape::cuboid(size.x / 2.0, size.y / 2.0, size.z / 2.0),
+                        Shape::Sphere => SharedShape::ball(size.x / 2.0),
+                        Shape::Cylinder => SharedShape::cylinder(size.y / 2.0, size.x / 2.0),
+                        Shape::Capsule => SharedShape::capsule_y(size.y / 2.0, size.x / 2.0),
+                    });
+                }
+            }
+
+            // Scale is not read back from physics, so it must be set here
+            obj.transform.scaling = size;
+            obj.updated = true;
+            self.last_full_update_sent.store(0, Ordering::Relaxed);
+        }
+
+        // Resetting should return the entity to where it was placed
+        self.reseters.insert(name.to_owned(), Box::new(RigidBodyResetter::new(handle, self.sim.clone())));
+
+        Ok(())
+    }
+
+    /// Arguments for `addEntity` (type, x, y, z, rotation, options) that recreate the room's entities and robots
+    pub(crate) fn export_entities(&self) -> Vec<Value> {
+        let snapshot = self.snapshot();
+
+        let entities = snapshot.entities.iter().map(|entity| {
+            let position = entity.transform.position;
+
+            // Triggers take their rotation as a scaled axis, other entities as Euler angles
+            let rotation = match entity.transform.rotation {
+                Orientation::Euler(e) => e,
+                Orientation::Quaternion(q) if entity.trigger => UnitQuaternion::from_quaternion(q).scaled_axis(),
+                Orientation::Quaternion(q) => {
+                    let (x, y, z) = UnitQuaternion::from_quaternion(q).euler_angles();
+                    vector![x, y, z]
+                },
+            };
+
+            let mut kind = "box".to_owned();
+            let scale = entity.transform.scaling;
+            let mut options: Vec<Value> = vec![
+                vec![Value::from("size"), vec![scale.x, scale.y, scale.z].into()].into(),
+            ];
+
+            if entity.trigger {
+                kind = "trigger".to_owned();
+            } else {
+                if entity.is_kinematic {
+                    options.push(vec![Value::from("kinematic"), true.into()].into());
+                }
+
+                if entity.visual_only {
+                    options.push(vec![Value::from("visualOnly"), true.into()].into());
+                }
+
+                match &entity.visual_info {
+                    Some(VisualInfo::Color(r, g, b, shape)) => {
+                        kind = shape.to_string();
+                        options.push(vec![Value::from("color"), vec![r * 255.0, g * 255.0, b * 255.0].into()].into());
+                    },
+                    Some(VisualInfo::Texture(t, u, v, shape)) => {
+                        kind = shape.to_string();
+                        options.push(vec![Value::from("texture"), t.clone().into()].into());
+                        options.push(vec![Value::from("uscale"), (*u).into()].into());
+                        options.push(vec![Value::from("vscale"), (*v).into()].into());
+                    },
+                    Some(VisualInfo::Mesh(m)) => {
+                        options.push(vec![Value::from("mesh"), m.clone().into()].into());
+                    },
+                    Some(VisualInfo::None) | None => {},
+                }
+            }
+
+            Value::from(vec![
+                Value::from(kind),
+                position.x.into(),
+                position.y.into(),
+                position.z.into(),
+                rotation.iter().map(|r| r.to_degrees()).collect::<Vec<_>>().into(),
+                options.into(),
+            ])
+        });
+
+        let robots = snapshot.robots.iter().map(|robot| {
+            // Robots are only rotated around the vertical axis
+            let forward = robot.orientation * Vector3::x();
+            let yaw = (-forward.z).atan2(forward.x).to_degrees();
+
+            Value::from(vec![
+                Value::from("robot"),
+                robot.position.x.into(),
+                robot.position.y.into(),
+                robot.position.z.into(),
+                vec![0.0, yaw, 0.0].into(),
+                vec![
+                    Value::from(vec![Value::from("size"), robot.scale.into()]),
+                    Value::from(vec![Value::from("speed"), robot.speed_mult.into()]),
+                ].into(),
+            ])
+        });
+
+        entities.chain(robots).collect()
+    }
+}
@@ -0,0 +1,101 @@
+use serde::{Deserialize, Serialize};
+
+use super::*;
+
+/// Number of events kept for each room, older events are dropped
+pub const MAX_EVENTS: usize = 1000;
+
+/// Number of events kept for a headless room, enough for the trigger events of a long batch run
+pub const MAX_HEADLESS_EVENTS: usize = 100_000;
+
+/// Something that happened in a room
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RoomEventKind {
+    UserJoined { username: String },
+    UserLeft { username: String },
+    /// User was removed by the owner
+    UserKicked { username: String, by: String, banned: bool },
+    RobotClaimed { robot: String, username: String },
+    /// Robot claim released, by the claimant or otherwise (previous claimant)
+    RobotUnclaimed { robot: String, username: String },
+    /// Whole room reset, by a user or the environment
+    RoomReset { username: Option<String> },
+    RobotReset { robot: String, username: String },
+    EntityAdded { name: String },
+    EntityRemoved { name: String },
+    AllEntitiesRemoved,
+    /// Runtime error in the environment's project
+    VmError { error: String, entity: String },
+    Trigger { trigger: String, entity: String, entered: bool },
+    /// User sent a chat message, the text is not kept
+    Chat { username: String },
+}
+
+/// An event with when it happened
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomEvent {
+    /// Unix timestamp
+    pub timestamp: i64,
+    pub roomtime: f64,
+    #[serde(flatten)]
+    pub kind: RoomEventKind,
+}
+
+/// Bounded log of a room's events
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct EventLog {
+    #[derivative(Debug = "ignore")]
+    events: Mutex<VecDeque<RoomEvent>>,
+    /// Maximum number of events kept
+    capacity: usize,
+    #[derivative(Debug = "ignore")]
+    roomtime: Arc<RwLock<f64>>,
+}
+
+impl EventLog {
+    pub fn new(roomtime: Arc<RwLock<f64>>, capacity: usize) -> Self {
+        EventLog {
+            events: Mutex::new(VecDeque::new()),
+            capacity,
+            roomtime,
+        }
+    }
+
+    /// Add an event at the current time
+    pub fn log(&self, kind: RoomEventKind) {
+        let event = RoomEvent {
+            timestamp: get_timestamp(),
+            roomtime: *self.roomtime.read().unwrap(),
+            kind,
+        };
+        self.push(event);
+    }
+
+    fn push(&self, event: RoomEvent) {
+        let mut events = self.events.lock().unwrap();
+        events.push_back(event);
+
+        while events.len() > self.capacity {
+            events.pop_front();
+        }
+    }
+
+    /// Add previously logged events, such as those of a restored room
+    pub fn extend(&self, events: impl IntoIterator<Item = RoomEvent>) {
+        for event in events {
+            self.push(event);
+        }
+    }
+
+    /// Events logged at or after a Unix timestamp, oldest first
+    pub fn events_since(&self, since: i64) -> Vec<RoomEvent> {
+        self.events.lock().unwrap().iter().filter(|e| e.timestamp >= since).cloned().collect()
+    }
+
+    /// All events in the log, oldest first
+    pub fn events(&self) -> Vec<RoomEvent> {
+        self.events_since(i64::MIN)
+    }
+}
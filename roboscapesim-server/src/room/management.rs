@@ -1,43 +1,56 @@
 use std::collections::BTreeMap;
 
 use super::RoomData;
+use super::events::RoomEventKind;
 
 use std::sync::atomic::Ordering;
 
 use crate::{room::clients::ClientsManager, services::ServiceType};
 use crate::util::util::get_timestamp;
 
-use dashmap::DashSet;
 use log::{info, error};
-use roboscapesim_common::UpdateMessage;
+use roboscapesim_common::{api::CloneRoomRequestData, UpdateMessage};
 
-use crate::ROOMS;
+use super::metadata::{hash_password, RoomMetadata};
 
-pub fn join_room(username: &str, password: &str, peer_id: u128, room_id: &str) -> Result<(), String> {
+use crate::{DRAINING, ROOMS};
+
+pub fn join_room(username: &str, password: &str, owner_token: Option<&str>, peer_id: u128, room_id: &str) -> Result<(), String> {
     info!("User {} (peer id {}), attempting to join room {}", username, peer_id, room_id);
 
+    if DRAINING.load(Ordering::Relaxed) {
+        return Err("The server is restarting, try again shortly".to_owned());
+    }
+
     if !ROOMS.contains_key(room_id) {
         return Err(format!("Room {} does not exist!", room_id));
     }
 
     let room = ROOMS.get(room_id).unwrap();
 
+    if room.metadata.banned.contains(username) {
+        info!("Banned user {} attempted to join room {}", username, room_id);
+        return Err("You have been banned from this room".to_owned());
+    }
+
     // Check password
-    if room.metadata.password.clone().is_some_and(|pass| pass != password) {
+    if !room.metadata.check_password(password) {
         error!("User {} attempted to join room {} with wrong password", username, room_id);
         return Err("Wrong password!".to_owned());
     }
 
+    // Usernames are not verified, so joining as the owner needs the token given when the room was created
+    if !room.metadata.check_owner_token(username, owner_token) {
+        error!("User {} attempted to join room {} as its owner without the owner token", username, room_id);
+        return Err("Only the room owner can join with this username".to_owned());
+    }
+
     // Setup connection to room
     if !room.metadata.visitors.contains(&username.to_owned()) {
         room.metadata.visitors.insert(username.to_owned());
     }
 
-    if !room.clients_manager.sockets.contains_key(username) {
-        room.clients_manager.sockets.insert(username.to_string(), DashSet::new());
-    }
-
-    room.clients_manager.sockets.get_mut(username).unwrap().insert(peer_id);
+    room.clients_manager.add_client(username, peer_id);
     room.last_interaction_time.store(get_timestamp(),Ordering::Relaxed);
 
     // Give client initial update
@@ -47,22 +60,80 @@ pub fn join_room(username: &str, password: &str, peer_id: u128, room_id: &str) -
     // Send room info to API (force announcement when client joins)
     room.announce(true);
 
-    // Initial robot claim data
+    send_room_status_to_client(&room, username, peer_id);
+    room.clients_manager.send_to_all_clients(&UpdateMessage::UserRole(username.to_owned(), room.metadata.get_role(username)));
+
+    room.events.log(RoomEventKind::UserJoined { username: username.to_owned() });
+
+    // Send user join event
+    let world_service_id = room.services.iter().find(|s| s.key().1 == ServiceType::World).unwrap().value().get_service_info().id.clone();
+    room.netsblox_msg_tx.send(((world_service_id, ServiceType::World), "userJoined".to_string(), BTreeMap::from([("username".to_owned(), username.to_owned())]))).unwrap();
+
+    room.chat.send_history_to_client(peer_id);
+
+    ClientsManager::send_to_client(&UpdateMessage::ResumeToken(room.clients_manager.issue_resume_token(username)), peer_id);
+
+    Ok(())
+}
+
+/// Reconnect a client to a room it was in, keeping the user's claims and sending only the changes since `since`
+pub fn resume_session(token: &str, since: f64, peer_id: u128, room_id: &str) -> Result<(), String> {
+    let room = ROOMS.get(room_id).ok_or_else(|| format!("Room {} does not exist!", room_id))?;
+
+    let username = room.clients_manager.get_resume_user(token).ok_or_else(|| "Your session has expired, please rejoin the room".to_owned())?;
+
+    if room.metadata.banned.contains(&username) {
+        return Err("You have been banned from this room".to_owned());
+    }
+
+    info!("User {} (peer id {}) resumed session in room {}", username, peer_id, room_id);
+
+    room.clients_manager.add_client(&username, peer_id);
+    room.last_interaction_time.store(get_timestamp(),Ordering::Relaxed);
+
+    room.clients_manager.send_info_to_client(&room, peer_id);
+    room.clients_manager.send_changes_since_to_client(&room, since, peer_id);
+    send_room_status_to_client(&room, &username, peer_id);
+
+    // Role may have changed while disconnected
+    ClientsManager::send_to_client(&UpdateMessage::UserRole(username.clone(), room.metadata.get_role(&username)), peer_id);
+
+    Ok(())
+}
+
+/// Send a client the robot claims and locks, the roles of other users in the room and whether it is being recorded
+fn send_room_status_to_client(room: &RoomData, username: &str, peer_id: u128) {
     for robot in room.robots.iter() {
         if robot.value().claimed_by.is_some() {   
             ClientsManager::send_to_client(&UpdateMessage::RobotClaimed(robot.key().clone(), robot.value().claimed_by.clone().unwrap_or("".to_owned())), peer_id);
         }
+
+        if robot.value().locked {
+            ClientsManager::send_to_client(&UpdateMessage::RobotLocked(robot.key().clone(), true), peer_id);
+        }
     }
 
-    // Send user join event
-    let world_service_id = room.services.iter().find(|s| s.key().1 == ServiceType::World).unwrap().value().get_service_info().id.clone();
-    room.netsblox_msg_tx.send(((world_service_id, ServiceType::World), "userJoined".to_string(), BTreeMap::from([("username".to_owned(), username.to_owned())]))).unwrap();
+    for user in room.clients_manager.sockets.iter().filter(|u| u.key() != username) {
+        ClientsManager::send_to_client(&UpdateMessage::UserRole(user.key().clone(), room.metadata.get_role(user.key())), peer_id);
+    }
 
-    Ok(())
+    // Let users know they are being recorded
+    if let Some(id) = room.recorder.id() {
+        ClientsManager::send_to_client(&UpdateMessage::Recording(Some(id)), peer_id);
+    }
+
+    // Joining counts as an interaction, so bring the warning up to date before telling the client about it
+    let now = get_timestamp();
+    room.update_idle_warning(now);
+    if let Some(warning) = room.current_idle_warning(now) {
+        ClientsManager::send_to_client(&warning, peer_id);
+    }
 }
 
-pub async fn create_room(environment: Option<String>, password: Option<String>, edit_mode: bool) -> String {
-    let room = RoomData::new(None, environment, password, edit_mode).await;
+/// Create a room, giving its ID and the token its owner joins with
+pub async fn create_room(environment: Option<String>, password: Option<String>, owner: String, edit_mode: bool) -> (String, String) {
+    let room = RoomData::new(None, environment, password, Some(owner), edit_mode, false).await;
+    let owner_token = room.metadata.issue_owner_token();
 
     // Set last interaction to creation time
     room.last_interaction_time.store(get_timestamp(),Ordering::Relaxed);
@@ -71,5 +142,52 @@ pub async fn create_room(environment: Option<String>, password: Option<String>,
     ROOMS.insert(room_id.to_string(), room.clone());
     RoomData::launch(room);
 
-    room_id
+    (room_id, owner_token)
+}
+
+/// Reasons a room could not be cloned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CloneRoomError {
+    NotFound,
+    WrongPassword,
+    Banned,
+}
+
+/// Create a new room from the current state of an existing one, giving its ID and the token its owner joins with.
+///
+/// If robot IDs are kept, both rooms have robots with the same IDs, so messages to those robots from NetsBlox may reach either room.
+pub async fn clone_room(request: CloneRoomRequestData) -> Result<(String, String), CloneRoomError> {
+    let source = ROOMS.get(&request.room_id).map(|r| r.value().clone()).ok_or(CloneRoomError::NotFound)?;
+
+    if source.metadata.banned.contains(&request.username) {
+        info!("Banned user {} attempted to clone room {}", request.username, request.room_id);
+        return Err(CloneRoomError::Banned);
+    }
+
+    if !source.metadata.check_password(&request.password.unwrap_or_default()) {
+        error!("User {} attempted to clone room {} with wrong password", request.username, request.room_id);
+        return Err(CloneRoomError::WrongPassword);
+    }
+
+    let snapshot = source.snapshot();
+
+    let mut metadata = RoomMetadata::new(RoomData::generate_room_id(None), snapshot.environment.clone(), request.new_password.map(|p| hash_password(&p)), source.metadata.hibernate_timeout, source.metadata.full_timeout, source.metadata.edit_mode, false);
+    metadata.visitors.insert(request.username.clone());
+    metadata.owner = Some(request.username.clone());
+
+    let reserved_macs = if request.keep_robot_ids { snapshot.robots.iter().map(|r| r.mac).collect() } else { Default::default() };
+
+    let room = RoomData::create(metadata, reserved_macs).await;
+    let owner_token = room.metadata.issue_owner_token();
+    room.last_interaction_time.store(get_timestamp(), Ordering::Relaxed);
+    room.next_object_id.store(snapshot.next_object_id, Ordering::Relaxed);
+
+    let room_id = room.metadata.name.clone();
+    info!("User {} cloned room {} as {}", request.username, request.room_id, room_id);
+
+    ROOMS.insert(room_id.clone(), room.clone());
+    RoomData::sync_world_when_ready(room.clone(), snapshot).await;
+    RoomData::launch(room);
+
+    Ok((room_id, owner_token))
 }
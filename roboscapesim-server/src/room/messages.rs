@@ -36,8 +36,12 @@ impl MessageHandler {
                 
             for (msg, key) in msgs {
                 trace!("{:?}", msg);
+                room.recorder.record(|| RecordedEvent::IoTScapeCall(msg.service.clone(), msg.device.clone(), msg.function.clone(), msg.params.clone()));
 
+                metrics::count_iotscape_request(&msg.service);
+                let start = Instant::now();
                 let response = self.handle_iotscape_message(msg);
+                metrics::IOTSCAPE_LATENCY.observe(start.elapsed());
 
                 if let Some(key) = key {
                     key.complete(response.0.map_err(|e| e.into()));
@@ -80,9 +84,15 @@ impl MessageHandler {
 
             if let Some(client) = client {
                 match msg {
-                    ClientMessage::ResetAll => { *needs_reset = true; },
+                    ClientMessage::ResetAll => {
+                        if require_owner(room, client_username, client_id, "reset the room") {
+                            room.events.log(RoomEventKind::RoomReset { username: Some(client_username.clone()) });
+                            *needs_reset = true;
+                        }
+                    },
                     ClientMessage::ResetRobot(robot_id) => {
                         if room.is_authorized(*client.key(), &robot_id) {
+                            room.events.log(RoomEventKind::RobotReset { robot: robot_id.clone(), username: client_username.clone() });
                             robot_resets.push(robot_id);
                         } else {
                             info!("Client {} not authorized to reset robot {}", client_username, robot_id);
@@ -93,8 +103,12 @@ impl MessageHandler {
                         if room.is_authorized(*client.key(), &robot_id) {
                             // Claim robot
                             if let Some(mut robot) = room.robots.get_mut(&robot_id) {
-                                if robot.claimed_by.is_none() {
+                                if robot.locked {
+                                    info!("Robot {} is locked, {} cannot claim it", robot_id, client_username);
+                                    ClientsManager::send_to_client(&UpdateMessage::NonFatalError("This robot has been locked by the room owner".to_owned()), client_id);
+                                } else if robot.claimed_by.is_none() {
                                     robot.claimed_by = Some(client_username.clone());
+                                    room.events.log(RoomEventKind::RobotClaimed { robot: robot_id.clone(), username: client_username.clone() });
 
                                     // Send claim message to clients
                                     room.clients_manager.send_to_all_clients(&UpdateMessage::RobotClaimed(robot_id.clone(), client_username.clone()));
@@ -113,6 +127,7 @@ impl MessageHandler {
                             if let Some(mut robot) = room.robots.get_mut(&robot_id) {
                                 if robot.claimed_by.clone().is_some_and(|claimed_by| &claimed_by == client_username) {
                                     robot.claimed_by = None;
+                                    room.events.log(RoomEventKind::RobotUnclaimed { robot: robot_id.clone(), username: client_username.clone() });
 
                                     // Send Unclaim message to clients
                                     room.clients_manager.send_to_all_clients(&UpdateMessage::RobotClaimed(robot_id.clone(), "".to_owned()));
@@ -134,6 +149,94 @@ impl MessageHandler {
                             info!("Client {} not authorized to encrypt robot {}", client_username, robot_id);
                         }
                     },
+                    ClientMessage::SetRole(username, role) => {
+                        if !require_owner(room, client_username, client_id, "change roles") {
+                            return;
+                        }
+
+                        if role == RoomRole::Owner || room.metadata.get_role(&username) == RoomRole::Owner {
+                            ClientsManager::send_to_client(&UpdateMessage::NonFatalError("The room owner cannot be changed".to_owned()), client_id);
+                            return;
+                        }
+
+                        info!("{} set role of {} to {:?} in {}", client_username, username, role, room.metadata.name);
+                        room.metadata.roles.insert(username.clone(), role);
+
+                        // Spectators cannot keep robots claimed
+                        if role == RoomRole::Spectator {
+                            for mut robot in room.robots.iter_mut() {
+                                if robot.claimed_by.as_ref() == Some(&username) {
+                                    robot.claimed_by = None;
+                                    room.events.log(RoomEventKind::RobotUnclaimed { robot: robot.key().clone(), username: username.clone() });
+                                    room.clients_manager.send_to_all_clients(&UpdateMessage::RobotClaimed(robot.key().clone(), "".to_owned()));
+                                }
+                            }
+                        }
+
+                        room.clients_manager.send_to_all_clients(&UpdateMessage::UserRole(username, role));
+                    },
+                    ClientMessage::ChangePassword(password) => {
+                        if require_owner(room, client_username, client_id, "change the password") {
+                            info!("{} changed password of {}", client_username, room.metadata.name);
+                            room.metadata.set_password(password.as_deref().filter(|p| !p.trim().is_empty()));
+
+                            // Update has_password in room list
+                            room.announce(true);
+                        }
+                    },
+                    ClientMessage::KickUser(username) => {
+                        remove_user(room, client_username, client_id, username, false);
+                    },
+                    ClientMessage::BanUser(username) => {
+                        remove_user(room, client_username, client_id, username, true);
+                    },
+                    ClientMessage::ForceUnclaimRobot(robot_id) => {
+                        if require_owner(room, client_username, client_id, "unclaim other users' robots") {
+                            if let Some(mut robot) = room.robots.get_mut(&robot_id) {
+                                if let Some(claimant) = robot.claimed_by.take() {
+                                    info!("{} removed {}'s claim on robot {}", client_username, claimant, robot_id);
+                                    room.events.log(RoomEventKind::RobotUnclaimed { robot: robot_id.clone(), username: claimant });
+                                    room.clients_manager.send_to_all_clients(&UpdateMessage::RobotClaimed(robot_id.clone(), "".to_owned()));
+                                }
+                            }
+                        }
+                    },
+                    ClientMessage::LockRobot(robot_id, locked) => {
+                        if require_owner(room, client_username, client_id, "lock robots") {
+                            if let Some(mut robot) = room.robots.get_mut(&robot_id) {
+                                info!("{} {} robot {}", client_username, if locked { "locked" } else { "unlocked" }, robot_id);
+                                robot.locked = locked;
+                                room.clients_manager.send_to_all_clients(&UpdateMessage::RobotLocked(robot_id.clone(), locked));
+                            }
+                        }
+                    },
+                    ClientMessage::Chat(text) => {
+                        room.chat.handle_chat(room, client_username, client_id, text);
+                    },
+                    ClientMessage::Annotate(position) => {
+                        room.chat.handle_annotation(room, client_username, client_id, position);
+                    },
+                    ClientMessage::SetEntityTransform(name, transform) => {
+                        if require_edit_mode(room, client_id) && require_owner(room, client_username, client_id, "move entities") {
+                            if let Err(e) = room.set_entity_transform(&name, transform) {
+                                ClientsManager::send_to_client(&UpdateMessage::NonFatalError(e), client_id);
+                            }
+                        }
+                    },
+                    ClientMessage::SetRecording(recording) => {
+                        if require_owner(room, client_username, client_id, "record the room") {
+                            room.set_recording(recording, client_id);
+                        }
+                    },
+                    ClientMessage::ExportEntities => {
+                        if require_owner(room, client_username, client_id, "export entities") {
+                            let export = serde_json::to_string(&room.export_entities()).unwrap_or_default();
+                            ClientsManager::send_to_client(&UpdateMessage::EntityExport(export), client_id);
+                        }
+                    },
+                    ClientMessage::KeepAlive => {
+                        room.last_interaction_time.store(get_timestamp(), Ordering::Relaxed);
+                    },
                     _ => {
                         warn!("Unhandled client message: {:?}", msg);
                     }
@@ -141,4 +244,48 @@ impl MessageHandler {
             }
         });
     }
-}
\ No newline at end of file
+}
+
+/// Test if a user is the room owner, telling them if not. Rooms without an owner allow collaborators.
+fn require_owner(room: &RoomData, username: &str, client_id: u128, action: &str) -> bool {
+    let role = room.metadata.get_role(username);
+    if role == RoomRole::Owner || (room.metadata.owner.is_none() && role >= RoomRole::Collaborator) {
+        return true;
+    }
+
+    info!("Client {} not authorized to {}", username, action);
+    ClientsManager::send_to_client(&UpdateMessage::NonFatalError(format!("Only the room owner can {action}")), client_id);
+    false
+}
+
+/// Test if a room is in edit mode, telling the user if not
+fn require_edit_mode(room: &RoomData, client_id: u128) -> bool {
+    if room.metadata.edit_mode {
+        return true;
+    }
+
+    ClientsManager::send_to_client(&UpdateMessage::NonFatalError("Entities can only be moved directly in edit mode rooms".to_owned()), client_id);
+    false
+}
+
+/// Kick or ban a user from the room on behalf of the owner
+fn remove_user(room: &RoomData, client_username: &str, client_id: u128, username: String, ban: bool) {
+    if !require_owner(room, client_username, client_id, if ban { "ban users" } else { "kick users" }) {
+        return;
+    }
+
+    if room.metadata.get_role(&username) == RoomRole::Owner {
+        ClientsManager::send_to_client(&UpdateMessage::NonFatalError("The room owner cannot be removed".to_owned()), client_id);
+        return;
+    }
+
+    info!("{} {} {} from {}", client_username, if ban { "banned" } else { "kicked" }, username, room.metadata.name);
+    if ban {
+        room.metadata.banned.insert(username.clone());
+    }
+    room.events.log(RoomEventKind::UserKicked { username: username.clone(), by: client_username.to_owned(), banned: ban });
+
+    // Their robots are unclaimed on the next update
+    room.clients_manager.kick_user(room, &username, if ban { "You have been banned from this room" } else { "You have been removed from this room" });
+    room.clients_manager.send_to_all_clients(&UpdateMessage::UserKicked(username, ban));
+}
@@ -1,20 +1,37 @@
+use crate::util::extra_rand::UpperHexadecimal;
 use crate::util::util::get_timestamp;
-use crate::room::clients::ClientsManager;
+use crate::room::clients::{hash_token, ClientsManager};
 use crate::api::get_server;
 
-use roboscapesim_common::api::RoomInfo;
+use roboscapesim_common::{api::RoomInfo, RoomRole};
 
 use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 use std::sync::Arc;
 
-use dashmap::DashSet;
+#[cfg(feature = "no_deadlocks")]
+use no_deadlocks::RwLock;
+#[cfg(not(feature = "no_deadlocks"))]
+use std::sync::RwLock;
+
+use dashmap::{DashMap, DashSet};
 use log::info;
+use argon2::{password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString}, Argon2};
+use rand::Rng;
 
 #[derive(Debug)]
 pub struct RoomMetadata {
     pub name: String,
     pub environment: String,
-    pub password: Option<String>,
+    /// Salted hash of the room password, see `hash_password`
+    pub password_hash: RwLock<Option<String>>,
+    /// Username of the user who created the room
+    pub owner: Option<String>,
+    /// Hash of the token the owner joins with, see `issue_owner_token` and `hash_token`
+    pub owner_token_hash: RwLock<Option<String>>,
+    /// Roles assigned by the owner, users not listed are collaborators
+    pub roles: DashMap<String, RoomRole>,
+    /// Usernames banned from joining by the owner
+    pub banned: DashSet<String>,
     pub hibernate_timeout: i64,
     pub full_timeout: i64,
     /// List of usernames of users who have visited the room
@@ -25,14 +42,20 @@ pub struct RoomMetadata {
     pub hibernating_since: Arc<AtomicI64>,
     /// Last time the room was announced to the API server
     pub last_announce_time: Arc<AtomicI64>,
+    /// Whether the room is run without clients (batch mode), if so, it never hibernates or announces itself
+    pub headless: bool,
 }
 
 impl RoomMetadata {
-    pub fn new(name: String, environment: String, password: Option<String>, hibernate_timeout: i64, full_timeout: i64, edit_mode: bool) -> Self {
+    pub fn new(name: String, environment: String, password_hash: Option<String>, hibernate_timeout: i64, full_timeout: i64, edit_mode: bool, headless: bool) -> Self {
         Self {
             name,
             environment,
-            password,
+            password_hash: RwLock::new(password_hash),
+            owner: None,
+            owner_token_hash: RwLock::new(None),
+            roles: DashMap::new(),
+            banned: DashSet::new(),
             hibernate_timeout,
             full_timeout,
             visitors: DashSet::new(),
@@ -40,6 +63,7 @@ impl RoomMetadata {
             hibernating: Arc::new(AtomicBool::new(false)),
             hibernating_since: Arc::new(AtomicI64::default()),
             last_announce_time: Arc::new(AtomicI64::new(0)),
+            headless,
         }
     }
 
@@ -49,13 +73,58 @@ impl RoomMetadata {
             id: self.name.clone(),
             environment: self.environment.clone(),
             server: get_server().to_owned(),
-            creator: "TODO".to_owned(),
-            has_password: self.password.is_some(),
+            creator: self.owner.clone().unwrap_or_default(),
+            has_password: self.password_hash.read().unwrap().is_some(),
             is_hibernating: self.hibernating.load(std::sync::atomic::Ordering::Relaxed),
             visitors: self.visitors.clone().into_iter().collect(),
         }
     }
 
+    /// Test if a password matches the room's password, rooms without a password accept any password
+    pub(crate) fn check_password(&self, password: &str) -> bool {
+        match &*self.password_hash.read().unwrap() {
+            Some(hash) => verify_password(hash, password),
+            None => true,
+        }
+    }
+
+    /// Change the room password, None removes it
+    pub(crate) fn set_password(&self, password: Option<&str>) {
+        *self.password_hash.write().unwrap() = password.map(hash_password);
+    }
+
+    /// Create the token the owner joins with, replacing any previous one
+    pub(crate) fn issue_owner_token(&self) -> String {
+        let token: String = rand::rng()
+            .sample_iter(&UpperHexadecimal)
+            .take(32)
+            .map(char::from)
+            .collect();
+        *self.owner_token_hash.write().unwrap() = Some(hash_token(&token));
+        token
+    }
+
+    /// Test if a user may join with a username, only the holder of the owner token may join as the owner
+    pub(crate) fn check_owner_token(&self, username: &str, token: Option<&str>) -> bool {
+        if self.owner.as_deref() != Some(username) {
+            return true;
+        }
+
+        match (&*self.owner_token_hash.read().unwrap(), token) {
+            (Some(hash), Some(token)) => *hash == hash_token(token),
+            _ => false,
+        }
+    }
+
+    /// Get the role of a user in the room
+    pub(crate) fn get_role(&self, username: &str) -> RoomRole {
+        if self.owner.as_deref() == Some(username) {
+            RoomRole::Owner
+        } else {
+            self.roles.get(username).map(|r| *r).unwrap_or_default()
+        }
+    }
+
     /// Check if the room should hibernate or wake up
     pub(crate) fn check_hibernation_state(&self, clients_manager: &ClientsManager) {
         if !self.hibernating.load(Ordering::Relaxed) && clients_manager.sockets.is_empty() {
@@ -68,3 +137,38 @@ impl RoomMetadata {
         }    
     }
 }
+
+
+/// Hash a password with Argon2 and a random salt, as a PHC string
+pub(crate) fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&rand::random::<[u8; 16]>()).expect("16 byte salts are a valid length");
+    Argon2::default().hash_password(password.as_bytes(), &salt).expect("Argon2 accepts default parameters").to_string()
+}
+
+/// Test if a password matches a hash created by `hash_password`, comparing in constant time
+pub(crate) fn verify_password(hash: &str, password: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn test_password_hash() {
+    let hash = hash_password("hunter2");
+    assert!(verify_password(&hash, "hunter2"));
+    assert!(!verify_password(&hash, "hunter3"));
+    assert_ne!(hash, hash_password("hunter2"));
+}
+
+#[test]
+fn test_owner_token() {
+    let mut metadata = RoomMetadata::new("test".to_owned(), "Default".to_owned(), None, 60, 120, false, true);
+    metadata.owner = Some("owner".to_owned());
+    assert!(!metadata.check_owner_token("owner", None));
+
+    let token = metadata.issue_owner_token();
+    assert!(metadata.check_owner_token("owner", Some(&token)));
+    assert!(!metadata.check_owner_token("owner", Some("wrong")));
+    assert!(metadata.check_owner_token("visitor", None));
+}
@@ -8,11 +8,12 @@ impl RoomData {
         let speed_mult = speed_mult.unwrap_or(1.0).clamp(-10.0, 10.0);
         let scale: f32 = scale.unwrap_or(1.0).clamp(1.0, 5.0);
 
-        let mut robot = RobotPhysics::create_robot_body(room.sim.clone(), None, Some(position), Some(orientation), Some(scale));
+        let mac = room.reserved_macs.lock().unwrap().pop_front();
+        let mut robot = RobotPhysics::create_robot_body(room.sim.clone(), mac, Some(position), Some(orientation), Some(scale));
         robot.motor_data.speed_scale = speed_mult;
         let robot_id: String = "robot_".to_string() + robot.id.as_str();
         room.sim.rigid_body_labels.insert(robot_id.clone(), robot.physics.body_handle);
-        room.objects.insert(robot_id.clone(), ObjectData {
+        room.insert_object(robot_id.clone(), ObjectData {
             name: robot_id.clone(),
             transform: Transform {scaling: vector![scale * SCALE, scale * SCALE, scale * SCALE], ..Default::default() },
             visual_info: Some(VisualInfo::Mesh("parallax_robot.glb".into())),
@@ -26,7 +27,7 @@ impl RoomData {
             let mut i = 0;
             for wheel in &robot.physics.wheel_bodies {
                 room.sim.rigid_body_labels.insert(format!("wheel_{}", i), *wheel);
-                room.objects.insert(format!("wheel_{}", i), ObjectData {
+                room.insert_object(format!("wheel_{}", i), ObjectData {
                     name: format!("wheel_{}", i),
                     transform: Transform { scaling: vector![0.18,0.03,0.18], ..Default::default() },
                     visual_info: Some(VisualInfo::default()),
@@ -106,7 +107,7 @@ impl RoomData {
 
         room.sim.rigid_body_labels.insert(body_name.clone(), cube_body_handle);
 
-        room.objects.insert(body_name.clone(), ObjectData {
+        room.insert_object(body_name.clone(), ObjectData {
             name: body_name.clone(),
             transform: Transform { position: position.into(), scaling: size, rotation: Orientation::Euler(rotation), ..Default::default() },
             visual_info: Some(visual_info),
@@ -145,7 +146,7 @@ impl RoomData {
         let collider_handle = room.sim.collider_set.write().unwrap().insert_with_parent(collider, cube_body_handle, &mut rigid_body_set.write().unwrap());
         room.sim.rigid_body_labels.insert(body_name.clone(), cube_body_handle);
 
-        room.objects.insert(body_name.clone(), ObjectData {
+        room.insert_object(body_name.clone(), ObjectData {
             name: body_name.clone(),
             transform: Transform { position: position.into(), scaling: size, rotation: Orientation::Euler(rotation), ..Default::default() },
             visual_info: Some(VisualInfo::None),
@@ -0,0 +1,406 @@
+use std::{collections::{HashSet, VecDeque}, fs, path::PathBuf};
+
+use nalgebra::Isometry3;
+use serde::{Deserialize, Serialize};
+
+use crate::robot::physics::RobotPhysics;
+use crate::room::events::RoomEvent;
+use crate::util::util::bytes_to_hex_string;
+use crate::ROOMS;
+
+use super::*;
+
+/// How often rooms are saved to disk
+pub const SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How long to wait for a restored room's VM to start before syncing its world anyway
+const VM_START_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Roomtime a restored room's environment must go without adding or removing entities before its world is synced to the snapshot
+const SETUP_QUIET_TIME: f64 = 1.0;
+
+/// Longest to wait for a restored room's environment to finish creating its world before syncing anyway
+const SETUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Directory room state is saved to, persistence is disabled if it is not configured
+pub static DATA_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| config().storage.room_data_dir.clone());
+
+/// Held while writing room files, so the saves on shutdown do not race a periodic save
+static SAVE_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+/// Saved state of a room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoomSnapshot {
+    pub name: String,
+    pub environment: String,
+    pub password_hash: Option<String>,
+    #[serde(default)]
+    pub owner: Option<String>,
+    /// Salted hash of the owner token, rooms saved without one have no owner
+    #[serde(default)]
+    pub owner_token_hash: Option<String>,
+    #[serde(default)]
+    pub roles: Vec<(String, RoomRole)>,
+    #[serde(default)]
+    pub banned: Vec<String>,
+    pub hibernate_timeout: i64,
+    pub full_timeout: i64,
+    pub edit_mode: bool,
+    pub visitors: Vec<String>,
+    pub last_interaction_time: i64,
+    pub next_object_id: i64,
+    pub entities: Vec<EntitySnapshot>,
+    pub robots: Vec<RobotSnapshot>,
+    #[serde(default)]
+    pub events: Vec<RoomEvent>,
+    /// Usernames and the hashes of their resume tokens, so clients can resume their sessions after a restart
+    #[serde(default)]
+    pub resume_token_hashes: Vec<(String, String)>,
+}
+
+/// Saved state of a non-robot entity
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EntitySnapshot {
+    /// Name without the room ID prefix
+    pub name: String,
+    pub transform: Transform,
+    pub visual_info: Option<VisualInfo>,
+    pub is_kinematic: bool,
+    pub visual_only: bool,
+    pub trigger: bool,
+}
+
+/// Saved state of a robot
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RobotSnapshot {
+    pub mac: [u8; 6],
+    pub position: Vector3<f32>,
+    pub orientation: UnitQuaternion<f32>,
+    pub scale: f32,
+    pub speed_mult: f32,
+    pub claimable: bool,
+    #[serde(default)]
+    pub locked: bool,
+}
+
+impl RoomData {
+    /// Capture the current state of the room
+    pub(crate) fn snapshot(&self) -> RoomSnapshot {
+        let prefix = self.metadata.name.clone() + "_";
+
+        let entities = self.objects.iter().filter(|o| !o.key().starts_with("robot_") && !o.key().starts_with("wheel_")).filter_map(|o| {
+            let handle = *self.sim.rigid_body_labels.get(o.key())?;
+            let visual_only = self.sim.rigid_body_set.read().unwrap().get(handle)?.colliders().is_empty();
+
+            Some(EntitySnapshot {
+                name: o.key().strip_prefix(&prefix).unwrap_or(o.key()).to_owned(),
+                transform: o.value().transform,
+                visual_info: o.value().visual_info.clone(),
+                is_kinematic: o.value().is_kinematic,
+                visual_only,
+                trigger: self.sim.sensors.iter().any(|s| &s.key().0 == o.key()),
+            })
+        }).collect();
+
+        let robots = self.robots.iter().filter_map(|r| {
+            let rigid_body_set = self.sim.rigid_body_set.read().unwrap();
+            let body = rigid_body_set.get(r.value().physics.body_handle)?;
+            let scale = self.objects.get(&("robot_".to_owned() + r.key())).map(|o| o.value().transform.scaling.x / SCALE).unwrap_or(1.0);
+
+            Some(RobotSnapshot {
+                mac: r.value().mac,
+                position: *body.translation(),
+                orientation: *body.rotation(),
+                scale,
+                speed_mult: r.value().motor_data.speed_scale,
+                claimable: r.value().claimable,
+                locked: r.value().locked,
+            })
+        }).collect();
+
+        RoomSnapshot {
+            name: self.metadata.name.clone(),
+            environment: self.metadata.environment.clone(),
+            password_hash: self.metadata.password_hash.read().unwrap().clone(),
+            owner: self.metadata.owner.clone(),
+            owner_token_hash: self.metadata.owner_token_hash.read().unwrap().clone(),
+            roles: self.metadata.roles.iter().map(|r| (r.key().clone(), *r.value())).collect(),
+            banned: self.metadata.banned.iter().map(|b| b.clone()).collect(),
+            hibernate_timeout: self.metadata.hibernate_timeout,
+            full_timeout: self.metadata.full_timeout,
+            edit_mode: self.metadata.edit_mode,
+            visitors: self.metadata.visitors.iter().map(|v| v.clone()).collect(),
+            last_interaction_time: self.last_interaction_time.load(Ordering::Relaxed),
+            next_object_id: self.next_object_id.load(Ordering::Relaxed),
+            entities,
+            robots,
+            events: self.events.events(),
+            resume_token_hashes: self.clients_manager.resume_token_hashes(),
+        }
+    }
+
+    /// Recreate a room from a snapshot, keeping its ID and robot MAC addresses
+    pub(crate) async fn restore(snapshot: RoomSnapshot) -> Arc<RoomData> {
+        let mut metadata = RoomMetadata::new(snapshot.name.clone(), snapshot.environment.clone(), snapshot.password_hash.clone(), snapshot.hibernate_timeout, snapshot.full_timeout, snapshot.edit_mode, false);
+        // Nobody could prove they are the owner without a token, so those rooms are left to collaborators
+        metadata.owner = snapshot.owner.clone().filter(|_| snapshot.owner_token_hash.is_some());
+        metadata.owner_token_hash = RwLock::new(snapshot.owner_token_hash.clone());
+
+        for (username, role) in &snapshot.roles {
+            metadata.roles.insert(username.clone(), *role);
+        }
+
+        for username in &snapshot.banned {
+            metadata.banned.insert(username.clone());
+        }
+
+        for visitor in &snapshot.visitors {
+            metadata.visitors.insert(visitor.clone());
+        }
+
+        let room = RoomData::create(metadata, snapshot.robots.iter().map(|r| r.mac).collect::<VecDeque<_>>()).await;
+        room.last_interaction_time.store(snapshot.last_interaction_time, Ordering::Relaxed);
+        room.next_object_id.store(snapshot.next_object_id, Ordering::Relaxed);
+        room.events.extend(snapshot.events.clone());
+        room.clients_manager.restore_resume_token_hashes(snapshot.resume_token_hashes.clone());
+        RoomData::sync_world_when_ready(room.clone(), snapshot).await;
+        room
+    }
+
+    /// Make the room's world match a snapshot once it is ready.
+    ///
+    /// Edit mode rooms are synced immediately. Other rooms rerun their environment, which creates the world again, so
+    /// they are synced after the VM has started and stopped adding or removing entities.
+    pub(crate) async fn sync_world_when_ready(room: Arc<RoomData>, snapshot: RoomSnapshot) {
+        if room.metadata.edit_mode {
+            room.sync_world(&snapshot).await;
+            return;
+        }
+
+        let Some(vm_started) = room.vm_manager.get().map(|vm| vm.started.clone()) else {
+            room.sync_world(&snapshot).await;
+            return;
+        };
+
+        spawn(async move {
+            let start = Instant::now();
+            while !vm_started.load(Ordering::Relaxed) && start.elapsed() < VM_START_TIMEOUT {
+                sleep(Duration::from_millis(100)).await;
+            }
+
+            // Setup is done once the world stops changing, roomtime only advances while the room is running
+            let started_at = *room.roomtime.read().unwrap();
+            let start = Instant::now();
+            loop {
+                let quiet_since = room.last_structure_change.read().unwrap().max(started_at);
+                if *room.roomtime.read().unwrap() - quiet_since >= SETUP_QUIET_TIME {
+                    break;
+                }
+
+                if start.elapsed() > SETUP_TIMEOUT {
+                    warn!("Environment of {} was still changing after {:?}, syncing anyway", room.metadata.name, SETUP_TIMEOUT);
+                    break;
+                }
+
+                sleep(Duration::from_millis(100)).await;
+            }
+
+            room.sync_world(&snapshot).await;
+        });
+    }
+
+    /// Make the room's entities and robots match a snapshot.
+    ///
+    /// Entities with the same name are moved to their saved pose, missing entities are added and entities not in the
+    /// snapshot are removed. Robots are matched by ID first, then paired up with any remaining robots.
+    pub(crate) async fn sync_world(&self, snapshot: &RoomSnapshot) {
+        let prefix = self.metadata.name.clone() + "_";
+        let names = snapshot.entities.iter().map(|e| prefix.clone() + &e.name).collect::<HashSet<_>>();
+
+        let removed = self.objects.iter()
+            .map(|o| o.key().clone())
+            .filter(|k| !k.starts_with("robot_") && !k.starts_with("wheel_") && !names.contains(k))
+            .collect::<Vec<_>>();
+        for name in removed {
+            self.remove(&name);
+        }
+
+        for entity in &snapshot.entities {
+            let position = entity.transform.position.coords;
+            let rotation: Vector3<f32> = entity.transform.rotation.into();
+
+            if !self.objects.contains_key(&(prefix.clone() + &entity.name)) {
+                if entity.trigger {
+                    RoomData::add_trigger(self, &entity.name, position, rotation, Some(entity.transform.scaling)).await;
+                } else {
+                    RoomData::add_shape(self, &entity.name, position, rotation, entity.visual_info.clone(), Some(entity.transform.scaling), entity.is_kinematic, entity.visual_only);
+                }
+            }
+
+            // Triggers take their rotation as a scaled axis, other entities as Euler angles, the same as `add_trigger` and `add_shape`
+            let orientation = match entity.transform.rotation {
+                Orientation::Euler(e) if entity.trigger => UnitQuaternion::new(e),
+                rotation => rotation.to_quaternion(),
+            };
+
+            // Entity rotations may have been converted to quaternions, so always set the exact pose
+            if let Some(handle) = self.sim.rigid_body_labels.get(&(prefix.clone() + &entity.name)).map(|h| *h) {
+                if let Some(body) = self.sim.rigid_body_set.write().unwrap().get_mut(handle) {
+                    body.set_position(Isometry3::from_parts(position.into(), orientation), true);
+                    body.set_linvel(Vector3::zeros(), true);
+                    body.set_angvel(Vector3::zeros(), true);
+                }
+            }
+        }
+
+        // Pair saved robots with existing ones, by ID if possible
+        let mut unmatched = self.robots.iter().map(|r| r.key().clone()).filter(|id| !snapshot.robots.iter().any(|r| &bytes_to_hex_string(&r.mac) == id)).collect::<Vec<_>>();
+        unmatched.sort();
+        let mut unmatched = unmatched.into_iter();
+
+        for robot in &snapshot.robots {
+            let id = bytes_to_hex_string(&robot.mac);
+            let id = if self.robots.contains_key(&id) { Some(id) } else { unmatched.next() };
+
+            let id = match id {
+                Some(id) => id,
+                None => RoomData::add_robot(self, robot.position, robot.orientation, false, Some(robot.speed_mult), Some(robot.scale)),
+            };
+
+            if let Some(mut r) = self.robots.get_mut(&id) {
+                RobotPhysics::update_transform(&mut r, self.sim.clone(), Some(robot.position), Some(robot.orientation.into()), true);
+                r.motor_data.speed_scale = robot.speed_mult;
+                r.claimable = robot.claimable;
+                r.locked = robot.locked;
+            }
+        }
+
+        self.last_full_update_sent.store(0, Ordering::Relaxed);
+    }
+}
+
+fn room_file(dir: &PathBuf, name: &str) -> PathBuf {
+    dir.join(format!("{name}.json"))
+}
+
+/// Save a room to the data directory, if persistence is enabled
+pub fn save_room(room: &RoomData) {
+    let Some(dir) = DATA_DIR.as_ref() else {
+        return;
+    };
+
+    // Batch runs are not kept
+    if room.metadata.headless {
+        return;
+    }
+
+    let result = fs::create_dir_all(dir)
+        .and_then(|_| serde_json::to_vec(&room.snapshot()).map_err(|e| e.into()))
+        .and_then(|data| {
+            // Write to a temporary file first so a crash does not leave a partial file
+            let path = room_file(dir, &room.metadata.name);
+            let tmp = path.with_extension("json.tmp");
+            fs::write(&tmp, data)?;
+            fs::rename(tmp, path)
+        });
+
+    if let Err(e) = result {
+        error!("Failed to save room {}: {:?}", room.metadata.name, e);
+    }
+}
+
+/// Save all rooms, if persistence is enabled
+///
+/// Snapshots and file writes block, so rooms are saved on a blocking thread.
+pub async fn save_all_rooms() {
+    if DATA_DIR.is_none() {
+        return;
+    }
+
+    let _lock = SAVE_LOCK.lock().await;
+    let saved = tokio::task::spawn_blocking(|| {
+        for room in ROOMS.iter() {
+            save_room(room.value());
+        }
+        ROOMS.len()
+    }).await;
+
+    match saved {
+        Ok(count) => info!("Saved {} rooms", count),
+        Err(e) => error!("Failed to save rooms: {:?}", e),
+    }
+}
+
+/// Delete a room's saved state, if persistence is enabled
+pub fn delete_room(name: &str) {
+    if let Some(dir) = DATA_DIR.as_ref() {
+        let path = room_file(dir, name);
+        if path.exists() {
+            if let Err(e) = fs::remove_file(&path) {
+                error!("Failed to delete saved room {}: {:?}", name, e);
+            }
+        }
+    }
+}
+
+/// Load saved rooms from the data directory and launch them, if persistence is enabled
+pub async fn restore_rooms() {
+    let Some(dir) = DATA_DIR.as_ref() else {
+        return;
+    };
+
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            info!("No saved rooms loaded from {}: {:?}", dir.display(), e);
+            return;
+        }
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != "json") {
+            continue;
+        }
+
+        let snapshot = match fs::read(&path).map_err(|e| e.to_string()).and_then(|data| serde_json::from_slice::<RoomSnapshot>(&data).map_err(|e| e.to_string())) {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                error!("Failed to load saved room {}: {}", path.display(), e);
+                continue;
+            }
+        };
+
+        // Skip rooms that would have been removed while the server was down
+        if get_timestamp() - snapshot.last_interaction_time > snapshot.full_timeout {
+            info!("Saved room {} has timed out", snapshot.name);
+            delete_room(&snapshot.name);
+            continue;
+        }
+
+        if ROOMS.contains_key(&snapshot.name) {
+            warn!("Room {} already exists, not restoring", snapshot.name);
+            continue;
+        }
+
+        info!("Restoring room {}", snapshot.name);
+        let room = RoomData::restore(snapshot).await;
+        ROOMS.insert(room.metadata.name.clone(), room.clone());
+        RoomData::launch(room);
+    }
+}
+
+/// Save all rooms regularly
+pub async fn save_rooms_periodically() {
+    if DATA_DIR.is_none() {
+        return;
+    }
+
+    let mut interval = time::interval(SAVE_INTERVAL);
+    interval.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
+
+    loop {
+        interval.tick().await;
+        save_all_rooms().await;
+    }
+}
@@ -0,0 +1,215 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::*;
+
+/// Directory recordings are saved to, recording is disabled if it is not configured
+pub static RECORDING_DIR: Lazy<Option<PathBuf>> = Lazy::new(|| config().storage.recording_dir.clone());
+
+/// Longest a recording can run before it is stopped, in seconds of roomtime
+const MAX_RECORDING_LENGTH: f64 = 2.0 * 60.0 * 60.0;
+
+/// Version of the recording file format
+const RECORDING_VERSION: u32 = 1;
+
+/// First entry of a recording file, followed by `(time, RecordedEvent)` entries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub version: u32,
+    pub room: String,
+    pub environment: String,
+    /// Unix timestamp the recording started at
+    pub started: i64,
+}
+
+/// Something that happened in a recorded room
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    /// Message sent to everyone in the room
+    #[serde(rename="u")]
+    Update(UpdateMessage),
+    /// Message from a user (username, message), other than heartbeats and password changes
+    #[serde(rename="c")]
+    ClientAction(String, ClientMessage),
+    /// RoboScape command received by a robot (robot id, message)
+    #[serde(rename="r")]
+    RoboScapeCommand(String, Vec<u8>),
+    /// IoTScape call to one of the room's services (service type, device, function, params)
+    #[serde(rename="i")]
+    IoTScapeCall(String, String, String, Vec<Value>),
+}
+
+/// A recording being written
+struct ActiveRecording {
+    id: String,
+    writer: BufWriter<File>,
+    /// Roomtime the recording started at
+    start: f64,
+}
+
+/// Writes a room's timeline to a file while recording is enabled
+#[derive(Derivative)]
+#[derivative(Debug)]
+pub struct Recorder {
+    #[derivative(Debug = "ignore")]
+    active: Mutex<Option<ActiveRecording>>,
+    /// Whether a recording is in progress, checked before building events to record
+    recording: AtomicBool,
+    #[derivative(Debug = "ignore")]
+    roomtime: Arc<RwLock<f64>>,
+}
+
+impl Recorder {
+    pub fn new(roomtime: Arc<RwLock<f64>>) -> Self {
+        Recorder {
+            active: Mutex::new(None),
+            recording: AtomicBool::new(false),
+            roomtime,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    /// ID of the recording in progress
+    pub fn id(&self) -> Option<String> {
+        self.active.lock().unwrap().as_ref().map(|a| a.id.clone())
+    }
+
+    /// Start a new recording of a room, returning its ID
+    pub fn start(&self, metadata: &RoomMetadata) -> Result<String, String> {
+        let dir = RECORDING_DIR.as_ref().ok_or("Recording is not enabled on this server")?;
+        let mut active = self.active.lock().unwrap();
+
+        if let Some(recording) = active.as_ref() {
+            return Err(format!("This room is already being recorded as {}", recording.id));
+        }
+
+        let suffix: String = rand::rng()
+            .sample_iter(&UpperHexadecimal)
+            .take(8)
+            .map(char::from)
+            .collect();
+        let id = format!("{}-{}", metadata.name.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>(), suffix);
+
+        let file = fs::create_dir_all(dir)
+            .and_then(|_| File::create(recording_file(dir, &id)))
+            .map_err(|e| format!("Could not create recording: {e}"))?;
+        let mut writer = BufWriter::new(file);
+
+        let header = RecordingHeader {
+            version: RECORDING_VERSION,
+            room: metadata.name.clone(),
+            environment: metadata.environment.clone(),
+            started: get_timestamp(),
+        };
+        rmp_serde::encode::write(&mut writer, &header).map_err(|e| format!("Could not create recording: {e}"))?;
+
+        *active = Some(ActiveRecording { id: id.clone(), writer, start: *self.roomtime.read().unwrap() });
+        self.recording.store(true, Ordering::Relaxed);
+        Ok(id)
+    }
+
+    /// Stop the recording in progress, returning its ID
+    pub fn stop(&self) -> Option<String> {
+        let mut recording = self.active.lock().unwrap().take()?;
+        self.recording.store(false, Ordering::Relaxed);
+
+        if let Err(e) = recording.writer.flush() {
+            error!("Failed to save recording {}: {:?}", recording.id, e);
+        }
+
+        Some(recording.id)
+    }
+
+    /// Add an event to the recording in progress, the event is only created if recording
+    pub fn record(&self, event: impl FnOnce() -> RecordedEvent) {
+        if !self.is_recording() {
+            return;
+        }
+
+        let time = *self.roomtime.read().unwrap();
+        if let Some(recording) = self.active.lock().unwrap().as_mut() {
+            if let Err(e) = rmp_serde::encode::write(&mut recording.writer, &(time - recording.start, event())) {
+                error!("Failed to write to recording {}: {:?}", recording.id, e);
+            }
+        }
+    }
+
+    /// Roomtime since the recording in progress started
+    fn length(&self) -> Option<f64> {
+        let time = *self.roomtime.read().unwrap();
+        self.active.lock().unwrap().as_ref().map(|a| time - a.start)
+    }
+}
+
+impl RoomData {
+    /// Start or stop recording the room, telling everyone in it
+    pub(crate) fn set_recording(&self, recording: bool, client_id: u128) {
+        if recording {
+            match self.recorder.start(&self.metadata) {
+                Ok(id) => {
+                    info!("Recording room {} as {}", self.metadata.name, id);
+                    self.clients_manager.send_to_all_clients(&UpdateMessage::Recording(Some(id)));
+
+                    // Recordings start with everything in the room
+                    self.last_full_update_sent.store(0, Ordering::Relaxed);
+                },
+                Err(e) => ClientsManager::send_to_client(&UpdateMessage::NonFatalError(e), client_id),
+            }
+        } else {
+            self.stop_recording();
+        }
+    }
+
+    /// Stop recording the room, if it is being recorded
+    pub(crate) fn stop_recording(&self) {
+        if let Some(id) = self.recorder.stop() {
+            info!("Stopped recording room {} ({})", self.metadata.name, id);
+            self.clients_manager.send_to_all_clients(&UpdateMessage::Recording(None));
+        }
+    }
+
+    /// Stop recordings that have run too long
+    pub(crate) fn check_recording_length(&self) {
+        if self.recorder.length().is_some_and(|l| l > MAX_RECORDING_LENGTH) {
+            info!("Recording of room {} reached the maximum length", self.metadata.name);
+            self.stop_recording();
+        }
+    }
+}
+
+fn recording_file(dir: &PathBuf, id: &str) -> PathBuf {
+    dir.join(format!("{id}.rec"))
+}
+
+/// Read a saved recording
+pub fn load_recording(id: &str) -> Result<(RecordingHeader, Vec<(f64, RecordedEvent)>), String> {
+    let dir = RECORDING_DIR.as_ref().ok_or("Recordings are not available on this server")?;
+
+    // IDs are only used as file names
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err("Invalid recording ID".to_owned());
+    }
+
+    let file = File::open(recording_file(dir, id)).map_err(|_| format!("Recording {id} not found"))?;
+    let mut reader = BufReader::new(file);
+
+    let header: RecordingHeader = rmp_serde::from_read(&mut reader).map_err(|e| format!("Recording {id} could not be read: {e}"))?;
+    if header.version > RECORDING_VERSION {
+        return Err(format!("Recording {id} was made by a newer version of the server"));
+    }
+
+    // Recordings in progress or cut off by a crash can end partway through an entry
+    let mut events = vec![];
+    while let Ok(entry) = rmp_serde::from_read::<_, (f64, RecordedEvent)>(&mut reader) {
+        events.push(entry);
+    }
+
+    Ok((header, events))
+}
@@ -0,0 +1,66 @@
+use std::{sync::atomic::{AtomicU64, Ordering}, time::Duration};
+
+use roboscapesim_common::metrics::Histogram;
+use serde::Serialize;
+
+/// Weight of the newest tick in the moving average
+const AVERAGE_WEIGHT: f64 = 0.05;
+
+/// Tracks how long a room's update ticks take
+#[derive(Debug, Default)]
+pub struct TickTiming {
+    last_micros: AtomicU64,
+    average_micros: AtomicU64,
+    max_micros: AtomicU64,
+    ticks: AtomicU64,
+    /// Ticks skipped because the previous tick was still running
+    skipped: AtomicU64,
+    /// Distribution of tick durations, for metrics
+    pub histogram: Histogram,
+}
+
+/// Tick timing of a room, as reported by the API
+#[derive(Debug, Clone, Serialize)]
+pub struct RoomTickTiming {
+    pub room: String,
+    pub last_ms: f64,
+    pub average_ms: f64,
+    pub max_ms: f64,
+    pub ticks: u64,
+    pub skipped_ticks: u64,
+}
+
+impl TickTiming {
+    /// Record the duration of a completed tick
+    pub fn record(&self, duration: Duration) {
+        let micros = duration.as_micros() as u64;
+        self.last_micros.store(micros, Ordering::Relaxed);
+        self.max_micros.fetch_max(micros, Ordering::Relaxed);
+        self.histogram.observe(duration);
+
+        // Only updated from one tick at a time, so load and store is fine
+        let ticks = self.ticks.fetch_add(1, Ordering::Relaxed);
+        let average = if ticks == 0 {
+            micros
+        } else {
+            (self.average_micros.load(Ordering::Relaxed) as f64 * (1.0 - AVERAGE_WEIGHT) + micros as f64 * AVERAGE_WEIGHT) as u64
+        };
+        self.average_micros.store(average, Ordering::Relaxed);
+    }
+
+    /// Record a tick that was skipped
+    pub fn record_skipped(&self) {
+        self.skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn report(&self, room: &str) -> RoomTickTiming {
+        RoomTickTiming {
+            room: room.to_owned(),
+            last_ms: self.last_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            average_ms: self.average_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            max_ms: self.max_micros.load(Ordering::Relaxed) as f64 / 1000.0,
+            ticks: self.ticks.load(Ordering::Relaxed),
+            skipped_ticks: self.skipped.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -5,10 +5,29 @@ use netsblox_vm::{compact_str::CompactString, std_util::AsyncKey};
 
 use super::*;
 
+/// Steps a headless room's VM once per physics step, instead of letting it run on its own,
+/// so the project keeps pace with the simulation however fast the simulation runs
+#[derive(Debug)]
+pub(crate) struct VMStepper {
+    step_tx: mpsc::Sender<()>,
+    done_rx: mpsc::Receiver<()>,
+}
+
+impl VMStepper {
+    /// Run one iteration of the VM and wait for it to finish, returns false if the VM has stopped
+    pub(crate) fn step(&self) -> bool {
+        self.step_tx.send(()).is_ok() && self.done_rx.recv().is_ok()
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct VMManager {
     vm_thread: OnceCell<JoinHandle<()>>,
     room: Weak<RoomData>,
+    /// Set once the project is loaded and started
+    pub(crate) started: Arc<AtomicBool>,
+    /// Set for headless rooms, which step the VM themselves
+    stepper: OnceCell<Mutex<VMStepper>>,
 }
 
 impl VMManager {
@@ -16,9 +35,16 @@ impl VMManager {
         Self {
             vm_thread: OnceCell::new(),
             room,
+            started: Arc::new(AtomicBool::new(false)),
+            stepper: OnceCell::new(),
         }
     }
 
+    /// Run one iteration of a headless room's VM, returns false if the VM is not stepped this way or has stopped
+    pub(crate) fn step(&self) -> bool {
+        self.stepper.get().is_some_and(|stepper| stepper.lock().unwrap().step())
+    }
+
     fn with_room<F, R>(&self, f: F) -> Option<R>
     where
         F: FnOnce(&RoomData) -> R,
@@ -41,6 +67,18 @@ impl VMManager {
             let robots = room.robots.clone();
             let is_alive = room.is_alive.clone();
             let environment = room.metadata.environment.clone();
+            let started = self.started.clone();
+            let events = room.events.clone();
+
+            // Headless rooms run the VM in lockstep with physics, other rooms let it run freely
+            let (step_rx, done_tx) = if room.metadata.headless {
+                let (step_tx, step_rx) = mpsc::channel();
+                let (done_tx, done_rx) = mpsc::channel();
+                self.stepper.set(Mutex::new(VMStepper { step_tx, done_rx })).unwrap();
+                (Some(step_rx), Some(done_tx))
+            } else {
+                (None, None)
+            };
 
             self.vm_thread.set(thread::spawn(move || {
                 tokio::runtime::Builder::new_current_thread()
@@ -54,7 +92,7 @@ impl VMManager {
                     let (project_name, role) = open_project(&project).unwrap_or_else(|_| panic!("failed to read file"));
                     let mut idle_sleeper = IdleAction::new(YIELDS_BEFORE_IDLE_SLEEP, Box::new(|| thread::sleep(IDLE_SLEEP_TIME)));
                     info!("Loading project {}", project_name);
-                    let system = Rc::new(StdSystem::new_async(DEFAULT_BASE_URL.to_owned().into(), Some(&project_name), Config {
+                    let system = Rc::new(StdSystem::new_async(config().network.cloud_base.clone().into(), Some(&project_name), Config {
                         request: Some(Rc::new(move |_mc, key, request: netsblox_vm::runtime::Request<'_, C, StdSystem<C>>,  _proc| {
                             match &request {
                                 netsblox_vm::runtime::Request::Rpc { host: _, service, rpc, args } => {
@@ -132,6 +170,7 @@ impl VMManager {
                         let mut proj = env.proj.borrow_mut(mc);
                         proj.input(mc, netsblox_vm::project::Input::Start);
                     });
+                    started.store(true, Ordering::Relaxed);
 
                     let mut last_collect_time = SHARED_CLOCK.read(netsblox_vm::runtime::Precision::Medium);
 
@@ -140,6 +179,12 @@ impl VMManager {
                         if !is_alive.load(Ordering::Relaxed) {
                             break;
                         }
+
+                        if let Some(step_rx) = &step_rx {
+                            if step_rx.recv().is_err() {
+                                break;
+                            }
+                        }
                         
                         if hibernating.load(Ordering::Relaxed) && hibernating_since.load(Ordering::Relaxed) < get_timestamp() + 2 {
                             sleep(Duration::from_millis(50)).await;
@@ -158,11 +203,16 @@ impl VMManager {
                                     if let ProjectStep::Error { error, proc } = &res {
                                         let entity = &*proc.get_call_stack().last().unwrap().entity.borrow();
                                         error!("\n>>> runtime error in entity {:?}: {:?}\n", entity.name, error);
+                                        metrics::VM_ERRORS.fetch_add(1, Ordering::Relaxed);
+                                        events.log(RoomEventKind::VmError { error: format!("{:?}", error.cause), entity: entity.name.to_string() });
                                         
                                         // TODO: Send error to clients
                                         let _msg = UpdateMessage::VMError(format!("{:?}", error.cause).to_string(), error.pos);
                                     }
-                                    idle_sleeper.consume(&res);
+                                    // Sleeping when idle would only slow down a lockstep run
+                                    if step_rx.is_none() {
+                                        idle_sleeper.consume(&res);
+                                    }
                                 }
                             });
 
@@ -172,6 +222,10 @@ impl VMManager {
                                 last_collect_time = SHARED_CLOCK.read(netsblox_vm::runtime::Precision::Medium);
                             }                            
                         }
+
+                        if let Some(done_tx) = &done_tx {
+                            let _ = done_tx.send(());
+                        }
                     }
                 });
             })).unwrap();
@@ -182,6 +236,9 @@ impl VMManager {
 
 impl Drop for VMManager {
     fn drop(&mut self) {
+        // A lockstep VM waits for its next step, which never comes once the stepper is gone
+        self.stepper.take();
+
         if let Some(handle) = self.vm_thread.take() {
             info!("Stopping VM thread");
             handle.join().unwrap();
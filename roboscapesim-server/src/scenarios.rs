@@ -1,10 +1,10 @@
-use std::{collections::BTreeMap, fs};
+use std::{collections::BTreeMap, fs, sync::atomic::{AtomicBool, Ordering}};
 
 use log::{error, info};
 use once_cell::sync::Lazy;
 use roboscapesim_common::api::EnvironmentInfo;
 use serde::{Serialize, Deserialize};
-use crate::{room::netsblox_api::Project, api::REQWEST_CLIENT};
+use crate::{room::netsblox_api::Project, api::REQWEST_CLIENT, config::{config, LimitsConfig}, simulation::PhysicsSettings};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Types of projects that can be loaded
@@ -25,6 +25,34 @@ pub struct LocalScenarioDef {
     pub creator: Option<String>,
     pub description: Option<String>,
     pub host: String,
+    /// Physics settings applied when a room is created with this scenario
+    #[serde(default)]
+    pub physics: Option<PhysicsSettings>,
+    /// Limits for rooms created with this scenario, in place of the server's limits
+    #[serde(default)]
+    pub limits: Option<ScenarioLimits>,
+}
+
+/// Limits set by a scenario, unset values use the server's limits
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScenarioLimits {
+    pub robots: Option<usize>,
+    pub dynamic_entities: Option<usize>,
+    pub kinematic_entities: Option<usize>,
+    pub visual_only_entities: Option<usize>,
+}
+
+impl ScenarioLimits {
+    /// Apply these limits over the given limits
+    pub fn apply_to(&self, limits: &LimitsConfig) -> LimitsConfig {
+        LimitsConfig {
+            robots: self.robots.unwrap_or(limits.robots),
+            dynamic_entities: self.dynamic_entities.unwrap_or(limits.dynamic_entities),
+            kinematic_entities: self.kinematic_entities.unwrap_or(limits.kinematic_entities),
+            visual_only_entities: self.visual_only_entities.unwrap_or(limits.visual_only_entities),
+        }
+    }
 }
 
 impl Into<EnvironmentInfo> for LocalScenarioDef {
@@ -44,12 +72,24 @@ pub static LOCAL_SCENARIOS: Lazy<BTreeMap<String, LocalScenarioDef>> = Lazy::new
     serde_json::from_str(DEFAULT_SCENARIOS_FILE).unwrap()
 });
 
+/// Get the local scenario definition for an environment name, if there is one
+pub fn get_local_scenario(environment: &str) -> Option<&'static LocalScenarioDef> {
+    LOCAL_SCENARIOS.get(&environment.to_lowercase())
+}
+
+/// Get the limits for rooms with an environment, the server's limits unless its scenario sets any
+pub fn get_environment_limits(environment: &str) -> LimitsConfig {
+    match get_local_scenario(environment).and_then(|scenario| scenario.limits.as_ref()) {
+        Some(limits) => limits.apply_to(&config().limits),
+        None => config().limits.clone(),
+    }
+}
+
 /// The default project to load if no project is specified
 pub const DEFAULT_PROJECT: &str = include_str!("../assets/scenarios/Default.xml");
 
-/// The base URL for the NetsBlox cloud server
-// TODO: make cloud URL configurable
-const CLOUD_BASE: &str = "https://cloud.netsblox.org";
+/// Whether environments may be paths to project files on disk, only enabled for batch mode
+pub static ALLOW_PROJECT_FILES: AtomicBool = AtomicBool::new(false);
 
 /// Load a project from a given environment name, or default to sample project if None
 pub async fn load_environment(environment: Option<String>) -> String {
@@ -60,7 +100,10 @@ pub async fn load_environment(environment: Option<String>) -> String {
     // First, check if environment is a project ID
     let environment: ProjectType = if let Some(env) = &environment {
         let env = env.to_owned();
-        if env.contains('/') {
+        if ALLOW_PROJECT_FILES.load(Ordering::Relaxed) && env.to_lowercase().ends_with(".xml") {
+            // Project file on disk (batch mode only)
+            ProjectType::LocalProject(env)
+        } else if env.contains('/') {
             // Assume it's a project ID
             ProjectType::RemoteProject(env)
         } else {
@@ -109,7 +152,7 @@ pub async fn get_project(project: &ProjectType) -> Result<String, String> {
     match project {
         ProjectType::RemoteProject(project_name) => {
             info!("Loading remote project {}", project_name);
-            let request = REQWEST_CLIENT.get(format!("{}/projects/user/{}", CLOUD_BASE, project_name)).send().await;
+            let request = REQWEST_CLIENT.get(format!("{}/projects/user/{}", config().network.cloud_base, project_name)).send().await;
             if request.is_err() {
                 Err(format!("failed to load project: {:?}", request.unwrap_err()))
             } else {
@@ -126,3 +169,24 @@ pub async fn get_project(project: &ProjectType) -> Result<String, String> {
         },
     }
 }
+
+#[test]
+fn test_scenario_limits() {
+    let scenario: LocalScenarioDef = serde_json::from_str(r#"{
+        "name": "Limited",
+        "host": "remote",
+        "path": "user/Limited",
+        "creator": null,
+        "description": null,
+        "limits": { "robots": 2, "dynamic_entities": 10 }
+    }"#).unwrap();
+
+    let limits = LimitsConfig::default();
+    let scenario_limits = scenario.limits.unwrap().apply_to(&limits);
+    assert_eq!(scenario_limits.robots, 2);
+    assert_eq!(scenario_limits.dynamic_entities, 10);
+    assert_eq!(scenario_limits.kinematic_entities, limits.kinematic_entities);
+    assert_eq!(scenario_limits.visual_only_entities, limits.visual_only_entities);
+
+    assert!(serde_json::from_str::<ScenarioLimits>(r#"{ "robot": 2 }"#).is_err());
+}
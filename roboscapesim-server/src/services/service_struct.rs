@@ -1,4 +1,4 @@
-use std::{hash::Hash, sync::{Arc, LazyLock}, time::Duration};
+use std::{hash::Hash, sync::{atomic::{AtomicBool, Ordering}, Arc, LazyLock}, time::Duration};
 
 use atomic_instant::AtomicInstant;
 use derivative::Derivative;
@@ -7,19 +7,19 @@ use iotscape::{IoTScapeServiceAsync, ServiceDefinition, Request};
 use log::{error, info, trace};
 use serde_json::Value;
 
+use crate::config::config;
 use crate::room::RoomData;
 use super::HandleMessageResult;
 
-static SERVER: LazyLock<String> = LazyLock::new(|| 
-    std::env::var("IOTSCAPE_SERVER").unwrap_or_else(|_| "52.73.65.98".to_string()));
-static PORT: LazyLock<String> = LazyLock::new(|| 
-    std::env::var("IOTSCAPE_PORT").unwrap_or_else(|_| "1978".to_string()));
-static ANNOUNCE_ENDPOINT: LazyLock<String> = LazyLock::new(|| 
-    std::env::var("IOTSCAPE_ANNOUNCE_ENDPOINT").unwrap_or_else(|_| "https://services.netsblox.org/routes/iotscape/announce".to_string()));
-static RESPONSE_ENDPOINT: LazyLock<String> = LazyLock::new(|| 
-    std::env::var("IOTSCAPE_RESPONSE_ENDPOINT").unwrap_or_else(|_| "https://services.netsblox.org/routes/iotscape/response".to_string()));
+static SERVER: LazyLock<String> = LazyLock::new(|| config().network.iotscape_server.clone());
+static PORT: LazyLock<String> = LazyLock::new(|| config().network.iotscape_port.to_string());
+static ANNOUNCE_ENDPOINT: LazyLock<String> = LazyLock::new(|| config().network.iotscape_announce_endpoint.clone());
+static RESPONSE_ENDPOINT: LazyLock<String> = LazyLock::new(|| config().network.iotscape_response_endpoint.clone());
 
 pub const DEFAULT_ANNOUNCE_PERIOD: Duration = Duration::from_secs(225);
+
+/// Whether services are announced to the IoTScape server, disabled for batch mode since its services are only used locally
+pub static ANNOUNCE_SERVICES: AtomicBool = AtomicBool::new(true);
 const MAX_UDP_RESPONSE_SIZE: usize = 500;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -76,20 +76,22 @@ impl ServiceInfo {
     pub async fn new(id: &str, definition: ServiceDefinition, service_type: ServiceType) -> Self {
         let service = Self::setup_service(definition, service_type, None);
 
-        if let Err(e) = service
-            .announce()
-            .await
-        {
-            error!("Could not announce service: {:?}", e);
-        }
-
-        let service2 = service.clone();
-        tokio::spawn(async move {
-            match service2.announce_http(&ANNOUNCE_ENDPOINT).await {
-                Ok(_) => {},
-                Err(e) => error!("Could not announce (HTTP) service: {:?}", e),
+        if ANNOUNCE_SERVICES.load(Ordering::Relaxed) {
+            if let Err(e) = service
+                .announce()
+                .await
+            {
+                error!("Could not announce service: {:?}", e);
             }
-        });
+
+            let service2 = service.clone();
+            tokio::spawn(async move {
+                match service2.announce_http(&ANNOUNCE_ENDPOINT).await {
+                    Ok(_) => {},
+                    Err(e) => error!("Could not announce (HTTP) service: {:?}", e),
+                }
+            });
+        }
 
         Self {
             id: id.to_owned(),
@@ -202,7 +204,7 @@ impl ServiceInfo {
         self.service.poll().await;
 
         // Re-announce to server regularly
-        if self.last_announce.elapsed() > self.announce_period {
+        if ANNOUNCE_SERVICES.load(Ordering::Relaxed) && self.last_announce.elapsed() > self.announce_period {
             if let Err(e) = self.service
                 .announce_lite()
                 .await {
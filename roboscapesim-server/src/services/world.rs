@@ -9,18 +9,21 @@ use rapier3d::prelude::AngVector;
 use roboscapesim_common::{UpdateMessage, VisualInfo, Shape};
 use serde_json::{Number, Value};
 
-use crate::{room::{clients::ClientsManager, RoomData}, services::{lidar::DEFAULT_LIDAR_CONFIGS, proximity::ProximityConfig, waypoint::WaypointConfig, *}, util::util::{bool_val, num_val, str_val, try_num_val}};
+use crate::{room::{clients::ClientsManager, events::RoomEventKind, RoomData}, services::{lidar::DEFAULT_LIDAR_CONFIGS, proximity::ProximityConfig, waypoint::WaypointConfig, *}, util::util::{bool_val, num_val, str_val, try_num_val}};
 
 use super::{service_struct::{Service, ServiceType, ServiceInfo}, HandleMessageResult};
 
-mod consts;
-use consts::{DYNAMIC_ENTITY_LIMIT, KINEMATIC_ENTITY_LIMIT, ROBOT_LIMIT, AVAILABLETEXTURES, AVAILABLEMESHES, MAX_COORD};
+pub(crate) mod consts;
+use consts::{AVAILABLETEXTURES, AVAILABLEMESHES, MAX_COORD};
 
 mod util;
 use util::{parse_visual_info, parse_visual_info_color, parse_rotation};
 
 mod handlers;
-use handlers::{handle_add_block, handle_add_robot, handle_add_sensor, list_entities, remove_entity, show_text};
+use handlers::{handle_add_block, handle_add_robot, handle_add_sensor, handle_animate_entity, list_entities, remove_entity, show_text};
+
+mod queries;
+use queries::{entities_in_box, entities_in_radius, raycast};
 
 mod config;
 use config::get_service_definition;
@@ -44,6 +47,7 @@ impl Service for WorldService {
 
         match msg.function.as_str() {
             "reset" => {
+                room.events.log(RoomEventKind::RoomReset { username: None });
                 room.reset();
             },
             "showText" => {
@@ -61,12 +65,21 @@ impl Service for WorldService {
                 ClientsManager::send_to_clients(&UpdateMessage::ClearText, room.clients_manager.sockets.iter().map(|p| p.clone().into_iter()).flatten());
             },
             "addEntity" => {
-                response = vec![Self::add_entity(None, &msg.params, room).into()];
+                match Self::add_entity(None, &msg.params, room) {
+                    Ok(id) => response = vec![id.into()],
+                    Err(e) => return self.respond_error(msg, e),
+                }
             },
             "instantiateEntities" => {
                 if msg.params[0].is_array() {
                     let objs = msg.params[0].as_array().unwrap();
-                    response = objs.iter().filter_map(|obj| obj.as_array().and_then(|obj| Self::add_entity(obj[0].as_str().map(|s| s.to_owned()), &obj.iter().skip(1).map(|o| o.to_owned()).collect(), room))).collect();
+                    for obj in objs.iter().filter_map(|obj| obj.as_array()) {
+                        match Self::add_entity(obj[0].as_str().map(|s| s.to_owned()), &obj.iter().skip(1).map(|o| o.to_owned()).collect(), room) {
+                            Ok(Some(id)) => response.push(id),
+                            Ok(None) => {},
+                            Err(e) => return self.respond_error(msg, e),
+                        }
+                    }
                 }
             },
             "listEntities" => {
@@ -77,14 +90,20 @@ impl Service for WorldService {
                     return (Ok(SimpleValue::Bool(false)), None);
                 }
 
-                response = handle_add_block(room, msg);
+                match handle_add_block(room, msg) {
+                    Ok(r) => response = r,
+                    Err(e) => return self.respond_error(msg, e),
+                }
             },
             "addRobot" => {
                 if msg.params.len() < 3 {
                     return (Ok(SimpleValue::Bool(false)), None);
                 }
 
-                response = handle_add_robot(room, msg);
+                match handle_add_robot(room, msg) {
+                    Ok(r) => response = r,
+                    Err(e) => return self.respond_error(msg, e),
+                }
             },
             "addSensor" => {
                 if msg.params.len() < 2 {
@@ -99,6 +118,79 @@ impl Service for WorldService {
             "listMeshes" => {
                 response = AVAILABLEMESHES.iter().map(|s| Value::from(*s)).collect::<Vec<_>>();
             },
+            "animateEntity" => {
+                if msg.params.len() < 3 {
+                    return (Ok(SimpleValue::Bool(false)), None);
+                }
+
+                if let Err(e) = handle_animate_entity(room, msg) {
+                    return self.respond_error(msg, e);
+                }
+            },
+            "stopAnimation" => {
+                if msg.params.is_empty() {
+                    return (Ok(SimpleValue::Bool(false)), None);
+                }
+
+                room.stop_animation(&str_val(&msg.params[0]));
+            },
+            "setScore" => {
+                if msg.params.len() < 2 {
+                    return (Ok(SimpleValue::Bool(false)), None);
+                }
+
+                room.scores.insert(str_val(&msg.params[0]), num_val(&msg.params[1]) as f64);
+            },
+            "setGravity" => {
+                if msg.params.len() < 3 {
+                    return (Ok(SimpleValue::Bool(false)), None);
+                }
+
+                let gravity = vector![num_val(&msg.params[0]), num_val(&msg.params[1]), num_val(&msg.params[2])];
+                if let Err(e) = room.sim.set_gravity(gravity) {
+                    return self.respond_error(msg, e);
+                }
+            },
+            "getGravity" => {
+                let gravity = room.sim.get_gravity();
+                response = vec![gravity.x.into(), gravity.y.into(), gravity.z.into()];
+            },
+            "setSolverIterations" => {
+                if msg.params.is_empty() {
+                    return (Ok(SimpleValue::Bool(false)), None);
+                }
+
+                if let Err(e) = room.sim.set_solver_iterations(num_val(&msg.params[0]).round().max(0.0) as usize) {
+                    return self.respond_error(msg, e);
+                }
+            },
+            "setCCDSubsteps" => {
+                if msg.params.is_empty() {
+                    return (Ok(SimpleValue::Bool(false)), None);
+                }
+
+                if let Err(e) = room.sim.set_ccd_substeps(num_val(&msg.params[0]).round().max(0.0) as usize) {
+                    return self.respond_error(msg, e);
+                }
+            },
+            "raycast" => {
+                match raycast(room, msg) {
+                    Ok(r) => response = r,
+                    Err(e) => return self.respond_error(msg, e),
+                }
+            },
+            "entitiesInRadius" => {
+                match entities_in_radius(room, msg) {
+                    Ok(r) => response = r,
+                    Err(e) => return self.respond_error(msg, e),
+                }
+            },
+            "entitiesInBox" => {
+                match entities_in_box(room, msg) {
+                    Ok(r) => response = r,
+                    Err(e) => return self.respond_error(msg, e),
+                }
+            },
             "listUsers" => {
                 response = room.clients_manager.sockets.iter().map(|kvp| Value::from(kvp.key().clone())).collect::<Vec<_>>();
             },
@@ -124,10 +216,18 @@ impl WorldService {
         }) as Box<dyn Service>
     }
 
-    fn add_entity(_desired_name: Option<String>, params: &Vec<Value>, room: &RoomData) -> Option<Value> {
+    /// Send an error response to a request
+    fn respond_error(&self, msg: &Request, error: String) -> HandleMessageResult {
+        info!("{} failed: {}", msg.function, error);
+        self.service_info.enqueue_response_to(msg, Err(error.clone()));
+        (Err(error), None)
+    }
+
+    /// Add an entity from its type, position, rotation and options, erroring if the room's limit for it has been reached
+    fn add_entity(_desired_name: Option<String>, params: &Vec<Value>, room: &RoomData) -> Result<Option<Value>, String> {
 
         if params.len() < 6 {
-            return None;
+            return Ok(None);
         }
 
         // TODO: use ids to replace existing entities or recreate with same id (should it keep room part consistent?)
@@ -135,9 +235,8 @@ impl WorldService {
         let mut entity_type = str_val(&params[0]).to_lowercase();
 
         // Check limits
-        if entity_type == "robot" && room.robots.len() >= ROBOT_LIMIT {
-            info!("Robot limit already reached");
-            return Some(Value::Bool(false));
+        if entity_type == "robot" {
+            room.check_robot_limit()?;
         }
 
         let x = num_val(&params[1]).clamp(-MAX_COORD, MAX_COORD);
@@ -216,11 +315,8 @@ impl WorldService {
         let parsed_visualinfo = parse_visual_info(&options, shape).unwrap_or(VisualInfo::Color(1.0, 1.0, 1.0, shape));
 
         if entity_type != "robot" {
-            if (!kinematic && room.count_dynamic() >= DYNAMIC_ENTITY_LIMIT) || ((kinematic || entity_type == "trigger") && room.count_kinematic() >= KINEMATIC_ENTITY_LIMIT) {
-                info!("Entity limit already reached");
-                return Some(Value::Bool(false));
-            }
-        }        
+            room.check_entity_limit(kinematic || entity_type == "trigger", visual_only && entity_type != "trigger")?;
+        }
 
         // Number part of name
         let name_num =  room.next_object_id.load(Ordering::Relaxed).to_string();
@@ -263,9 +359,9 @@ impl WorldService {
         if let Some(id) = id {
             // Increment only if successful
             room.next_object_id.fetch_add(1, Ordering::Relaxed);
-            return Some(id.into());
+            return Ok(Some(id.into()));
         }
         
-        None
+        Ok(None)
     }
 }
@@ -368,6 +368,249 @@ pub fn get_service_definition(id: &str) -> ServiceDefinition {
         },
     );
 
+    definition.methods.insert(
+        "setGravity".to_owned(),
+        MethodDescription {
+            documentation: Some("Set the gravity vector of the World, in m/s^2".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "x".to_owned(),
+                    documentation: Some("X component".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "y".to_owned(),
+                    documentation: Some("Y component".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "z".to_owned(),
+                    documentation: Some("Z component".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: None,
+                r#type: vec![],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "getGravity".to_owned(),
+        MethodDescription {
+            documentation: Some("Get the gravity vector of the World, in m/s^2".to_owned()),
+            params: vec![],
+            returns: MethodReturns {
+                documentation: Some("Gravity vector".to_owned()),
+                r#type: vec!["number".to_owned(), "number".to_owned(), "number".to_owned()],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "setSolverIterations".to_owned(),
+        MethodDescription {
+            documentation: Some("Set the number of physics solver iterations per step, higher values improve stability of large stacks at a performance cost".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "iterations".to_owned(),
+                    documentation: Some("Number of iterations (1-32)".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: None,
+                r#type: vec![],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "setCCDSubsteps".to_owned(),
+        MethodDescription {
+            documentation: Some("Set the maximum number of continuous collision detection substeps per step, higher values help fast objects avoid passing through thin ones".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "substeps".to_owned(),
+                    documentation: Some("Number of substeps (1-16)".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: None,
+                r#type: vec![],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "raycast".to_owned(),
+        MethodDescription {
+            documentation: Some("Cast a ray and find the first Entity it hits".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "origin".to_owned(),
+                    documentation: Some("Start of ray as [x, y, z]".to_owned()),
+                    r#type: "array".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "direction".to_owned(),
+                    documentation: Some("Direction of ray as [x, y, z]".to_owned()),
+                    r#type: "array".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "maxDistance".to_owned(),
+                    documentation: Some("Maximum distance to check".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: true,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: Some("ID of Entity hit and distance to it, or empty list if nothing was hit".to_owned()),
+                r#type: vec!["string".to_owned(), "number".to_owned()],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "entitiesInRadius".to_owned(),
+        MethodDescription {
+            documentation: Some("List Entities within a radius of a point".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "point".to_owned(),
+                    documentation: Some("Center as [x, y, z]".to_owned()),
+                    r#type: "array".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "radius".to_owned(),
+                    documentation: Some("Radius to check".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: Some("IDs of Entities found".to_owned()),
+                r#type: vec!["array".to_owned()],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "entitiesInBox".to_owned(),
+        MethodDescription {
+            documentation: Some("List Entities within an axis-aligned box".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "corner1".to_owned(),
+                    documentation: Some("First corner as [x, y, z]".to_owned()),
+                    r#type: "array".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "corner2".to_owned(),
+                    documentation: Some("Opposite corner as [x, y, z]".to_owned()),
+                    r#type: "array".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: Some("IDs of Entities found".to_owned()),
+                r#type: vec!["array".to_owned()],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "animateEntity".to_owned(),
+        MethodDescription {
+            documentation: Some("Move a kinematic Entity along a path of waypoints, carrying along anything riding on it".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "entity".to_owned(),
+                    documentation: Some("ID of Entity to animate".to_owned()),
+                    r#type: "string".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "waypoints".to_owned(),
+                    documentation: Some("List of [x, y, z] positions to move through".to_owned()),
+                    r#type: "array".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "speed".to_owned(),
+                    documentation: Some("Speed to move at, or list of speeds to move towards each waypoint".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "mode".to_owned(),
+                    documentation: Some("once, loop, or pingpong (default once)".to_owned()),
+                    r#type: "string".to_owned(),
+                    optional: true,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: None,
+                r#type: vec![],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "stopAnimation".to_owned(),
+        MethodDescription {
+            documentation: Some("Stop an Entity's animation, leaving it at its current position".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "entity".to_owned(),
+                    documentation: Some("ID of Entity".to_owned()),
+                    r#type: "string".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: None,
+                r#type: vec![],
+            },
+        },
+    );
+
+    definition.methods.insert(
+        "setScore".to_owned(),
+        MethodDescription {
+            documentation: Some("Set a named score, included in batch mode reports".to_owned()),
+            params: vec![
+                MethodParam {
+                    name: "name".to_owned(),
+                    documentation: Some("Name of score".to_owned()),
+                    r#type: "string".to_owned(),
+                    optional: false,
+                },
+                MethodParam {
+                    name: "value".to_owned(),
+                    documentation: Some("Value of score".to_owned()),
+                    r#type: "number".to_owned(),
+                    optional: false,
+                },
+            ],
+            returns: MethodReturns {
+                documentation: None,
+                r#type: vec![],
+            },
+        },
+    );
+
     definition.events.insert(
         "reset".to_owned(),
         EventDescription { params: vec![] },
@@ -1,9 +1,3 @@
-pub const DYNAMIC_ENTITY_LIMIT: usize = 25;
-pub const KINEMATIC_ENTITY_LIMIT: usize = 100;
-// TODO: Give visual only entities a separate limit
-pub const VISUAL_ONLY_ENTITY_LIMIT: usize = 250;
-pub const ROBOT_LIMIT: usize = 4;
-
 pub const MAX_COORD: f32 = 10000.0;
 
 pub const AVAILABLETEXTURES: [&str; 15] = [
@@ -9,7 +9,7 @@ use rapier3d::prelude::AngVector;
 use roboscapesim_common::{UpdateMessage, VisualInfo, Shape};
 use serde_json::{Number, Value};
 
-use crate::{room::{clients::ClientsManager, RoomData}, services::{lidar::DEFAULT_LIDAR_CONFIGS, proximity::ProximityConfig, waypoint::WaypointConfig, world::{consts::{DYNAMIC_ENTITY_LIMIT, KINEMATIC_ENTITY_LIMIT, MAX_COORD, ROBOT_LIMIT}, util::{parse_visual_info, parse_visual_info_color}}, EntityService, LIDARService, PositionService, ProximityService, ServiceType, WaypointService}, util::util::{bool_val, num_val, str_val, try_num_val}};
+use crate::{room::{animation::{AnimationMode, PathAnimation}, clients::ClientsManager, RoomData}, services::{lidar::DEFAULT_LIDAR_CONFIGS, proximity::ProximityConfig, waypoint::WaypointConfig, world::{consts::MAX_COORD, util::{parse_vector, parse_visual_info, parse_visual_info_color}}, EntityService, LIDARService, PositionService, ProximityService, ServiceType, WaypointService}, util::util::{bool_val, num_val, str_val, try_num_val}};
 
 
 pub fn handle_add_sensor(room: &RoomData, msg: &Request) -> Vec<Value> {
@@ -112,22 +112,19 @@ pub fn handle_add_sensor(room: &RoomData, msg: &Request) -> Vec<Value> {
     }
 }
 
-pub fn handle_add_robot(room: &RoomData, msg: &Request) -> Vec<Value> {
-    if room.robots.len() >= ROBOT_LIMIT {
-        info!("Robot limit already reached");
-        vec![false.into()]
-    } else {
-        let x = num_val(&msg.params[0]);
-        let y = num_val(&msg.params[1]);
-        let z = num_val(&msg.params[2]);
-        let heading = num_val(msg.params.get(3).unwrap_or(&serde_json::Value::Number(Number::from(0)))) * PI / 180.0;
-    
-        let id = RoomData::add_robot(room, vector![x, y, z], UnitQuaternion::from_axis_angle(&Vector3::y_axis(), heading), false, None, None);
-        vec![id.into()]
-    }
+pub fn handle_add_robot(room: &RoomData, msg: &Request) -> Result<Vec<Value>, String> {
+    room.check_robot_limit()?;
+
+    let x = num_val(&msg.params[0]);
+    let y = num_val(&msg.params[1]);
+    let z = num_val(&msg.params[2]);
+    let heading = num_val(msg.params.get(3).unwrap_or(&serde_json::Value::Number(Number::from(0)))) * PI / 180.0;
+
+    let id = RoomData::add_robot(room, vector![x, y, z], UnitQuaternion::from_axis_angle(&Vector3::y_axis(), heading), false, None, None);
+    Ok(vec![id.into()])
 }
 
-pub fn handle_add_block(room: &RoomData, msg: &Request) -> Vec<Value> {
+pub fn handle_add_block(room: &RoomData, msg: &Request) -> Result<Vec<Value>, String> {
     {
         let x = num_val(&msg.params[0]).clamp(-MAX_COORD, MAX_COORD);
         let y = num_val(&msg.params[1]).clamp(-MAX_COORD, MAX_COORD);
@@ -168,13 +165,10 @@ pub fn handle_add_block(room: &RoomData, msg: &Request) -> Vec<Value> {
             parse_visual_info_color(visualinfo, Shape::Box)
         };
 
-        if (!kinematic && room.count_dynamic() >= DYNAMIC_ENTITY_LIMIT) || (kinematic && room.count_kinematic() >= KINEMATIC_ENTITY_LIMIT) {
-            info!("Entity limit already reached");
-            vec![false.into()]
-        } else {
-            let id = RoomData::add_shape(room, &name, vector![x, y, z], AngVector::new(0.0, heading, 0.0), Some(parsed_visualinfo), Some(vector![width, height, depth]), kinematic, false);
-            vec![id.into()]
-        }
+        room.check_entity_limit(kinematic, false)?;
+
+        let id = RoomData::add_shape(room, &name, vector![x, y, z], AngVector::new(0.0, heading, 0.0), Some(parsed_visualinfo), Some(vector![width, height, depth]), kinematic, false);
+        Ok(vec![id.into()])
     }
 }
 
@@ -241,4 +235,25 @@ pub fn show_text(room: &RoomData, msg: &Request) -> Option<(Result<SimpleValue,
     ClientsManager::send_to_clients(&UpdateMessage::DisplayText(id, text, timeout), room.clients_manager.sockets.iter().map(|p| p.clone().into_iter()).flatten());
 
     None
-}
\ No newline at end of file
+}
+
+/// Start moving a kinematic entity through waypoints (entity, waypoints, speed or speeds, mode)
+pub fn handle_animate_entity(room: &RoomData, msg: &Request) -> Result<(), String> {
+    let entity = str_val(&msg.params[0]);
+
+    let waypoints = msg.params[1].as_array()
+        .ok_or("Waypoints must be a list of [x, y, z] positions")?
+        .iter()
+        .map(|w| parse_vector(w).map(|w| w.map(|c| c.clamp(-MAX_COORD, MAX_COORD))).ok_or("Waypoints must be a list of [x, y, z] positions"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let speeds = match &msg.params[2] {
+        Value::Array(speeds) => speeds.iter().map(num_val).collect(),
+        speed => vec![num_val(speed)],
+    };
+
+    let mode_name = msg.params.get(3).map(str_val).unwrap_or_default();
+    let mode = AnimationMode::parse(&mode_name).ok_or(format!("Unknown animation mode {mode_name}, expected once, loop, or pingpong"))?;
+
+    room.start_animation(&entity, PathAnimation::new(waypoints, speeds, mode)?)
+}
@@ -0,0 +1,75 @@
+use std::collections::BTreeSet;
+
+use iotscape::Request;
+use rapier3d::prelude::{Ball, Cuboid, Isometry, QueryFilter, Ray};
+use serde_json::Value;
+
+use crate::{room::RoomData, util::util::num_val};
+
+use super::{consts::MAX_COORD, util::parse_vector};
+
+/// Cast a ray from origin in direction, returns the name of the first entity hit and the distance to it, or an empty list if nothing was hit
+pub fn raycast(room: &RoomData, msg: &Request) -> Result<Vec<Value>, String> {
+    let origin = msg.params.first().and_then(parse_vector).ok_or("Origin must be a list of three numbers")?;
+    let direction = msg.params.get(1).and_then(parse_vector).ok_or("Direction must be a list of three numbers")?;
+    let max_distance = msg.params.get(2).map(num_val).unwrap_or(MAX_COORD).clamp(0.0, MAX_COORD * 2.0);
+
+    let direction = direction.try_normalize(f32::EPSILON).ok_or("Direction must not be zero")?;
+    let ray = Ray::new(origin.into(), direction);
+
+    let hit = room.sim.with_query_pipeline(Some(QueryFilter::default().exclude_sensors()), |query_pipeline| {
+        query_pipeline.cast_ray(&ray, max_distance, true)
+    });
+
+    Ok(match hit {
+        Some((handle, distance)) => vec![room.get_entity_name_from_collider(handle).unwrap_or_default().into(), distance.into()],
+        None => vec![],
+    })
+}
+
+/// List the entities intersecting a sphere
+pub fn entities_in_radius(room: &RoomData, msg: &Request) -> Result<Vec<Value>, String> {
+    let center = msg.params.first().and_then(parse_vector).ok_or("Point must be a list of three numbers")?;
+    let radius = msg.params.get(1).map(num_val).unwrap_or_default();
+
+    if !radius.is_finite() || radius <= 0.0 {
+        return Err("Radius must be a positive number".to_owned());
+    }
+
+    let shape = Ball::new(radius.min(MAX_COORD));
+    let handles = room.sim.with_query_pipeline(Some(QueryFilter::default().exclude_sensors()), |query_pipeline| {
+        query_pipeline.intersect_shape(Isometry::translation(center.x, center.y, center.z), &shape).map(|(handle, _)| handle).collect::<Vec<_>>()
+    });
+
+    Ok(entity_names(room, handles))
+}
+
+/// List the entities intersecting an axis-aligned box given by two opposite corners
+pub fn entities_in_box(room: &RoomData, msg: &Request) -> Result<Vec<Value>, String> {
+    let corner1 = msg.params.first().and_then(parse_vector).ok_or("First corner must be a list of three numbers")?;
+    let corner2 = msg.params.get(1).and_then(parse_vector).ok_or("Second corner must be a list of three numbers")?;
+
+    let center = (corner1 + corner2) / 2.0;
+    let half_extents = (corner1 - corner2).abs() / 2.0;
+
+    if half_extents.iter().any(|e| !e.is_finite() || *e <= 0.0) {
+        return Err("Box must have a positive size on every axis".to_owned());
+    }
+
+    let shape = Cuboid::new(half_extents);
+    let handles = room.sim.with_query_pipeline(Some(QueryFilter::default().exclude_sensors()), |query_pipeline| {
+        query_pipeline.intersect_shape(Isometry::translation(center.x, center.y, center.z), &shape).map(|(handle, _)| handle).collect::<Vec<_>>()
+    });
+
+    Ok(entity_names(room, handles))
+}
+
+/// Get the unique, sorted names of the entities the colliders belong to
+fn entity_names(room: &RoomData, handles: Vec<rapier3d::prelude::ColliderHandle>) -> Vec<Value> {
+    handles.into_iter()
+        .filter_map(|handle| room.get_entity_name_from_collider(handle))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(Value::from)
+        .collect()
+}
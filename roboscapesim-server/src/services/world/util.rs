@@ -26,6 +26,14 @@ pub fn parse_rotation(rotation: &Value) -> nalgebra::Matrix<f32, nalgebra::Const
     rotation
 }
 
+/// Parse a 3D vector from a list of three numbers
+pub fn parse_vector(value: &Value) -> Option<nalgebra::Vector3<f32>> {
+    match value {
+        serde_json::Value::Array(a) if a.len() >= 3 => Some(nalgebra::vector![num_val(&a[0]), num_val(&a[1]), num_val(&a[2])]),
+        _ => None,
+    }
+}
+
 pub fn parse_visual_info(options: &BTreeMap<String, Value>, shape: Shape) -> Option<VisualInfo> {
     if options.len() == 0 {
         return None;
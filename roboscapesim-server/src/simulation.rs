@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{num::NonZeroUsize, sync::Arc};
 
 #[cfg(feature = "no_deadlocks")]
 use no_deadlocks::{Mutex, RwLock};
@@ -8,6 +8,7 @@ use std::sync::{Mutex, RwLock};
 use dashmap::{DashMap, DashSet};
 use nalgebra::Vector3;
 use rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::robot::RobotData;
 
@@ -15,7 +16,7 @@ use crate::robot::RobotData;
 pub struct Simulation {
     pub rigid_body_set: Arc<RwLock<RigidBodySet>>,
     pub collider_set: Arc<RwLock<ColliderSet>>,
-    pub gravity: Vector3<f32>,
+    pub gravity: Arc<RwLock<Vector3<f32>>>,
     pub integration_parameters: Arc<RwLock<IntegrationParameters>>,
     pub physics_pipeline: Arc<Mutex<PhysicsPipeline>>,
     pub island_manager: Arc<Mutex<IslandManager>>,
@@ -32,13 +33,32 @@ pub struct Simulation {
 
 pub const SCALE: f32 = 3.0;
 
+/// Maximum magnitude of gravity allowed, in m/s^2
+pub const MAX_GRAVITY: f32 = 100.0;
+/// Maximum number of solver iterations allowed
+pub const MAX_SOLVER_ITERATIONS: usize = 32;
+/// Maximum number of CCD substeps allowed
+pub const MAX_CCD_SUBSTEPS: usize = 16;
+
+/// Physics settings that can be changed per room, unset values are left unchanged
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhysicsSettings {
+    /// Gravity vector in m/s^2
+    #[serde(default)]
+    pub gravity: Option<[f32; 3]>,
+    #[serde(default)]
+    pub solver_iterations: Option<usize>,
+    #[serde(default)]
+    pub ccd_substeps: Option<usize>,
+}
+
 impl Simulation {
     /// Instantiate the simulation objects with default settings
     pub fn new() -> Simulation {
         Simulation {
             rigid_body_set: Arc::new(RwLock::new(RigidBodySet::new())),
             collider_set: Arc::new(RwLock::new(ColliderSet::new())),
-            gravity: vector![0.0, -9.81 * SCALE, 0.0],
+            gravity: Arc::new(RwLock::new(vector![0.0, -9.81 * SCALE, 0.0])),
 //            integration_parameters: Arc::new(RwLock::new(IntegrationParameters { max_ccd_substeps: 2, max_stabilization_iterations: 6, max_velocity_friction_iterations: 10, max_velocity_iterations: 14, allowed_linear_error: 0.002, prediction_distance: 0.0015, min_island_size: 64, ..Default::default() })),
             integration_parameters: Arc::new(RwLock::new(IntegrationParameters { max_ccd_substeps: 2, ..Default::default() })),
             physics_pipeline: Arc::new(Mutex::new(PhysicsPipeline::new())),
@@ -62,7 +82,7 @@ impl Simulation {
         
         // Run physics
         self.physics_pipeline.lock().unwrap().step(
-            &self.gravity,
+            &self.gravity.read().unwrap(),
             &self.integration_parameters.read().unwrap(),
             &mut self.island_manager.lock().unwrap(),
             &mut self.broad_phase.lock().unwrap(),
@@ -77,6 +97,102 @@ impl Simulation {
           );    
     }
 
+    /// Get gravity in m/s^2
+    pub fn get_gravity(&self) -> Vector3<f32> {
+        *self.gravity.read().unwrap() / SCALE
+    }
+
+    /// Set gravity in m/s^2
+    pub fn set_gravity(&self, gravity: Vector3<f32>) -> Result<(), String> {
+        Self::validate_gravity(&gravity)?;
+        *self.gravity.write().unwrap() = gravity * SCALE;
+        self.wake_all();
+        Ok(())
+    }
+
+    /// Set the number of solver iterations used per step
+    pub fn set_solver_iterations(&self, iterations: usize) -> Result<(), String> {
+        Self::validate_solver_iterations(iterations)?;
+        self.integration_parameters.write().unwrap().num_solver_iterations = NonZeroUsize::new(iterations).unwrap();
+        Ok(())
+    }
+
+    /// Get the number of solver iterations used per step
+    pub fn get_solver_iterations(&self) -> usize {
+        self.integration_parameters.read().unwrap().num_solver_iterations.get()
+    }
+
+    /// Set the maximum number of CCD substeps used per step
+    pub fn set_ccd_substeps(&self, substeps: usize) -> Result<(), String> {
+        Self::validate_ccd_substeps(substeps)?;
+        self.integration_parameters.write().unwrap().max_ccd_substeps = substeps;
+        Ok(())
+    }
+
+    /// Get the maximum number of CCD substeps used per step
+    pub fn get_ccd_substeps(&self) -> usize {
+        self.integration_parameters.read().unwrap().max_ccd_substeps
+    }
+
+    /// Apply physics settings, nothing is changed if any value is invalid
+    pub fn apply_settings(&self, settings: &PhysicsSettings) -> Result<(), String> {
+        if let Some(gravity) = settings.gravity {
+            Self::validate_gravity(&gravity.into())?;
+        }
+
+        if let Some(iterations) = settings.solver_iterations {
+            Self::validate_solver_iterations(iterations)?;
+        }
+
+        if let Some(substeps) = settings.ccd_substeps {
+            Self::validate_ccd_substeps(substeps)?;
+        }
+
+        if let Some(gravity) = settings.gravity {
+            self.set_gravity(gravity.into())?;
+        }
+
+        if let Some(iterations) = settings.solver_iterations {
+            self.set_solver_iterations(iterations)?;
+        }
+
+        if let Some(substeps) = settings.ccd_substeps {
+            self.set_ccd_substeps(substeps)?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_gravity(gravity: &Vector3<f32>) -> Result<(), String> {
+        if !gravity.iter().all(|g| g.is_finite()) || gravity.norm() > MAX_GRAVITY {
+            return Err(format!("Gravity must be a finite vector with magnitude at most {MAX_GRAVITY} m/s^2"));
+        }
+        Ok(())
+    }
+
+    fn validate_solver_iterations(iterations: usize) -> Result<(), String> {
+        if !(1..=MAX_SOLVER_ITERATIONS).contains(&iterations) {
+            return Err(format!("Solver iterations must be between 1 and {MAX_SOLVER_ITERATIONS}"));
+        }
+        Ok(())
+    }
+
+    fn validate_ccd_substeps(substeps: usize) -> Result<(), String> {
+        if !(1..=MAX_CCD_SUBSTEPS).contains(&substeps) {
+            return Err(format!("CCD substeps must be between 1 and {MAX_CCD_SUBSTEPS}"));
+        }
+        Ok(())
+    }
+
+    /// Wake all dynamic bodies, so resting bodies respond to changed settings
+    fn wake_all(&self) {
+        for (_, body) in self.rigid_body_set.write().unwrap().iter_mut() {
+            if body.is_dynamic() {
+                body.wake_up(true);
+            }
+        }
+    }
+
     /// Remove all parts of a robot from the simulation
     pub fn cleanup_robot(&self, r: &RobotData) {
         // Clean up robot parts
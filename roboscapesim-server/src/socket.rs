@@ -6,8 +6,8 @@ use once_cell::sync::Lazy;
 
 use async_tungstenite::{WebSocketReceiver, WebSocketSender, tungstenite::Message};
 use async_listen::ListenExt;
-use roboscapesim_common::{ClientMessage, UpdateMessage};
-use std::sync::Arc;
+use roboscapesim_common::{compact::merge_compact_updates, ClientMessage, UpdateMessage, CAPABILITY_COMPACT_UPDATES, MIN_CLIENT_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use std::sync::{atomic::{AtomicBool, AtomicU32, Ordering}, Arc};
 
 #[cfg(feature = "no_deadlocks")]
 use no_deadlocks::Mutex;
@@ -19,14 +19,10 @@ use crossbeam_channel::{Sender, Receiver, self};
 use tokio::time::{Duration, sleep};
 use futures::{SinkExt, FutureExt};
 
-use crate::{CLIENTS, room::management::join_room};
+use crate::{config::config, metrics, CLIENTS, replay::{control_replay, start_replay}, room::{clients::ClientsManager, management::{join_room, resume_session}}};
 
 /// Local WebSocket port number
-pub static LOCAL_WS_PORT: Lazy<u16> = Lazy::new(|| std::env::var("LOCAL_WS_PORT")
-    .unwrap_or_else(|_| "5000".to_string())
-    .parse::<u16>()
-    .expect("PORT must be a number")
-);
+pub static LOCAL_WS_PORT: Lazy<u16> = Lazy::new(|| config().network.ws_port);
 
 #[derive(Derivative)]
 #[derivative(Debug)]
@@ -43,6 +39,10 @@ pub struct SocketInfo {
     pub sink: Arc<Mutex<WebSocketSender<TcpStream>>>,
     #[derivative(Debug = "ignore")]
     pub stream: Arc<Mutex<WebSocketReceiver<TcpStream>>>,
+    /// Protocol version sent by the client in `ClientMessage::Hello`, 0 if it has not sent one
+    pub protocol_version: AtomicU32,
+    /// Whether the client wants `UpdateMessage::CompactUpdate` instead of incremental `UpdateMessage::Update`
+    pub compact_updates: AtomicBool,
 }
 
 pub async fn accept_connection(tcp_stream: TcpStream) -> Result<u128, String> {
@@ -77,6 +77,8 @@ pub async fn accept_connection(tcp_stream: TcpStream) -> Result<u128, String> {
         rx1, 
         sink: Arc::new(Mutex::new(sink)),
         stream: Arc::new(Mutex::new(stream)),
+        protocol_version: AtomicU32::new(0),
+        compact_updates: AtomicBool::new(false),
     });
 
     info!("Connected clients: {}", CLIENTS.len());
@@ -103,27 +105,62 @@ pub async fn ws_rx() {
                             break;
                         },
                         Message::Text(msg) => {
+                            metrics::WS_BYTES_IN.fetch_add(msg.len() as u64, Ordering::Relaxed);
                             if let Ok(msg) = serde_json::from_str::<ClientMessage>(&msg) {
                                 deserialized_msg = Some(msg);
                             } 
                         },
                         Message::Binary(msg) => {
+                            metrics::WS_BYTES_IN.fetch_add(msg.len() as u64, Ordering::Relaxed);
                             if let Ok(msg) = rmp_serde::from_slice(msg.iter().as_slice()) {
                                 deserialized_msg = Some(msg);
                             } 
                         },
-                        _ => {}
+                        _ => continue,
+                    }
+
+                    let version = client.protocol_version.load(Ordering::Relaxed);
+                    if let Some(e) = protocol_error(deserialized_msg.as_ref(), version) {
+                        info!("Client {} sent a message unsupported with protocol version {}", client.key(), version);
+                        client.tx.send(UpdateMessage::FatalError(e)).unwrap();
+                        continue;
                     }
 
                     if let Some(msg) = deserialized_msg {
                         match msg {
-                            ClientMessage::JoinRoom(id, username, password) => {
-                                if let Err(e) = join_room(&username, &(password.unwrap_or_default()), client.key().to_owned(), &id){
-                                    error!("Error joining room: {:?}", e);
+                            ClientMessage::Hello(version, capabilities) => {
+                                if version < MIN_CLIENT_PROTOCOL_VERSION {
+                                    info!("Client {} using unsupported protocol version {}", client.key(), version);
+                                    client.tx.send(UpdateMessage::FatalError(incompatible_client_message(version))).unwrap();
+                                    continue;
+                                }
 
-                                    // Send error message
-                                    client.tx.send(UpdateMessage::FatalError(e.to_string())).unwrap();
-                                }   
+                                trace!("Client {} using protocol version {} with capabilities {:?}", client.key(), version, capabilities);
+                                client.protocol_version.store(version, Ordering::Relaxed);
+
+                                let compact_updates = capabilities.iter().any(|c| c == CAPABILITY_COMPACT_UPDATES);
+                                client.compact_updates.store(compact_updates, Ordering::Relaxed);
+
+                                let enabled = if compact_updates { vec![CAPABILITY_COMPACT_UPDATES.to_owned()] } else { vec![] };
+                                client.tx.send(UpdateMessage::Hello(PROTOCOL_VERSION, enabled)).unwrap();
+                            },
+                            ClientMessage::JoinRoom(id, username, password) => {
+                                spawn_join_room(username, password, None, client.key().to_owned(), id);
+                            },
+                            ClientMessage::JoinRoomAsOwner(id, username, password, owner_token) => {
+                                spawn_join_room(username, password, Some(owner_token), client.key().to_owned(), id);
+                            },
+                            ClientMessage::ResumeSession(id, token, since) => {
+                                if let Err(e) = resume_session(&token, since, client.key().to_owned(), &id) {
+                                    info!("Error resuming session: {:?}", e);
+                                    client.tx.send(UpdateMessage::FatalError(e)).unwrap();
+                                }
+                            },
+                            ClientMessage::WatchRecording(id) => {
+                                start_replay(client.key().to_owned(), id);
+                            },
+                            ClientMessage::ReplayControl(control) => {
+                                control_replay(client.key().to_owned(), control);
                             },
                             _ => {
                                 client.tx1.send(msg.to_owned()).unwrap();
@@ -146,6 +183,37 @@ pub async fn ws_rx() {
     }
 }
 
+/// Join a room on a blocking thread, since checking the password is slow enough to hold up other clients' messages
+fn spawn_join_room(username: String, password: Option<String>, owner_token: Option<String>, peer_id: u128, room_id: String) {
+    tokio::task::spawn_blocking(move || {
+        if let Err(e) = join_room(&username, &password.unwrap_or_default(), owner_token.as_deref(), peer_id, &room_id) {
+            error!("Error joining room: {:?}", e);
+
+            // Send error message
+            ClientsManager::send_to_client(&UpdateMessage::FatalError(e), peer_id);
+        }
+    });
+}
+
+/// Error shown to clients too old to talk to this server
+fn incompatible_client_message(version: u32) -> String {
+    format!("This version of RoboScape Online (protocol {version}) is no longer supported by the server (protocol {PROTOCOL_VERSION}). Please reload the page to update.")
+}
+
+/// Error to send instead of handling a message from a client that has not sent a supported protocol version, `msg` is None if it could not be decoded
+///
+/// Clients from before the handshake existed never send a Hello, and may send messages in shapes that no longer decode.
+fn protocol_error(msg: Option<&ClientMessage>, protocol_version: u32) -> Option<String> {
+    if protocol_version >= MIN_CLIENT_PROTOCOL_VERSION {
+        return None;
+    }
+
+    match msg {
+        None | Some(ClientMessage::JoinRoom(..) | ClientMessage::JoinRoomAsOwner(..) | ClientMessage::ResumeSession(..) | ClientMessage::WatchRecording(..)) => Some(incompatible_client_message(protocol_version)),
+        _ => None,
+    }
+}
+
 pub async fn ws_tx() {
     loop {        
         // Get client updates
@@ -175,6 +243,15 @@ pub async fn ws_tx() {
                             }
                         }
                     },
+                    UpdateMessage::CompactUpdate(t, updates) => {
+                        // Merge into a directly preceding compact update, so changes only in the older one are not lost
+                        if let Some(UpdateMessage::CompactUpdate(pending_t, pending)) = to_send.last_mut() {
+                            *pending_t = t;
+                            merge_compact_updates(pending, updates);
+                        } else {
+                            to_send.push(UpdateMessage::CompactUpdate(t, updates));
+                        }
+                    },
                     _ => {
                         to_send.push(msg);
                     }
@@ -190,6 +267,7 @@ pub async fn ws_tx() {
                 let r = rmp_serde::to_vec(&msg);
 
                 if let Ok(buf) = r {
+                    metrics::WS_BYTES_OUT.fetch_add(buf.len() as u64, Ordering::Relaxed);
                     sink.feed(Message::Binary(buf.into())).now_or_never();
                 } else if let Err(e) = r {
                     info!("Error serializing message: {:?}", e);
@@ -222,3 +300,13 @@ pub async fn ws_accept() {
     }
 }
 
+#[test]
+fn test_protocol_error() {
+    // Join message from a client before the owner token was added
+    let old_join = serde_json::from_str::<ClientMessage>(r#"{"j":["room","user",null]}"#).unwrap();
+    assert_eq!(protocol_error(Some(&old_join), 0), Some(incompatible_client_message(0)));
+    assert_eq!(protocol_error(None, 0), Some(incompatible_client_message(0)));
+    assert_eq!(protocol_error(Some(&ClientMessage::Hello(PROTOCOL_VERSION, vec![])), 0), None);
+    assert_eq!(protocol_error(Some(&old_join), PROTOCOL_VERSION), None);
+    assert_eq!(protocol_error(None, PROTOCOL_VERSION), None);
+}
@@ -3,7 +3,6 @@ use std::time::Duration;
 use netsblox_vm::runtime::{GetType, ProcessKind, SimpleValue, Unwindable};
 use netsblox_vm::{ast, runtime::{CustomTypes, Value, EntityKind, FromAstError, Settings}, gc::{Mutation, Collect, RefLock, Gc, Arena, Rootable}, project::Project, bytecode::{Locations, ByteCode}, std_system::StdSystem};
 
-pub const DEFAULT_BASE_URL: &str = "https://cloud.netsblox.org";
 pub const STEPS_PER_IO_ITER: usize = 64;
 pub const YIELDS_BEFORE_IDLE_SLEEP: usize = 100;
 pub const IDLE_SLEEP_TIME: Duration = Duration::from_millis(5);
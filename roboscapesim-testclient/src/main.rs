@@ -3,7 +3,7 @@ use std::time::{SystemTime, Duration, Instant};
 
 use async_tungstenite::tungstenite::Message;
 use clap::Parser;
-use roboscapesim_common::{ClientMessage, UpdateMessage};
+use roboscapesim_common::{ClientMessage, UpdateMessage, PROTOCOL_VERSION};
 use serde::{Deserialize, Serialize};
 use log::{info, trace, warn};
 use futures::{prelude::*, future::join_all};
@@ -83,6 +83,7 @@ async fn run_test_client(args: &Args, id: usize) {
             password: None,
             username: username.clone(),
             edit_mode: false,
+            tags: vec![],
         })
         .send()
         .await
@@ -93,8 +94,9 @@ async fn run_test_client(args: &Args, id: usize) {
     let (mut ws_stream, _) = async_tungstenite::tokio::connect_async(room.server).await.expect("Failed to connect to simulation server");
     info!("Client {}: Connected to simulation server", id);
 
-    // Send join message
-    ws_stream.send(Message::Binary(rmp_serde::to_vec(&ClientMessage::JoinRoom(room.room_id.clone(), username.clone(), None)).unwrap().into())).await.expect("Failed to send join message");
+    // Send handshake and join message
+    ws_stream.send(Message::Binary(rmp_serde::to_vec(&ClientMessage::Hello(PROTOCOL_VERSION, vec![])).unwrap().into())).await.expect("Failed to send hello message");
+    ws_stream.send(Message::Binary(rmp_serde::to_vec(&ClientMessage::join_room(room.room_id.clone(), username.clone(), None, room.owner_token.clone())).unwrap().into())).await.expect("Failed to send join message");
 
     let ws_stream = std::sync::Arc::new(tokio::sync::Mutex::new(ws_stream));
 